@@ -0,0 +1,108 @@
+/*
+ * C interface of the YPBank parsers, the `ypbank` shared library.
+ *
+ * Ownership:
+ * - readers and writers are created by the library and released with
+ *   ypbank_reader_free() and ypbank_writer_finish() or ypbank_writer_free();
+ * - strings returned by the library are owned by it and valid until the
+ *   call documented for each of them; the caller never frees them;
+ * - strings and records passed to the library are only borrowed for the
+ *   duration of the call.
+ *
+ * Functions returning a pointer return NULL on failure, functions returning
+ * int return -1; the message is then available from ypbank_last_error().
+ * Extra fields of the records, e.g. CHANNEL, are not exposed.
+ */
+
+#ifndef YPBANK_H
+#define YPBANK_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Transaction types, ypbank_record.tx_type */
+#define YPBANK_DEPOSIT 0
+#define YPBANK_TRANSFER 1
+#define YPBANK_WITHDRAWAL 2
+
+/* Transaction statuses, ypbank_record.status */
+#define YPBANK_SUCCESS 0
+#define YPBANK_FAILURE 1
+#define YPBANK_PENDING 2
+
+/* A transaction record */
+typedef struct ypbank_record {
+    uint64_t tx_id;
+    /* One of YPBANK_DEPOSIT, YPBANK_TRANSFER or YPBANK_WITHDRAWAL */
+    uint8_t tx_type;
+    uint64_t from_user_id;
+    uint64_t to_user_id;
+    /* Amount in the smallest currency unit */
+    uint64_t amount;
+    /* ISO 4217 code, e.g. "EUR", or "" if unknown */
+    char currency[4];
+    /* Milliseconds since the Unix epoch */
+    uint64_t timestamp;
+    /* One of YPBANK_SUCCESS, YPBANK_FAILURE or YPBANK_PENDING */
+    uint8_t status;
+    /* NUL-terminated UTF-8 text */
+    const char *description;
+} ypbank_record;
+
+typedef struct ypbank_reader ypbank_reader;
+typedef struct ypbank_writer ypbank_writer;
+
+/*
+ * Message of the last failed call on the calling thread, or NULL if none
+ * failed. Valid until the next failed call on the same thread.
+ */
+const char *ypbank_last_error(void);
+
+/*
+ * Reads all records of the file at `path`. `format` is a format name such
+ * as "bin", "csv" or "txt"; if NULL, it is detected from the extension.
+ */
+ypbank_reader *ypbank_reader_open(const char *path, const char *format);
+
+/* Number of records in the file */
+size_t ypbank_reader_len(const ypbank_reader *reader);
+
+/*
+ * Stores the next record in `record` and returns 1, or returns 0 after the
+ * last record. `record->description` is owned by the reader and valid until
+ * the next call on it. Returns -1 for a record that `ypbank_record` cannot
+ * hold: a DESCRIPTION with NUL or a TIMESTAMP finer than milliseconds.
+ */
+int ypbank_reader_next(ypbank_reader *reader, ypbank_record *record);
+
+/* Releases the reader; NULL is ignored */
+void ypbank_reader_free(ypbank_reader *reader);
+
+/*
+ * Creates a writer of records to the file at `path`; the file is not touched
+ * before ypbank_writer_finish(). `format` is as in ypbank_reader_open().
+ */
+ypbank_writer *ypbank_writer_create(const char *path, const char *format);
+
+/* Adds a copy of `record` to be written; returns 0 */
+int ypbank_writer_push(ypbank_writer *writer, const ypbank_record *record);
+
+/*
+ * Writes the added records to the file and releases the writer, also when
+ * writing fails; returns 0. The records are written to a temporary file that
+ * then replaces the file, so a failure leaves an existing file intact.
+ */
+int ypbank_writer_finish(ypbank_writer *writer);
+
+/* Releases the writer without writing the records; NULL is ignored */
+void ypbank_writer_free(ypbank_writer *writer);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* YPBANK_H */
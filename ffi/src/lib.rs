@@ -0,0 +1,371 @@
+//! C interface of the YPBank parsers, declared in `include/ypbank.h`.
+//!
+//! A reader loads all records of a file on open and hands them out one by
+//! one; a writer collects records and writes them on finish, since some
+//! formats, e.g. SQLite, are written as a whole. Errors are kept per thread
+//! for `ypbank_last_error`.
+
+use rust_parser::commands::write_file;
+use rust_parser::currency::Currency;
+use rust_parser::format::{Format, FormatOptions};
+use rust_parser::storage::{
+    ExtraFields, YPBankRecord, YPBankRecordStatus, YPBankRecordType, YPBankStorage,
+};
+use rust_parser::timestamp::{TimeUnit, Timestamp, TimestampError};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char, c_int};
+use std::fmt::Display;
+use std::fs::File;
+use std::io::BufReader;
+use std::ptr;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// A record as laid out in `ypbank_record`
+#[repr(C)]
+pub struct Record {
+    tx_id: u64,
+    tx_type: u8,
+    from_user_id: u64,
+    to_user_id: u64,
+    amount: u64,
+    currency: [c_char; 4],
+    timestamp: u64,
+    status: u8,
+    description: *const c_char,
+}
+
+/// Records of a file being handed out
+pub struct Reader {
+    records: std::vec::IntoIter<YPBankRecord>,
+    len: usize,
+    description: CString,
+}
+
+/// Records collected for a file
+pub struct Writer {
+    format: Format,
+    path: String,
+    storage: YPBankStorage,
+}
+
+fn set_error(message: impl Display) {
+    let message = message.to_string().replace('\0', " ");
+    LAST_ERROR.with(|e| *e.borrow_mut() = CString::new(message).ok());
+}
+
+/// Converts a C string argument, recording an error if it is NULL or not UTF-8
+///
+/// # Safety
+///
+/// `s` must be NULL or a NUL-terminated string.
+unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> Option<&'a str> {
+    if s.is_null() {
+        set_error(format_args!("{} is NULL", name));
+        return None;
+    }
+    // SAFETY: guaranteed by the caller
+    let s = unsafe { CStr::from_ptr(s) }.to_str();
+    s.inspect_err(|_| set_error(format_args!("{} is not UTF-8", name)))
+        .ok()
+}
+
+/// Resolves the format by name or, if `format` is NULL, by extension
+///
+/// # Safety
+///
+/// `format` must be NULL or a NUL-terminated string.
+unsafe fn format_arg(path: &str, format: *const c_char) -> Option<Format> {
+    let format = if format.is_null() {
+        Format::from_path(path)
+    } else {
+        // SAFETY: guaranteed by the caller
+        let name = unsafe { str_arg(format, "format") }?;
+        name.parse().ok()
+    };
+    format.or_else(|| {
+        set_error(format_args!("{}: unknown format", path));
+        None
+    })
+}
+
+/// Fails for a timestamp that whole milliseconds cannot hold
+fn to_c(record: &YPBankRecord, description: *const c_char) -> Result<Record, TimestampError> {
+    let mut currency = [0; 4];
+    if let Some(c) = record.currency {
+        for (to, from) in currency.iter_mut().zip(c.code().bytes()) {
+            *to = from as c_char;
+        }
+    }
+    Ok(Record {
+        tx_id: record.tx_id,
+        tx_type: match record.tx_type {
+            YPBankRecordType::DEPOSIT => 0,
+            YPBankRecordType::TRANSFER => 1,
+            YPBankRecordType::WITHDRAWAL => 2,
+        },
+        from_user_id: record.from_user_id,
+        to_user_id: record.to_user_id,
+        amount: record.amount,
+        currency,
+        timestamp: record.timestamp.to_unix_exact(TimeUnit::Millis)?,
+        status: match record.status {
+            YPBankRecordStatus::SUCCESS => 0,
+            YPBankRecordStatus::FAILURE => 1,
+            YPBankRecordStatus::PENDING => 2,
+        },
+        description,
+    })
+}
+
+/// # Safety
+///
+/// `record.description` must be NULL or a NUL-terminated string.
+unsafe fn from_c(record: &Record) -> Result<YPBankRecord, &'static str> {
+    let tx_type = match record.tx_type {
+        0 => YPBankRecordType::DEPOSIT,
+        1 => YPBankRecordType::TRANSFER,
+        2 => YPBankRecordType::WITHDRAWAL,
+        _ => return Err("invalid TX_TYPE"),
+    };
+    let status = match record.status {
+        0 => YPBankRecordStatus::SUCCESS,
+        1 => YPBankRecordStatus::FAILURE,
+        2 => YPBankRecordStatus::PENDING,
+        _ => return Err("invalid STATUS"),
+    };
+    let currency = match record.currency[0] {
+        0 => None,
+        _ => {
+            // SAFETY: the array is NUL-terminated unless all 4 bytes are set
+            let code = (record.currency[3] == 0)
+                .then(|| unsafe { CStr::from_ptr(record.currency.as_ptr()) });
+            let code = code.and_then(|c| c.to_str().ok());
+            Some(
+                code.and_then(Currency::from_code)
+                    .ok_or("invalid CURRENCY")?,
+            )
+        }
+    };
+    let description = if record.description.is_null() {
+        String::new()
+    } else {
+        // SAFETY: guaranteed by the caller
+        let description = unsafe { CStr::from_ptr(record.description) };
+        description
+            .to_str()
+            .map_err(|_| "DESCRIPTION is not UTF-8")?
+            .to_string()
+    };
+    Ok(YPBankRecord {
+        tx_id: record.tx_id,
+        tx_type,
+        from_user_id: record.from_user_id,
+        to_user_id: record.to_user_id,
+        amount: record.amount,
+        currency,
+        timestamp: Timestamp::from_millis(record.timestamp),
+        status,
+        description,
+        extra: ExtraFields::new(),
+    })
+}
+
+/// Message of the last failed call on this thread, or NULL
+#[unsafe(no_mangle)]
+pub extern "C" fn ypbank_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+/// Reads all records of a file
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string and `format` NULL or one.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ypbank_reader_open(
+    path: *const c_char,
+    format: *const c_char,
+) -> *mut Reader {
+    // SAFETY: guaranteed by the caller
+    let Some(path) = (unsafe { str_arg(path, "path") }) else {
+        return ptr::null_mut();
+    };
+    // SAFETY: guaranteed by the caller
+    let Some(format) = (unsafe { format_arg(path, format) }) else {
+        return ptr::null_mut();
+    };
+    let storage = File::open(path)
+        .map_err(|e| e.to_string())
+        .and_then(|file| {
+            let mut reader = BufReader::new(file);
+            let storage = format.read(&mut reader, &FormatOptions::default());
+            storage.map_err(|e| e.to_string())
+        });
+    match storage {
+        Ok(storage) => {
+            let records = storage.into_records();
+            Box::into_raw(Box::new(Reader {
+                len: records.len(),
+                records: records.into_iter(),
+                description: CString::default(),
+            }))
+        }
+        Err(e) => {
+            set_error(format_args!("{}: {}", path, e));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Number of records in the file
+///
+/// # Safety
+///
+/// `reader` must come from `ypbank_reader_open` and not be freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ypbank_reader_len(reader: *const Reader) -> usize {
+    // SAFETY: guaranteed by the caller
+    unsafe { &*reader }.len
+}
+
+/// Stores the next record, returning 1, or returns 0 after the last one
+///
+/// Returns -1 for a record that `ypbank_record` cannot hold.
+///
+/// # Safety
+///
+/// `reader` must come from `ypbank_reader_open` and not be freed; `record`
+/// must point to writable memory for a record.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ypbank_reader_next(reader: *mut Reader, record: *mut Record) -> c_int {
+    // SAFETY: guaranteed by the caller
+    let reader = unsafe { &mut *reader };
+    let Some(next) = reader.records.next() else {
+        return 0;
+    };
+    let Ok(description) = CString::new(next.description.as_str()) else {
+        set_error(format_args!(
+            "record {}: DESCRIPTION contains NUL",
+            next.tx_id
+        ));
+        return -1;
+    };
+    reader.description = description;
+    match to_c(&next, reader.description.as_ptr()) {
+        // SAFETY: guaranteed by the caller
+        Ok(next) => unsafe { record.write(next) },
+        Err(e) => {
+            set_error(format_args!("record {}: {}", next.tx_id, e));
+            return -1;
+        }
+    }
+    1
+}
+
+/// Releases a reader
+///
+/// # Safety
+///
+/// `reader` must be NULL or come from `ypbank_reader_open` and not be freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ypbank_reader_free(reader: *mut Reader) {
+    if !reader.is_null() {
+        // SAFETY: guaranteed by the caller
+        drop(unsafe { Box::from_raw(reader) });
+    }
+}
+
+/// Creates a writer of records to a file
+///
+/// The file is not touched until `ypbank_writer_finish`.
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string and `format` NULL or one.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ypbank_writer_create(
+    path: *const c_char,
+    format: *const c_char,
+) -> *mut Writer {
+    // SAFETY: guaranteed by the caller
+    let Some(path) = (unsafe { str_arg(path, "path") }) else {
+        return ptr::null_mut();
+    };
+    // SAFETY: guaranteed by the caller
+    let Some(format) = (unsafe { format_arg(path, format) }) else {
+        return ptr::null_mut();
+    };
+    Box::into_raw(Box::new(Writer {
+        format,
+        path: path.to_string(),
+        storage: YPBankStorage::new(),
+    }))
+}
+
+/// Adds a copy of a record to be written
+///
+/// # Safety
+///
+/// `writer` must come from `ypbank_writer_create` and not be released;
+/// `record` must point to a record whose description is NULL or a
+/// NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ypbank_writer_push(writer: *mut Writer, record: *const Record) -> c_int {
+    // SAFETY: guaranteed by the caller
+    let (writer, record) = unsafe { (&mut *writer, &*record) };
+    // SAFETY: guaranteed by the caller
+    match unsafe { from_c(record) } {
+        Ok(record) => {
+            writer.storage.push(record);
+            0
+        }
+        Err(e) => {
+            set_error(format_args!("record {}: {}", record.tx_id, e));
+            -1
+        }
+    }
+}
+
+/// Writes the added records and releases the writer
+///
+/// The records are written to a temporary file that then replaces the file,
+/// as by the `ypbank` tool, so a failure leaves an existing file intact.
+///
+/// # Safety
+///
+/// `writer` must come from `ypbank_writer_create` and not be released.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ypbank_writer_finish(writer: *mut Writer) -> c_int {
+    // SAFETY: guaranteed by the caller
+    let writer = unsafe { Box::from_raw(writer) };
+    let Writer {
+        format,
+        path,
+        storage,
+    } = *writer;
+    match write_file(&path, format, storage, &FormatOptions::default(), true) {
+        Ok(()) => 0,
+        // The error names the file
+        Err(e) => {
+            set_error(e);
+            -1
+        }
+    }
+}
+
+/// Releases a writer without writing its records
+///
+/// # Safety
+///
+/// `writer` must be NULL or come from `ypbank_writer_create` and not be
+/// released.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ypbank_writer_free(writer: *mut Writer) {
+    if !writer.is_null() {
+        // SAFETY: guaranteed by the caller
+        drop(unsafe { Box::from_raw(writer) });
+    }
+}
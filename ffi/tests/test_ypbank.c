@@ -0,0 +1,142 @@
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "ypbank.h"
+
+static int failures = 0;
+
+#define CHECK(cond)                                                   \
+    do {                                                              \
+        if (!(cond)) {                                                \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__,    \
+                    __LINE__, #cond);                                 \
+            failures++;                                               \
+        }                                                             \
+    } while (0)
+
+static const ypbank_record RECORDS[] = {
+    {1, YPBANK_DEPOSIT, 0, 42, 1000, "", 1633036800000, YPBANK_SUCCESS,
+     "Initial deposit"},
+    {2, YPBANK_TRANSFER, 42, 7, 250, "EUR", 1633036860000, YPBANK_PENDING,
+     "Rent"},
+};
+#define RECORD_COUNT (sizeof(RECORDS) / sizeof(RECORDS[0]))
+
+static void write_records(const char *path, const char *format) {
+    ypbank_writer *writer = ypbank_writer_create(path, format);
+    CHECK(writer != NULL);
+    if (writer == NULL) {
+        return;
+    }
+    for (size_t i = 0; i < RECORD_COUNT; i++) {
+        CHECK(ypbank_writer_push(writer, &RECORDS[i]) == 0);
+    }
+    CHECK(ypbank_writer_finish(writer) == 0);
+}
+
+static void test_round_trip(const char *dir, const char *name,
+                            const char *format) {
+    char path[4096];
+    snprintf(path, sizeof(path), "%s/%s", dir, name);
+    write_records(path, format);
+
+    ypbank_reader *reader = ypbank_reader_open(path, format);
+    CHECK(reader != NULL);
+    if (reader == NULL) {
+        fprintf(stderr, "%s\n", ypbank_last_error());
+        return;
+    }
+    CHECK(ypbank_reader_len(reader) == RECORD_COUNT);
+    ypbank_record record;
+    for (size_t i = 0; i < RECORD_COUNT; i++) {
+        CHECK(ypbank_reader_next(reader, &record) == 1);
+        CHECK(record.tx_id == RECORDS[i].tx_id);
+        CHECK(record.tx_type == RECORDS[i].tx_type);
+        CHECK(record.from_user_id == RECORDS[i].from_user_id);
+        CHECK(record.to_user_id == RECORDS[i].to_user_id);
+        CHECK(record.amount == RECORDS[i].amount);
+        CHECK(strcmp(record.currency, RECORDS[i].currency) == 0);
+        CHECK(record.timestamp == RECORDS[i].timestamp);
+        CHECK(record.status == RECORDS[i].status);
+        CHECK(strcmp(record.description, RECORDS[i].description) == 0);
+    }
+    CHECK(ypbank_reader_next(reader, &record) == 0);
+    ypbank_reader_free(reader);
+}
+
+static void test_errors(const char *dir) {
+    CHECK(ypbank_reader_open("/nonexistent/records.bin", NULL) == NULL);
+    CHECK(strstr(ypbank_last_error(), "/nonexistent/records.bin") != NULL);
+
+    CHECK(ypbank_reader_open("records.unknown", NULL) == NULL);
+    CHECK(strstr(ypbank_last_error(), "unknown format") != NULL);
+
+    char path[4096];
+    snprintf(path, sizeof(path), "%s/invalid.bin", dir);
+    FILE *file = fopen(path, "wb");
+    CHECK(file != NULL);
+    fputs("not a record", file);
+    fclose(file);
+    CHECK(ypbank_reader_open(path, NULL) == NULL);
+    CHECK(strstr(ypbank_last_error(), "invalid.bin") != NULL);
+
+    ypbank_writer *writer = ypbank_writer_create(path, "bin");
+    ypbank_record record = RECORDS[0];
+    record.tx_type = 7;
+    CHECK(ypbank_writer_push(writer, &record) == -1);
+    CHECK(strstr(ypbank_last_error(), "invalid TX_TYPE") != NULL);
+    record = RECORDS[1];
+    memcpy(record.currency, "XYZ", 4);
+    CHECK(ypbank_writer_push(writer, &record) == -1);
+    CHECK(strstr(ypbank_last_error(), "invalid CURRENCY") != NULL);
+    ypbank_writer_free(writer);
+
+    /* A failed write leaves the existing file intact */
+    writer = ypbank_writer_create(path, "bin");
+    record = RECORDS[0];
+    record.amount = UINT64_MAX;
+    CHECK(ypbank_writer_push(writer, &record) == 0);
+    CHECK(ypbank_writer_finish(writer) == -1);
+    CHECK(strstr(ypbank_last_error(), "AMOUNT out of range") != NULL);
+    char content[32] = {0};
+    file = fopen(path, "rb");
+    CHECK(file != NULL);
+    CHECK(fread(content, 1, sizeof(content) - 1, file) == 12);
+    fclose(file);
+    CHECK(strcmp(content, "not a record") == 0);
+
+    /* Microseconds are not rounded to the milliseconds of the record */
+    snprintf(path, sizeof(path), "%s/micros.json", dir);
+    file = fopen(path, "w");
+    CHECK(file != NULL);
+    fputs("[{\"TX_ID\": 3, \"TX_TYPE\": \"DEPOSIT\", \"FROM_USER_ID\": 0, "
+          "\"TO_USER_ID\": 5, \"AMOUNT\": 100, "
+          "\"TIMESTAMP\": \"2021-10-01T00:00:00.000001Z\", "
+          "\"STATUS\": \"SUCCESS\", \"DESCRIPTION\": \"\"}]",
+          file);
+    fclose(file);
+    ypbank_reader *reader = ypbank_reader_open(path, NULL);
+    CHECK(reader != NULL);
+    if (reader != NULL) {
+        CHECK(ypbank_reader_next(reader, &record) == -1);
+        CHECK(strstr(ypbank_last_error(), "sub-millisecond") != NULL);
+        ypbank_reader_free(reader);
+    }
+}
+
+int main(int argc, char **argv) {
+    const char *dir = argc > 1 ? argv[1] : ".";
+    test_round_trip(dir, "records.bin", NULL);
+    test_round_trip(dir, "records.csv", NULL);
+    test_round_trip(dir, "records.out", "txt");
+    test_round_trip(dir, "records.db", NULL);
+    test_errors(dir);
+    if (failures > 0) {
+        fprintf(stderr, "%d checks failed\n", failures);
+        return EXIT_FAILURE;
+    }
+    printf("all checks passed\n");
+    return EXIT_SUCCESS;
+}
@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_parser::format::format_csv::CsvParser;
+use rust_parser::parser::Parser;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = CsvParser::from_read(&mut &data[..]);
+});
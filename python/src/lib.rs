@@ -0,0 +1,288 @@
+//! Python bindings of the YPBank parsers, the `ypbank` extension module.
+//!
+//! Files are read and written by the parsers of the `rust-parser` crate;
+//! records are exposed as `ypbank.Record` objects and can be turned into a
+//! dict of columns for `pandas.DataFrame`.
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList};
+use rust_parser::currency::Currency;
+use rust_parser::error::ParserError as Error;
+use rust_parser::format::{FORMAT_NAMES, Format, FormatOptions};
+use rust_parser::storage::{ExtraFields, STANDARD_FIELDS, YPBankRecord, YPBankStorage};
+use rust_parser::timestamp::{TimeUnit, Timestamp};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+create_exception!(
+    ypbank,
+    ParserError,
+    PyValueError,
+    "Records that cannot be read or written."
+);
+
+/// A transaction record
+///
+/// Amounts are in minor units and timestamps in milliseconds since the Unix
+/// epoch; `tx_type` and `status` are the names used in the files, e.g.
+/// `"DEPOSIT"` and `"SUCCESS"`.
+#[pyclass(module = "ypbank", get_all, set_all, eq, from_py_object)]
+#[derive(Clone, PartialEq)]
+struct Record {
+    tx_id: u64,
+    tx_type: String,
+    from_user_id: u64,
+    to_user_id: u64,
+    amount: u64,
+    currency: Option<String>,
+    timestamp: u64,
+    status: String,
+    description: String,
+    extra: ExtraFields,
+}
+
+#[pymethods]
+impl Record {
+    #[new]
+    #[pyo3(signature = (
+        tx_id, tx_type, from_user_id, to_user_id, amount, timestamp, status,
+        description = String::new(), currency = None, extra = ExtraFields::new()
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        tx_id: u64,
+        tx_type: String,
+        from_user_id: u64,
+        to_user_id: u64,
+        amount: u64,
+        timestamp: u64,
+        status: String,
+        description: String,
+        currency: Option<String>,
+        extra: ExtraFields,
+    ) -> PyResult<Self> {
+        let record = Self {
+            tx_id,
+            tx_type,
+            from_user_id,
+            to_user_id,
+            amount,
+            currency,
+            timestamp,
+            status,
+            description,
+            extra,
+        };
+        record.to_rust()?;
+        Ok(record)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Record(tx_id={}, tx_type={:?}, amount={}, status={:?})",
+            self.tx_id, self.tx_type, self.amount, self.status
+        )
+    }
+}
+
+impl Record {
+    /// Fails for a timestamp that whole milliseconds cannot hold
+    fn from_rust(record: &YPBankRecord) -> PyResult<Self> {
+        Ok(Self {
+            tx_id: record.tx_id,
+            tx_type: record.tx_type.to_string(),
+            from_user_id: record.from_user_id,
+            to_user_id: record.to_user_id,
+            amount: record.amount,
+            currency: record.currency.map(|c| c.code().to_string()),
+            timestamp: record
+                .timestamp
+                .to_unix_exact(TimeUnit::Millis)
+                .map_err(|e| ParserError::new_err(e.to_string()))?,
+            status: record.status.to_string(),
+            description: record.description.clone(),
+            extra: record.extra.clone(),
+        })
+    }
+
+    fn to_rust(&self) -> PyResult<YPBankRecord> {
+        let invalid = |name: &str| ParserError::new_err(format!("invalid {}", name));
+        let currency = match &self.currency {
+            Some(code) => Some(Currency::from_code(code).ok_or_else(|| invalid("CURRENCY"))?),
+            None => None,
+        };
+        Ok(YPBankRecord {
+            tx_id: self.tx_id,
+            tx_type: self.tx_type.parse().map_err(|_| invalid("TX_TYPE"))?,
+            from_user_id: self.from_user_id,
+            to_user_id: self.to_user_id,
+            amount: self.amount,
+            currency,
+            timestamp: Timestamp::from_millis(self.timestamp),
+            status: self.status.parse().map_err(|_| invalid("STATUS"))?,
+            description: self.description.clone(),
+            extra: self.extra.clone(),
+        })
+    }
+}
+
+/// Names of the supported formats
+#[pyfunction]
+fn formats() -> Vec<&'static str> {
+    FORMAT_NAMES.to_vec()
+}
+
+/// Reads the records of a file held in `data`
+#[pyfunction]
+fn read(data: &[u8], format: &str) -> PyResult<Vec<Record>> {
+    let storage = parse_format(format)?.read(&mut &data[..], &FormatOptions::default());
+    records(storage.map_err(parser_error)?)
+}
+
+/// Reads the records of a file; the format is detected from its extension
+/// if omitted
+#[pyfunction]
+#[pyo3(signature = (path, format = None))]
+fn read_file(path: PathBuf, format: Option<&str>) -> PyResult<Vec<Record>> {
+    let format = path_format(&path, format)?;
+    let mut reader = BufReader::new(File::open(&path)?);
+    let storage = format.read(&mut reader, &FormatOptions::default());
+    records(storage.map_err(parser_error)?)
+}
+
+/// Writes records, any iterable of `Record`, in `format`
+#[pyfunction]
+fn write<'py>(
+    py: Python<'py>,
+    records: &Bound<'py, PyAny>,
+    format: &str,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let format = parse_format(format)?;
+    let storage = storage(records)?;
+    let mut data = Vec::new();
+    format
+        .write(storage, &mut data, &FormatOptions::default())
+        .map_err(parser_error)?;
+    Ok(PyBytes::new(py, &data))
+}
+
+/// Writes records to a file; the format is detected from its extension if
+/// omitted
+#[pyfunction]
+#[pyo3(signature = (records, path, format = None))]
+fn write_file(records: &Bound<'_, PyAny>, path: PathBuf, format: Option<&str>) -> PyResult<()> {
+    let format = path_format(&path, format)?;
+    let storage = storage(records)?;
+    let mut writer = BufWriter::new(File::create(&path)?);
+    format
+        .write(storage, &mut writer, &FormatOptions::default())
+        .map_err(parser_error)?;
+    Ok(writer.flush()?)
+}
+
+/// Converts a file held in `data` between formats
+#[pyfunction]
+fn convert<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    from_format: &str,
+    to_format: &str,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let options = FormatOptions::default();
+    let storage = parse_format(from_format)?
+        .read(&mut &data[..], &options)
+        .map_err(parser_error)?;
+    let mut out = Vec::new();
+    parse_format(to_format)?
+        .write(storage, &mut out, &options)
+        .map_err(parser_error)?;
+    Ok(PyBytes::new(py, &out))
+}
+
+/// Columns of the records for `pandas.DataFrame`
+///
+/// Keys are the CSV column names, `TX_ID` to `DESCRIPTION`, followed by the
+/// extra fields of any record in name order. `CURRENCY` and extra fields are
+/// `None` where a record lacks them.
+#[pyfunction]
+fn to_columns<'py>(py: Python<'py>, records: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyDict>> {
+    let records = records
+        .try_iter()?
+        .map(|r| Ok(r?.extract::<Record>()?))
+        .collect::<PyResult<Vec<_>>>()?;
+    let columns = PyDict::new(py);
+    macro_rules! column {
+        ($name:expr, $field:ident) => {
+            columns.set_item(
+                $name,
+                PyList::new(py, records.iter().map(|r| r.$field.clone()))?,
+            )?;
+        };
+    }
+    column!(STANDARD_FIELDS[0], tx_id);
+    column!(STANDARD_FIELDS[1], tx_type);
+    column!(STANDARD_FIELDS[2], from_user_id);
+    column!(STANDARD_FIELDS[3], to_user_id);
+    column!(STANDARD_FIELDS[4], amount);
+    column!(STANDARD_FIELDS[5], currency);
+    column!(STANDARD_FIELDS[6], timestamp);
+    column!(STANDARD_FIELDS[7], status);
+    column!(STANDARD_FIELDS[8], description);
+    let names: BTreeSet<&String> = records.iter().flat_map(|r| r.extra.keys()).collect();
+    for name in names {
+        let values = records.iter().map(|r| r.extra.get(name).cloned());
+        columns.set_item(name, PyList::new(py, values)?)?;
+    }
+    Ok(columns)
+}
+
+fn records(storage: YPBankStorage) -> PyResult<Vec<Record>> {
+    storage.records().iter().map(Record::from_rust).collect()
+}
+
+fn storage(records: &Bound<'_, PyAny>) -> PyResult<YPBankStorage> {
+    let mut storage = YPBankStorage::new();
+    for record in records.try_iter()? {
+        storage.push(record?.extract::<Record>()?.to_rust()?);
+    }
+    Ok(storage)
+}
+
+fn parse_format(name: &str) -> PyResult<Format> {
+    name.parse()
+        .map_err(|_| PyValueError::new_err(format!("unknown format: {}", name)))
+}
+
+fn path_format(path: &Path, format: Option<&str>) -> PyResult<Format> {
+    match format {
+        Some(name) => parse_format(name),
+        None => Format::from_path(path).ok_or_else(|| {
+            PyValueError::new_err(format!("cannot detect the format of '{}'", path.display()))
+        }),
+    }
+}
+
+fn parser_error(error: Error) -> PyErr {
+    match error {
+        Error::IO { error, .. } => PyOSError::new_err(error.to_string()),
+        error => ParserError::new_err(error.to_string()),
+    }
+}
+
+#[pymodule]
+fn ypbank(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("ParserError", m.py().get_type::<ParserError>())?;
+    m.add_class::<Record>()?;
+    m.add_function(wrap_pyfunction!(formats, m)?)?;
+    m.add_function(wrap_pyfunction!(read, m)?)?;
+    m.add_function(wrap_pyfunction!(read_file, m)?)?;
+    m.add_function(wrap_pyfunction!(write, m)?)?;
+    m.add_function(wrap_pyfunction!(write_file, m)?)?;
+    m.add_function(wrap_pyfunction!(convert, m)?)?;
+    m.add_function(wrap_pyfunction!(to_columns, m)?)?;
+    Ok(())
+}
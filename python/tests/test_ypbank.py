@@ -0,0 +1,88 @@
+import os
+import tempfile
+import unittest
+
+import ypbank
+
+
+def sample():
+    return [
+        ypbank.Record(
+            tx_id=1,
+            tx_type="DEPOSIT",
+            from_user_id=0,
+            to_user_id=42,
+            amount=1000,
+            timestamp=1633036800000,
+            status="SUCCESS",
+            description="Initial deposit",
+        ),
+        ypbank.Record(
+            tx_id=2,
+            tx_type="TRANSFER",
+            from_user_id=42,
+            to_user_id=7,
+            amount=250,
+            timestamp=1633036860000,
+            status="PENDING",
+            currency="EUR",
+            extra={"CHANNEL": "mobile"},
+        ),
+    ]
+
+
+class RoundTripTest(unittest.TestCase):
+    def test_all_formats(self):
+        records = sample()
+        for name in ypbank.formats():
+            with self.subTest(format=name):
+                data = ypbank.write(records, name)
+                self.assertEqual(ypbank.read(data, name), records)
+
+    def test_convert(self):
+        csv = ypbank.write(sample(), "csv")
+        data = ypbank.convert(csv, "csv", "bin")
+        self.assertEqual(ypbank.read(data, "bin"), sample())
+
+    def test_files(self):
+        with tempfile.TemporaryDirectory() as dir:
+            path = os.path.join(dir, "records.txt")
+            ypbank.write_file(iter(sample()), path)
+            self.assertEqual(ypbank.read_file(path), sample())
+            self.assertEqual(ypbank.read_file(path, "txt"), sample())
+
+
+class ColumnsTest(unittest.TestCase):
+    def test_columns(self):
+        columns = ypbank.to_columns(sample())
+        self.assertEqual(list(columns)[:2], ["TX_ID", "TX_TYPE"])
+        self.assertEqual(columns["AMOUNT"], [1000, 250])
+        self.assertEqual(columns["CURRENCY"], [None, "EUR"])
+        self.assertEqual(columns["CHANNEL"], [None, "mobile"])
+
+
+class ErrorTest(unittest.TestCase):
+    def test_invalid_record(self):
+        with self.assertRaises(ypbank.ParserError):
+            ypbank.Record(1, "GIFT", 0, 1, 10, 0, "SUCCESS")
+
+    def test_invalid_data(self):
+        with self.assertRaises(ypbank.ParserError):
+            ypbank.read(b"not a record", "bin")
+        with self.assertRaises(ValueError):
+            ypbank.read(b"", "xml")
+
+    def test_sub_millisecond_timestamp(self):
+        data = ypbank.write(sample(), "json").replace(
+            b"1633036800000", b'"2021-10-01T00:00:00.000001Z"'
+        )
+        with self.assertRaisesRegex(ypbank.ParserError, "sub-millisecond"):
+            ypbank.read(data, "json")
+
+    def test_missing_file(self):
+        with self.assertRaises(OSError):
+            ypbank.read_file("/nonexistent/records.csv")
+
+
+if __name__ == "__main__":
+    unittest.main()
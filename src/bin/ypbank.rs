@@ -0,0 +1,8 @@
+use rust_parser::commands;
+use std::env;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().collect();
+    commands::main(&args)
+}
@@ -0,0 +1,14 @@
+//! Alias of `ypbank reconcile`.
+
+use rust_parser::cli::CliConfig;
+use rust_parser::commands::{self, reconcile::ReconcileConfig};
+use std::env;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().collect();
+    let command = ReconcileConfig::command()
+        .named("ypbank_reconcile")
+        .version(env!("CARGO_PKG_VERSION"));
+    commands::run::<ReconcileConfig>(&command, &args)
+}
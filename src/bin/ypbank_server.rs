@@ -0,0 +1,14 @@
+//! Alias of `ypbank serve`.
+
+use rust_parser::cli::CliConfig;
+use rust_parser::commands::{self, serve::ServeConfig};
+use std::env;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().collect();
+    let command = ServeConfig::command()
+        .named("ypbank_server")
+        .version(env!("CARGO_PKG_VERSION"));
+    commands::run::<ServeConfig>(&command, &args)
+}
@@ -0,0 +1,14 @@
+//! Alias of `ypbank view`.
+
+use rust_parser::cli::CliConfig;
+use rust_parser::commands::{self, view::ViewConfig};
+use std::env;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().collect();
+    let command = ViewConfig::command()
+        .named("ypbank_view")
+        .version(env!("CARGO_PKG_VERSION"));
+    commands::run::<ViewConfig>(&command, &args)
+}
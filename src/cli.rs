@@ -1,51 +1,789 @@
 //! Command-line argument parsing utilities
 
 use crate::error::CliError;
+use crate::format::format_csv::{CsvOptions, parse_columns};
+use std::collections::HashSet;
+use std::str::FromStr;
 
 /// A trait for working with CLI arguments
 pub trait CliConfig: Default {
-    /// Set an argument and its value
+    /// Describes the command and the options it accepts
+    fn command() -> Command;
+
+    /// Set an argument and its value; boolean flags receive `true` or `false`
     fn set_arg(&mut self, flag: &str, value: String) -> Result<(), CliError>;
 
-    /// Validate configuration arguments
-    fn validate_args(&self) -> Result<(), CliError>;
+    /// Validate configuration arguments after all of them are set
+    fn validate_args(&self) -> Result<(), CliError> {
+        Ok(())
+    }
+}
+
+/// Type of the value taken by an option
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ValueKind {
+    /// A boolean switch without a value; `--flag=false` turns it off
+    Flag,
+    /// Free-form text
+    Text,
+    /// A non-negative integer
+    Integer,
+    /// A single character; `tab` and `\t` denote a tab
+    Char,
+}
+
+/// Declarative description of a single option
+#[derive(Debug, Clone)]
+pub struct Opt {
+    /// Long name, used as `--name`
+    pub name: String,
+    /// Optional short name, used as `-c`
+    pub short: Option<char>,
+    /// Type of the value
+    pub kind: ValueKind,
+    /// Placeholder for the value in the help text
+    pub value_name: &'static str,
+    /// One-line description
+    pub help: &'static str,
+    /// Value applied before the command line is parsed
+    pub default: Option<&'static str>,
+    /// The option must be given
+    pub required: bool,
+    /// The option may also be given as a positional argument
+    pub positional: bool,
+    /// The option may be given several times, including as positionals
+    pub multiple: bool,
+    /// Allowed values; empty if any value is accepted
+    pub choices: &'static [&'static str],
+}
+
+impl Opt {
+    /// Creates an option taking a text value
+    pub fn value(name: impl Into<String>, value_name: &'static str, help: &'static str) -> Self {
+        Self {
+            name: name.into(),
+            short: None,
+            kind: ValueKind::Text,
+            value_name,
+            help,
+            default: None,
+            required: false,
+            positional: false,
+            multiple: false,
+            choices: &[],
+        }
+    }
+
+    /// Creates a boolean switch
+    pub fn flag(name: impl Into<String>, help: &'static str) -> Self {
+        Self {
+            kind: ValueKind::Flag,
+            ..Self::value(name, "", help)
+        }
+    }
+
+    /// Sets the short name
+    pub fn short(mut self, short: char) -> Self {
+        self.short = Some(short);
+        self
+    }
+
+    /// Sets the value type
+    pub fn kind(mut self, kind: ValueKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Sets the default value
+    pub fn default(mut self, default: &'static str) -> Self {
+        self.default = Some(default);
+        self
+    }
+
+    /// Marks the option as required
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    /// Allows giving the option as a positional argument
+    pub fn positional(mut self) -> Self {
+        self.positional = true;
+        self
+    }
+
+    /// Allows giving the option several times
+    pub fn multiple(mut self) -> Self {
+        self.multiple = true;
+        self
+    }
+
+    /// Restricts the option to the given values
+    pub fn choices(mut self, choices: &'static [&'static str]) -> Self {
+        self.choices = choices;
+        self
+    }
+
+    fn display_name(&self) -> String {
+        if self.positional {
+            format!("<{}>", self.value_name)
+        } else {
+            format!("--{}", self.name)
+        }
+    }
+
+    fn check_value(&self, value: &str) -> bool {
+        let valid_kind = match self.kind {
+            ValueKind::Flag => value.parse::<bool>().is_ok(),
+            ValueKind::Text => true,
+            ValueKind::Integer => value.parse::<u64>().is_ok(),
+            ValueKind::Char => parse_char(value).is_some(),
+        };
+        valid_kind && (self.choices.is_empty() || self.choices.contains(&value))
+    }
+}
+
+/// Declarative description of a command
+#[derive(Debug, Clone)]
+pub struct Command {
+    /// Program name, including the parent program for subcommands
+    pub name: String,
+    /// Program version, printed by `--version`
+    pub version: &'static str,
+    /// One-line description of the program
+    pub about: &'static str,
+    /// Accepted options
+    pub options: Vec<Opt>,
+}
+
+impl Command {
+    /// Creates a command without options
+    pub fn new(name: impl Into<String>, about: &'static str) -> Self {
+        Self {
+            name: name.into(),
+            version: "",
+            about,
+            options: Vec::new(),
+        }
+    }
+
+    /// Sets the version, enabling `--version`
+    pub fn version(mut self, version: &'static str) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Renames the command, e.g. when it runs as a subcommand or an alias
+    pub fn named(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Adds an option
+    pub fn opt(mut self, opt: Opt) -> Self {
+        self.options.push(opt);
+        self
+    }
+
+    /// Adds several options
+    pub fn opts(mut self, opts: impl IntoIterator<Item = Opt>) -> Self {
+        self.options.extend(opts);
+        self
+    }
+
+    /// One-line usage summary
+    pub fn usage(&self) -> String {
+        let mut usage = format!("Usage: {} [OPTIONS]", self.name);
+        for opt in self.options.iter().filter(|o| o.required && !o.positional) {
+            usage.push_str(&format!(" --{} <{}>", opt.name, opt.value_name));
+        }
+        for opt in self.options.iter().filter(|o| o.positional) {
+            let dots = if opt.multiple { "..." } else { "" };
+            if opt.required {
+                usage.push_str(&format!(" <{}>{}", opt.value_name, dots));
+            } else {
+                usage.push_str(&format!(" [{}]{}", opt.value_name, dots));
+            }
+        }
+        usage
+    }
+
+    /// Full help text listing every option
+    pub fn help(&self) -> String {
+        let mut rows: Vec<(String, String)> = Vec::new();
+        for opt in &self.options {
+            let short = opt.short.map(|c| format!("-{}, ", c)).unwrap_or_default();
+            let value = match opt.kind {
+                ValueKind::Flag => String::new(),
+                _ => format!(" <{}>", opt.value_name),
+            };
+            let mut help = opt.help.to_string();
+            if !opt.choices.is_empty() {
+                help.push_str(&format!(" [possible values: {}]", opt.choices.join(", ")));
+            }
+            if let Some(default) = opt.default {
+                help.push_str(&format!(" [default: {}]", default));
+            }
+            if opt.required {
+                help.push_str(" [required]");
+            }
+            rows.push((format!("{:>4}--{}{}", short, opt.name, value), help));
+        }
+        rows.push(("-h, --help".to_string(), "Print help".to_string()));
+        if !self.version.is_empty() {
+            rows.push(("-V, --version".to_string(), "Print version".to_string()));
+        }
+
+        let width = rows.iter().map(|(left, _)| left.len()).max().unwrap_or(0);
+        let mut text = format!("{}\n\n{}\n\nOptions:\n", self.about, self.usage());
+        for (left, help) in rows {
+            text.push_str(&format!("  {:<width$}  {}\n", left, help, width = width));
+        }
+        text
+    }
+
+    fn find(&self, name: &str) -> Option<&Opt> {
+        self.options.iter().find(|o| o.name == name)
+    }
+
+    fn find_short(&self, short: char) -> Option<&Opt> {
+        self.options.iter().find(|o| o.short == Some(short))
+    }
+}
+
+/// Result of parsing a command line
+#[derive(Debug)]
+pub enum Parsed<T> {
+    /// Run with the parsed configuration
+    Run(T),
+    /// Help was requested; the text should be printed
+    Help(String),
+    /// The version was requested; the text should be printed
+    Version(String),
 }
 
 /// Parses command-line arguments into configuration object
-pub fn parse_args<T: CliConfig>(args: &[String]) -> Result<T, CliError> {
-    let mut config = T::default();
-    let mut i = 1;
+///
+/// Supports `--name value`, `--name=value`, boolean `--flag`, short options
+/// (bundled as `-ab` for flags), positional arguments and `--` to end
+/// options. All problems found are reported together.
+pub fn parse_args<T: CliConfig>(args: &[String]) -> Result<Parsed<T>, CliError> {
+    parse_command(&T::command(), args)
+}
+
+/// Parses command-line arguments against an explicitly given command
+///
+/// Used when the command differs from [`CliConfig::command`], for example
+/// when it was renamed to run as a subcommand.
+pub fn parse_command<T: CliConfig>(
+    command: &Command,
+    args: &[String],
+) -> Result<Parsed<T>, CliError> {
+    let mut parser = ArgParser {
+        command,
+        config: T::default(),
+        errors: Vec::new(),
+        seen: HashSet::new(),
+    };
+    for opt in &command.options {
+        if let Some(default) = opt.default {
+            parser.set(opt, default.to_string(), false);
+        }
+    }
+
+    let mut positionals = Vec::new();
+    let mut options_ended = false;
+    let mut rest = args.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        if options_ended || arg == "-" || !arg.starts_with('-') {
+            positionals.push(arg.clone());
+        } else if arg == "--" {
+            options_ended = true;
+        } else if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            if let Some(info) = command.builtin(name) {
+                return Ok(info);
+            }
+            let Some(opt) = command.find(name) else {
+                parser
+                    .errors
+                    .push(CliError::UnknownArgument { name: arg.clone() });
+                continue;
+            };
+            let value = match (opt.kind, inline) {
+                (_, Some(value)) => value,
+                (ValueKind::Flag, None) => "true".to_string(),
+                (_, None) => match rest.next() {
+                    Some(value) => value.clone(),
+                    None => {
+                        parser.seen.insert(opt.name.clone());
+                        parser
+                            .errors
+                            .push(CliError::MissingValue { name: arg.clone() });
+                        continue;
+                    }
+                },
+            };
+            parser.set(opt, value, true);
+        } else {
+            let shorts = &arg[1..];
+            for (pos, c) in shorts.char_indices() {
+                let Some(opt) = command.find_short(c) else {
+                    if let Some(info) = command.builtin_short(c) {
+                        return Ok(info);
+                    }
+                    parser.errors.push(CliError::UnknownArgument {
+                        name: format!("-{}", c),
+                    });
+                    continue;
+                };
+                if opt.kind == ValueKind::Flag {
+                    parser.set(opt, "true".to_string(), true);
+                    continue;
+                }
+                let attached = &shorts[pos + c.len_utf8()..];
+                let value = if !attached.is_empty() {
+                    Some(attached.to_string())
+                } else {
+                    rest.next().cloned()
+                };
+                match value {
+                    Some(value) => parser.set(opt, value, true),
+                    None => {
+                        parser.seen.insert(opt.name.clone());
+                        parser.errors.push(CliError::MissingValue {
+                            name: format!("-{}", c),
+                        })
+                    }
+                }
+                break;
+            }
+        }
+    }
+
+    let mut slots = command.options.iter().filter(|o| o.positional);
+    let mut slot = slots.next();
+    for value in positionals {
+        match slot {
+            Some(opt) => {
+                parser.set(opt, value, true);
+                if !opt.multiple {
+                    slot = slots.next();
+                }
+            }
+            None => parser
+                .errors
+                .push(CliError::UnknownArgument { name: value }),
+        }
+    }
+
+    parser.finish()
+}
+
+impl Command {
+    fn builtin<T>(&self, name: &str) -> Option<Parsed<T>> {
+        match name {
+            "help" => Some(Parsed::Help(self.help())),
+            "version" if !self.version.is_empty() => {
+                Some(Parsed::Version(format!("{} {}", self.name, self.version)))
+            }
+            _ => None,
+        }
+    }
+
+    fn builtin_short<T>(&self, short: char) -> Option<Parsed<T>> {
+        match short {
+            'h' => self.builtin("help"),
+            'V' => self.builtin("version"),
+            _ => None,
+        }
+    }
+}
+
+/// Declarative description of a program made of subcommands
+#[derive(Debug, Clone)]
+pub struct MultiCommand {
+    /// Program name
+    pub name: String,
+    /// Program version, printed by `--version`
+    pub version: &'static str,
+    /// One-line description of the program
+    pub about: &'static str,
+    /// Options shared by every subcommand; they may precede the subcommand
+    pub global: Vec<Opt>,
+    /// Available subcommands
+    pub commands: Vec<Command>,
+}
+
+/// Result of selecting a subcommand
+#[derive(Debug)]
+pub enum Dispatch {
+    /// Run the subcommand with the given arguments
+    Run {
+        /// Name of the subcommand as given on the command line
+        name: String,
+        /// The subcommand, renamed to include the program name
+        command: Command,
+        /// Arguments for [`parse_command`], starting with the program name
+        args: Vec<String>,
+    },
+    /// Help was requested; the text should be printed
+    Help(String),
+    /// The version was requested; the text should be printed
+    Version(String),
+}
+
+impl MultiCommand {
+    /// Creates a program without subcommands
+    pub fn new(name: impl Into<String>, about: &'static str) -> Self {
+        Self {
+            name: name.into(),
+            version: "",
+            about,
+            global: Vec::new(),
+            commands: Vec::new(),
+        }
+    }
+
+    /// Sets the version, enabling `--version`
+    pub fn version(mut self, version: &'static str) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Adds options shared by every subcommand
+    pub fn global(mut self, opts: impl IntoIterator<Item = Opt>) -> Self {
+        self.global.extend(opts);
+        self
+    }
+
+    /// Adds a subcommand
+    pub fn subcommand(mut self, command: Command) -> Self {
+        self.commands.push(command);
+        self
+    }
+
+    /// One-line usage summary
+    pub fn usage(&self) -> String {
+        format!("Usage: {} [OPTIONS] <COMMAND> [ARGS]", self.name)
+    }
+
+    /// Full help text listing every subcommand and the global options
+    pub fn help(&self) -> String {
+        let width = self
+            .commands
+            .iter()
+            .map(|c| c.name.len())
+            .max()
+            .unwrap_or(0);
+        let mut text = format!("{}\n\n{}\n\nCommands:\n", self.about, self.usage());
+        for command in &self.commands {
+            text.push_str(&format!(
+                "  {:<width$}  {}\n",
+                command.name,
+                command.about,
+                width = width
+            ));
+        }
+        let global = Command::new(self.name.clone(), self.about)
+            .version(self.version)
+            .opts(self.global.clone())
+            .help();
+        if let Some((_, options)) = global.split_once("Options:\n") {
+            text.push_str("\nGlobal options:\n");
+            text.push_str(options);
+        }
+        text.push_str(&format!(
+            "\nRun '{} <COMMAND> --help' for the options of a command.\n",
+            self.name
+        ));
+        text
+    }
+
+    /// Selects the subcommand named on the command line
+    ///
+    /// Global options given before the subcommand are passed on to it,
+    /// `help <COMMAND>` prints the help of a subcommand.
+    pub fn dispatch(&self, args: &[String]) -> Result<Dispatch, CliError> {
+        let mut globals = Vec::new();
+        let mut rest = args.iter().skip(1);
+        while let Some(arg) = rest.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(Dispatch::Help(self.help())),
+                "-V" | "--version" if !self.version.is_empty() => {
+                    return Ok(Dispatch::Version(format!("{} {}", self.name, self.version)));
+                }
+                "help" => {
+                    let Some(name) = rest.next() else {
+                        return Ok(Dispatch::Help(self.help()));
+                    };
+                    return Ok(Dispatch::Help(self.find(name)?.help()));
+                }
+                _ if arg.starts_with('-') && arg != "-" => {
+                    globals.push(arg.clone());
+                    if self.global_takes_value(arg)
+                        && let Some(value) = rest.next()
+                    {
+                        globals.push(value.clone());
+                    }
+                }
+                name => {
+                    let command = self.find(name)?;
+                    let args = std::iter::once(command.name.clone())
+                        .chain(globals)
+                        .chain(rest.cloned())
+                        .collect();
+                    return Ok(Dispatch::Run {
+                        name: name.to_string(),
+                        command,
+                        args,
+                    });
+                }
+            }
+        }
+        Err(CliError::MissingArgument {
+            name: "<COMMAND>".to_string(),
+        })
+    }
+
+    fn find(&self, name: &str) -> Result<Command, CliError> {
+        let command = self
+            .commands
+            .iter()
+            .find(|c| c.name == name)
+            .ok_or_else(|| CliError::UnknownArgument {
+                name: name.to_string(),
+            })?;
+        Ok(command
+            .clone()
+            .named(format!("{} {}", self.name, name))
+            .version(self.version))
+    }
+
+    fn global_takes_value(&self, arg: &str) -> bool {
+        let opt = match arg.strip_prefix("--") {
+            Some(long) if !long.contains('=') => self.global.iter().find(|o| o.name == long),
+            Some(_) => None,
+            None => {
+                let shorts = &arg[1..];
+                let last = shorts.chars().last();
+                match shorts.chars().count() {
+                    1 => self.global.iter().find(|o| o.short == last),
+                    _ => None,
+                }
+            }
+        };
+        opt.is_some_and(|o| o.kind != ValueKind::Flag)
+    }
+}
 
-    while i < args.len() {
-        let arg = &args[i];
-        let flag = arg
-            .strip_prefix("--")
-            .ok_or_else(|| CliError::UnknownArgument { name: arg.clone() })?;
-        let value = args
-            .get(i + 1)
-            .ok_or_else(|| CliError::MissingValue { name: arg.clone() })?;
-        config.set_arg(flag, value.to_string())?;
-        i += 2;
+struct ArgParser<'a, T> {
+    command: &'a Command,
+    config: T,
+    errors: Vec<CliError>,
+    seen: HashSet<String>,
+}
+
+impl<T: CliConfig> ArgParser<'_, T> {
+    fn set(&mut self, opt: &Opt, value: String, given: bool) {
+        if given && !opt.check_value(&value) {
+            self.errors.push(CliError::InvalidValue {
+                name: opt.display_name(),
+                value,
+            });
+            return;
+        }
+        if given {
+            self.seen.insert(opt.name.clone());
+        }
+        if let Err(e) = self.config.set_arg(&opt.name, value) {
+            self.errors.push(e);
+        }
+    }
+
+    fn finish(mut self) -> Result<Parsed<T>, CliError> {
+        for opt in &self.command.options {
+            if opt.required && !self.seen.contains(&opt.name) {
+                self.errors.push(CliError::MissingArgument {
+                    name: opt.display_name(),
+                });
+            }
+        }
+        if self.errors.is_empty()
+            && let Err(e) = self.config.validate_args()
+        {
+            self.errors.push(e);
+        }
+        match self.errors.len() {
+            0 => Ok(Parsed::Run(self.config)),
+            1 => Err(self.errors.remove(0)),
+            _ => Err(CliError::Multiple(self.errors)),
+        }
+    }
+}
+
+/// Describes the options handled by [`set_csv_option`] for reading CSV
+pub fn csv_read_options(prefix: &str) -> Vec<Opt> {
+    vec![
+        Opt::value(
+            format!("{prefix}columns"),
+            "LIST",
+            "CSV columns of a file without a header, e.g. TX_ID,AMOUNT,STATUS",
+        ),
+        Opt::flag(
+            format!("{prefix}lenient"),
+            "Ignore malformed columns and surplus fields",
+        ),
+        Opt::value(format!("{prefix}delimiter"), "CHAR", "CSV field delimiter")
+            .kind(ValueKind::Char)
+            .default(","),
+        Opt::value(format!("{prefix}quote"), "CHAR", "CSV quote character")
+            .kind(ValueKind::Char)
+            .default("\""),
+        Opt::value(format!("{prefix}header"), "MODE", "CSV header line")
+            .choices(&["required", "absent", "auto"])
+            .default("required"),
+        Opt::flag(format!("{prefix}trim"), "Trim whitespace around CSV fields").default("true"),
+    ]
+}
+
+/// Describes the options handled by [`set_csv_option`] for writing CSV
+pub fn csv_write_options(prefix: &str) -> Vec<Opt> {
+    vec![
+        Opt::value(
+            format!("{prefix}columns"),
+            "LIST",
+            "CSV columns in order, e.g. TX_ID,AMOUNT,STATUS",
+        ),
+        Opt::flag(
+            format!("{prefix}extra-columns"),
+            "Append CURRENCY and extra field columns",
+        )
+        .default("true"),
+        Opt::value(format!("{prefix}delimiter"), "CHAR", "CSV field delimiter")
+            .kind(ValueKind::Char)
+            .default(","),
+        Opt::value(format!("{prefix}quote"), "CHAR", "CSV quote character")
+            .kind(ValueKind::Char)
+            .default("\""),
+        Opt::value(format!("{prefix}header"), "MODE", "CSV header line")
+            .choices(&["required", "absent"])
+            .default("required"),
+        Opt::flag(format!("{prefix}bom"), "Write a UTF-8 byte order mark"),
+        Opt::value(
+            format!("{prefix}line-terminator"),
+            "EOL",
+            "CSV line terminator",
+        )
+        .choices(&["lf", "crlf"])
+        .default("lf"),
+    ]
+}
+
+/// Applies a CSV option given as `--<prefix><name> <value>`
+///
+/// Recognised names: `columns`, `extra-columns`, `lenient`, `delimiter`,
+/// `quote`, `header`, `bom`, `line-terminator` and `trim`; a command offers
+/// those of [`csv_read_options`] or [`csv_write_options`]. An explicit
+/// `columns` list also stops extra columns from being appended.
+pub fn set_csv_option(
+    options: &mut CsvOptions,
+    flag: &str,
+    name: &str,
+    value: String,
+) -> Result<(), CliError> {
+    let invalid = || CliError::InvalidValue {
+        name: format!("--{}", flag),
+        value: value.clone(),
+    };
+    let dialect = &mut options.dialect;
+    match name {
+        "columns" => {
+            options.columns = parse_columns(&value).map_err(|_| invalid())?;
+            options.extra_columns = false;
+        }
+        "extra-columns" => options.extra_columns = value.parse().map_err(|_| invalid())?,
+        "lenient" => options.lenient = value.parse().map_err(|_| invalid())?,
+        "delimiter" => dialect.delimiter = parse_char(&value).ok_or_else(invalid)?,
+        "quote" => dialect.quote = parse_char(&value).ok_or_else(invalid)?,
+        "header" => dialect.header = FromStr::from_str(&value).map_err(|_| invalid())?,
+        "bom" => dialect.bom = value.parse().map_err(|_| invalid())?,
+        "line-terminator" => {
+            dialect.line_terminator = FromStr::from_str(&value).map_err(|_| invalid())?
+        }
+        "trim" => dialect.trim = value.parse().map_err(|_| invalid())?,
+        _ => {
+            return Err(CliError::UnknownArgument {
+                name: format!("--{}", flag),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Parses a single character, accepting `tab` and `\t` for a tab
+fn parse_char(s: &str) -> Option<char> {
+    if s == "tab" || s == "\\t" {
+        return Some('\t');
+    }
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
     }
-    config.validate_args()?;
-    Ok(config)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::format::format_csv::CsvHeader;
 
     #[derive(Default)]
     struct TestConfig {
         input: String,
         output: String,
+        verbose: bool,
+        quiet: bool,
+        count: u64,
+        mode: String,
     }
 
     impl CliConfig for TestConfig {
+        fn command() -> Command {
+            Command::new("prog", "Test program")
+                .version("1.2.3")
+                .opt(
+                    Opt::value("input", "FILE", "Input file")
+                        .short('i')
+                        .required()
+                        .positional(),
+                )
+                .opt(
+                    Opt::value("output", "FILE", "Output file")
+                        .short('o')
+                        .required(),
+                )
+                .opt(Opt::flag("verbose", "Verbose output").short('v'))
+                .opt(Opt::flag("quiet", "Quiet output").short('q'))
+                .opt(Opt::value("count", "N", "Count").kind(ValueKind::Integer))
+                .opt(
+                    Opt::value("mode", "MODE", "Mode")
+                        .choices(&["fast", "slow"])
+                        .default("fast"),
+                )
+        }
+
         fn set_arg(&mut self, flag: &str, value: String) -> Result<(), CliError> {
             match flag {
                 "input" => self.input = value.clone(),
                 "output" => self.output = value.clone(),
+                "verbose" => self.verbose = value == "true",
+                "quiet" => self.quiet = value == "true",
+                "count" => self.count = value.parse().unwrap(),
+                "mode" => self.mode = value.clone(),
                 _ => {
                     return Err(CliError::UnknownArgument {
                         name: format!("--{}", flag),
@@ -54,41 +792,171 @@ mod tests {
             }
             Ok(())
         }
-
-        fn validate_args(&self) -> Result<(), CliError> {
-            for (flag, val) in [("--input", &self.input), ("--output", &self.output)] {
-                if val.is_empty() {
-                    return Err(CliError::MissingArgument {
-                        name: flag.to_string(),
-                    });
-                }
-            }
-            Ok(())
-        }
     }
 
     fn args(parts: &[&str]) -> Vec<String> {
         parts.iter().map(|s| s.to_string()).collect()
     }
 
+    fn run(parts: &[&str]) -> TestConfig {
+        match parse_args::<TestConfig>(&args(parts)) {
+            Ok(Parsed::Run(config)) => config,
+            other => panic!("expected configuration, got: {:?}", other.err()),
+        }
+    }
+
+    fn errors(parts: &[&str]) -> Vec<CliError> {
+        match parse_args::<TestConfig>(&args(parts)) {
+            Err(CliError::Multiple(errors)) => errors,
+            Err(e) => vec![e],
+            Ok(_) => panic!("expected errors"),
+        }
+    }
+
     #[test]
     fn parses_known_flags() {
-        let result =
-            parse_args::<TestConfig>(&args(&["prog", "--input", "a.csv", "--output", "b.csv"]));
-        let config = result.unwrap();
+        let config = run(&["prog", "--input", "a.csv", "--output", "b.csv"]);
+        assert_eq!(config.input, "a.csv");
+        assert_eq!(config.output, "b.csv");
+        assert_eq!(config.mode, "fast");
+        assert!(!config.verbose);
+    }
+
+    #[test]
+    fn parses_equals_short_and_positional_forms() {
+        let config = run(&[
+            "prog",
+            "-vq",
+            "--count=3",
+            "-ob.csv",
+            "--mode=slow",
+            "a.csv",
+        ]);
         assert_eq!(config.input, "a.csv");
         assert_eq!(config.output, "b.csv");
+        assert_eq!(config.count, 3);
+        assert_eq!(config.mode, "slow");
+        assert!(config.verbose && config.quiet);
+
+        let config = run(&["prog", "-o", "b.csv", "--verbose=false", "--", "-a.csv"]);
+        assert_eq!(config.input, "-a.csv");
+        assert!(!config.verbose);
     }
 
     #[test]
     fn returns_error_for_unknown_argument() {
-        let result = parse_args::<TestConfig>(&args(&["prog", "--input", "a.csv", "--foo", "bar"]));
-        assert!(matches!(result, Err(CliError::UnknownArgument { .. })));
+        let errors = errors(&["prog", "--input", "a.csv", "--foo", "bar"]);
+        assert!(
+            errors
+                .iter()
+                .any(|e| matches!(e, CliError::UnknownArgument { name } if name == "--foo"))
+        );
     }
 
     #[test]
     fn returns_error_when_value_is_missing() {
-        let result = parse_args::<TestConfig>(&args(&["prog", "--input"]));
-        assert!(matches!(result, Err(CliError::MissingValue { .. })));
+        let errors = errors(&["prog", "--output", "b.csv", "--input"]);
+        assert!(matches!(errors[..], [CliError::MissingValue { .. }]));
+    }
+
+    #[test]
+    fn reports_all_errors_at_once() {
+        let errors = errors(&["prog", "--count", "x", "--mode", "medium", "a.csv", "b.csv"]);
+        assert_eq!(errors.len(), 4, "{:?}", errors);
+        assert!(matches!(errors[0], CliError::InvalidValue { .. }));
+        assert!(matches!(errors[1], CliError::InvalidValue { .. }));
+        assert!(matches!(errors[2], CliError::UnknownArgument { .. }));
+        assert!(matches!(errors[3], CliError::MissingArgument { .. }));
+    }
+
+    #[test]
+    fn returns_help_and_version() {
+        let help = parse_args::<TestConfig>(&args(&["prog", "--output", "x", "-h"]));
+        let Ok(Parsed::Help(text)) = help else {
+            panic!("expected help");
+        };
+        assert!(text.contains("Usage: prog [OPTIONS] --output <FILE> <FILE>"));
+        assert!(text.contains("-o, --output <FILE>"));
+        assert!(text.contains("[possible values: fast, slow] [default: fast]"));
+
+        let version = parse_args::<TestConfig>(&args(&["prog", "--version"]));
+        assert!(matches!(version, Ok(Parsed::Version(v)) if v == "prog 1.2.3"));
+    }
+
+    #[test]
+    fn dispatches_subcommands() {
+        let program = MultiCommand::new("tool", "Test tool")
+            .version("1.2.3")
+            .global(vec![
+                Opt::flag("verbose", "Verbose output").short('v'),
+                Opt::value("mode", "MODE", "Mode"),
+            ])
+            .subcommand(TestConfig::command().named("run"));
+
+        let dispatch = program.dispatch(&args(&["tool", "-v", "--mode", "slow", "run", "-o", "b"]));
+        let Ok(Dispatch::Run {
+            name,
+            command,
+            args: sub_args,
+        }) = dispatch
+        else {
+            panic!("expected a subcommand");
+        };
+        assert_eq!(name, "run");
+        assert_eq!(command.name, "tool run");
+        assert_eq!(
+            sub_args,
+            args(&["tool run", "-v", "--mode", "slow", "-o", "b"])
+        );
+
+        let help = program.dispatch(&args(&["tool", "help", "run"]));
+        assert!(matches!(help, Ok(Dispatch::Help(text)) if text.contains("Usage: tool run")));
+        let help = program.dispatch(&args(&["tool", "--help"]));
+        assert!(matches!(help, Ok(Dispatch::Help(text)) if text.contains("Global options:")));
+
+        let unknown = program.dispatch(&args(&["tool", "-v", "jump"]));
+        assert!(matches!(unknown, Err(CliError::UnknownArgument { name }) if name == "jump"));
+        let missing = program.dispatch(&args(&["tool", "-v"]));
+        assert!(matches!(missing, Err(CliError::MissingArgument { .. })));
+    }
+
+    #[test]
+    fn sets_csv_options() {
+        let mut options = CsvOptions::default();
+        set_csv_option(&mut options, "csv-delimiter", "delimiter", ";".into()).unwrap();
+        set_csv_option(&mut options, "csv-quote", "quote", "'".into()).unwrap();
+        set_csv_option(&mut options, "csv-header", "header", "auto".into()).unwrap();
+        set_csv_option(&mut options, "csv-bom", "bom", "true".into()).unwrap();
+        assert_eq!(options.dialect.delimiter, ';');
+        assert_eq!(options.dialect.quote, '\'');
+        assert_eq!(options.dialect.header, CsvHeader::Auto);
+        assert!(options.dialect.bom);
+
+        set_csv_option(&mut options, "csv-delimiter", "delimiter", "tab".into()).unwrap();
+        assert_eq!(options.dialect.delimiter, '\t');
+    }
+
+    #[test]
+    fn splits_csv_read_and_write_options() {
+        let names = |opts: Vec<Opt>| -> Vec<String> { opts.into_iter().map(|o| o.name).collect() };
+        let read = names(csv_read_options("in-"));
+        let write = names(csv_write_options("out-"));
+        assert!(read.contains(&"in-lenient".to_string()));
+        assert!(!read.contains(&"in-bom".to_string()));
+        assert!(!read.contains(&"in-line-terminator".to_string()));
+        assert!(write.contains(&"out-bom".to_string()));
+        assert!(!write.contains(&"out-lenient".to_string()));
+        assert!(!write.contains(&"out-trim".to_string()));
+    }
+
+    #[test]
+    fn rejects_invalid_csv_option_values() {
+        let mut options = CsvOptions::default();
+        let result = set_csv_option(&mut options, "csv-delimiter", "delimiter", ";;".into());
+        assert!(matches!(result, Err(CliError::InvalidValue { .. })));
+        let result = set_csv_option(&mut options, "csv-header", "header", "maybe".into());
+        assert!(matches!(result, Err(CliError::InvalidValue { .. })));
+        let result = set_csv_option(&mut options, "csv-foo", "foo", "x".into());
+        assert!(matches!(result, Err(CliError::UnknownArgument { .. })));
     }
 }
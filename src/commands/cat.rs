@@ -0,0 +1,40 @@
+//! Printing of record files.
+
+use super::{GlobalOptions, InputOptions, OutputOptions, Subcommand};
+use crate::cli::{CliConfig, Command};
+use crate::error::CliError;
+
+/// Options of the `cat` subcommand
+#[derive(Default)]
+pub struct CatConfig {
+    input: InputOptions,
+    output: OutputOptions,
+}
+
+impl CliConfig for CatConfig {
+    fn command() -> Command {
+        Command::new("cat", "Prints the records of files to stdout")
+            .opts(InputOptions::options())
+            .opts(OutputOptions::options())
+            .opts(GlobalOptions::options())
+    }
+
+    fn set_arg(&mut self, flag: &str, value: String) -> Result<(), CliError> {
+        match flag {
+            _ if flag.starts_with("output-") => self.output.set(flag, value),
+            _ => self.input.set(flag, value),
+        }
+    }
+}
+
+impl Subcommand for CatConfig {
+    fn run(self) -> Result<(), CliError> {
+        let mut out = self.output.stdout_records();
+        let result = self.input.for_each_record(|_, record| {
+            out.write_record(&record)?;
+            Ok(true)
+        });
+        out.finish()?;
+        result
+    }
+}
@@ -0,0 +1,456 @@
+//! Comparison of the records of two files.
+
+use super::convert::TIME_FORMATS;
+use super::{GLOBAL_OPTIONS, GlobalOptions, Subcommand, parse_value, read_file};
+use crate::cli::{CliConfig, Command, Opt, csv_read_options, set_csv_option};
+use crate::error::CliError;
+use crate::format::format_csv::{CsvColumn, CsvOptions};
+use crate::format::{FORMAT_NAMES, Format, FormatOptions};
+use crate::storage::{YPBankRecord, is_extra_field_name};
+use crate::timestamp::{TimestampFormat, parse_duration};
+use std::time::Duration;
+use strum_macros::{Display, EnumString};
+use unicode_normalization::UnicodeNormalization;
+
+/// Options of the `compare` subcommand
+#[derive(Default)]
+pub struct CompareConfig {
+    global: GlobalOptions,
+    file1: String,
+    format1: Option<Format>,
+    file2: String,
+    format2: Option<Format>,
+    csv1: CsvOptions,
+    csv2: CsvOptions,
+    time1: TimestampFormat,
+    time2: TimestampFormat,
+    comparison: Comparison,
+}
+
+impl CompareConfig {
+    /// Options for reading a file with its CSV options and timestamp format
+    fn read_options(&self, csv: &CsvOptions, time: TimestampFormat) -> FormatOptions {
+        let mut options = self.global.read_options(csv);
+        options.csv.timestamp = time;
+        options.txt.timestamp = time;
+        options
+    }
+}
+
+/// Whether the order of records matters
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum MatchOrder {
+    /// Records may appear in any order, but as often in both files
+    #[default]
+    Multiset,
+    /// Records must appear in the same order
+    Sequence,
+}
+
+/// Rules deciding whether two records are considered equal
+#[derive(Debug, Default, Clone)]
+pub struct Comparison {
+    /// Standard fields that are not compared
+    pub ignore: Vec<CsvColumn>,
+    /// Extra fields that are not compared
+    pub ignore_extra: Vec<String>,
+    /// Ignore leading and trailing whitespace of descriptions
+    pub trim: bool,
+    /// Compare descriptions case-insensitively
+    pub ignore_case: bool,
+    /// Compare descriptions in Unicode normalization form C
+    pub nfc: bool,
+    /// Largest accepted difference between timestamps
+    pub time_tolerance: Duration,
+    /// Whether the order of records matters
+    pub order: MatchOrder,
+}
+
+impl Comparison {
+    /// Sets the ignored fields from a comma-separated list of field names
+    ///
+    /// Standard names such as `DESCRIPTION` refer to the standard fields,
+    /// anything else to an extra field.
+    pub fn set_ignored(&mut self, flag: &str, list: &str) -> Result<(), CliError> {
+        for name in list.split(',').map(str::trim) {
+            match name.parse::<CsvColumn>() {
+                Ok(column) => self.ignore.push(column),
+                Err(_) if is_extra_field_name(name) => self.ignore_extra.push(name.to_string()),
+                Err(_) => {
+                    return Err(CliError::InvalidValue {
+                        name: format!("--{}", flag),
+                        value: name.to_string(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `a` and `b` are equal under these rules
+    pub fn matches(&self, a: &YPBankRecord, b: &YPBankRecord) -> bool {
+        let compared = |column| !self.ignore.contains(&column);
+        (!compared(CsvColumn::TxId) || a.tx_id == b.tx_id)
+            && (!compared(CsvColumn::TxType) || a.tx_type == b.tx_type)
+            && (!compared(CsvColumn::FromUserId) || a.from_user_id == b.from_user_id)
+            && (!compared(CsvColumn::ToUserId) || a.to_user_id == b.to_user_id)
+            && (!compared(CsvColumn::Amount) || a.amount == b.amount)
+            && (!compared(CsvColumn::Currency) || a.currency == b.currency)
+            && (!compared(CsvColumn::Timestamp)
+                || a.timestamp.abs_diff(&b.timestamp) <= self.time_tolerance)
+            && (!compared(CsvColumn::Status) || a.status == b.status)
+            && (!compared(CsvColumn::Description)
+                || self.description(&a.description) == self.description(&b.description))
+            && self.extra_matches(a, b)
+    }
+
+    /// Splits the records into those without a counterpart in the other list
+    ///
+    /// Records are paired greedily, so with a time tolerance a record may
+    /// take a counterpart that a later record would have matched better.
+    pub fn unmatched<'a>(
+        &self,
+        left: &'a [YPBankRecord],
+        right: &'a [YPBankRecord],
+    ) -> (Vec<&'a YPBankRecord>, Vec<&'a YPBankRecord>) {
+        if self.order == MatchOrder::Sequence {
+            let pairs = left.iter().zip(right);
+            let mut only_left: Vec<_> = Vec::new();
+            let mut only_right: Vec<_> = Vec::new();
+            for (a, b) in pairs.filter(|(a, b)| !self.matches(a, b)) {
+                only_left.push(a);
+                only_right.push(b);
+            }
+            only_left.extend(left.iter().skip(right.len()));
+            only_right.extend(right.iter().skip(left.len()));
+            return (only_left, only_right);
+        }
+
+        let mut used = vec![false; right.len()];
+        let mut only_left = Vec::new();
+        for a in left {
+            let found = (0..right.len()).find(|&i| !used[i] && self.matches(a, &right[i]));
+            match found {
+                Some(i) => used[i] = true,
+                None => only_left.push(a),
+            }
+        }
+        let only_right = right
+            .iter()
+            .zip(used)
+            .filter(|(_, used)| !used)
+            .map(|(b, _)| b)
+            .collect();
+        (only_left, only_right)
+    }
+
+    fn description(&self, description: &str) -> String {
+        let mut text = if self.trim {
+            description.trim().to_string()
+        } else {
+            description.to_string()
+        };
+        if self.nfc {
+            text = text.nfc().collect();
+        }
+        if self.ignore_case {
+            text = text.to_lowercase();
+        }
+        text
+    }
+
+    fn extra_matches(&self, a: &YPBankRecord, b: &YPBankRecord) -> bool {
+        let compared = |(name, _): &(&String, &String)| !self.ignore_extra.contains(name);
+        a.extra
+            .iter()
+            .filter(compared)
+            .eq(b.extra.iter().filter(compared))
+    }
+}
+
+impl CliConfig for CompareConfig {
+    fn command() -> Command {
+        Command::new(
+            "compare",
+            "Compares the transaction records of two YPBank files",
+        )
+        .opt(
+            Opt::value("file1", "FILE1", "First file")
+                .required()
+                .positional(),
+        )
+        .opt(
+            Opt::value(
+                "format1",
+                "FMT",
+                "Format of the first file; detected from the extension if omitted",
+            )
+            .choices(FORMAT_NAMES),
+        )
+        .opt(
+            Opt::value("file2", "FILE2", "Second file")
+                .required()
+                .positional(),
+        )
+        .opt(
+            Opt::value(
+                "format2",
+                "FMT",
+                "Format of the second file; detected from the extension if omitted",
+            )
+            .choices(FORMAT_NAMES),
+        )
+        .opt(
+            Opt::value(
+                "time-format1",
+                "UNIT",
+                "Timestamp format of the first file if CSV or TXT",
+            )
+            .choices(TIME_FORMATS)
+            .default("ms"),
+        )
+        .opt(
+            Opt::value(
+                "time-format2",
+                "UNIT",
+                "Timestamp format of the second file if CSV or TXT",
+            )
+            .choices(TIME_FORMATS)
+            .default("ms"),
+        )
+        .opt(Opt::value(
+            "ignore",
+            "FIELDS",
+            "Comma-separated fields not to compare, e.g. DESCRIPTION,CHANNEL",
+        ))
+        .opt(Opt::flag(
+            "trim-descriptions",
+            "Ignore leading and trailing whitespace of descriptions",
+        ))
+        .opt(Opt::flag(
+            "ignore-case",
+            "Compare descriptions case-insensitively",
+        ))
+        .opt(Opt::flag(
+            "nfc",
+            "Compare descriptions in Unicode normalization form C",
+        ))
+        .opt(
+            Opt::value(
+                "time-tolerance",
+                "DURATION",
+                "Largest accepted timestamp difference, e.g. 1s or 500ms",
+            )
+            .default("0"),
+        )
+        .opt(
+            Opt::value("order", "ORDER", "Whether the order of records matters")
+                .choices(&["multiset", "sequence"])
+                .default("multiset"),
+        )
+        .opts(csv_read_options("csv1-"))
+        .opts(csv_read_options("csv2-"))
+        .opts(GlobalOptions::options())
+    }
+
+    fn set_arg(&mut self, flag: &str, value: String) -> Result<(), CliError> {
+        match flag {
+            "file1" => self.file1 = value.clone(),
+            "format1" => self.format1 = Some(parse_value(flag, value)?),
+            "file2" => self.file2 = value.clone(),
+            "format2" => self.format2 = Some(parse_value(flag, value)?),
+            "time-format1" => self.time1 = parse_value(flag, value)?,
+            "time-format2" => self.time2 = parse_value(flag, value)?,
+            "ignore" => self.comparison.set_ignored(flag, &value)?,
+            "trim-descriptions" => self.comparison.trim = parse_value(flag, value)?,
+            "ignore-case" => self.comparison.ignore_case = parse_value(flag, value)?,
+            "nfc" => self.comparison.nfc = parse_value(flag, value)?,
+            "time-tolerance" => {
+                self.comparison.time_tolerance =
+                    parse_duration(&value).ok_or(CliError::InvalidValue {
+                        name: format!("--{}", flag),
+                        value,
+                    })?
+            }
+            "order" => self.comparison.order = parse_value(flag, value)?,
+            _ if flag.starts_with("csv1-") => {
+                set_csv_option(&mut self.csv1, flag, &flag["csv1-".len()..], value)?
+            }
+            _ if flag.starts_with("csv2-") => {
+                set_csv_option(&mut self.csv2, flag, &flag["csv2-".len()..], value)?
+            }
+            _ if GLOBAL_OPTIONS.contains(&flag) => self.global.set(flag, value)?,
+            _ => {
+                return Err(CliError::UnknownArgument {
+                    name: format!("--{}", flag),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Subcommand for CompareConfig {
+    fn run(self) -> Result<(), CliError> {
+        let format1 = self
+            .global
+            .resolve_format(self.format1, &self.file1, "format1")?;
+        let format2 = self
+            .global
+            .resolve_format(self.format2, &self.file2, "format2")?;
+        let options1 = self.read_options(&self.csv1, self.time1);
+        let options2 = self.read_options(&self.csv2, self.time2);
+        let storage1 = read_file(&self.file1, format1, &options1)?;
+        let storage2 = read_file(&self.file2, format2, &options2)?;
+        self.global.info(&format!(
+            "read {} and {} records",
+            storage1.records().len(),
+            storage2.records().len()
+        ));
+
+        let (only1, only2) = self
+            .comparison
+            .unmatched(storage1.records(), storage2.records());
+        if !only1.is_empty() || !only2.is_empty() {
+            for (file, records) in [(&self.file1, &only1), (&self.file2, &only2)] {
+                for record in records {
+                    self.global
+                        .info(&format!("only in '{}': TX_ID {}", file, record.tx_id));
+                }
+            }
+            println!(
+                "!!! The transaction records in '{}' and '{}' are NOT IDENTICAL.",
+                self.file1, self.file2
+            );
+            return Err(CliError::Differences {
+                file1: self.file1,
+                file2: self.file2,
+            });
+        }
+
+        println!(
+            "The transaction records in '{}' and '{}' are identical.",
+            self.file1, self.file2
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{ExtraFields, YPBankRecordStatus, YPBankRecordType};
+    use crate::timestamp::Timestamp;
+
+    fn record(tx_id: u64, description: &str, ms: u64) -> YPBankRecord {
+        YPBankRecord {
+            tx_id,
+            tx_type: YPBankRecordType::DEPOSIT,
+            from_user_id: 0,
+            to_user_id: 1,
+            amount: 100,
+            currency: None,
+            timestamp: Timestamp::from_millis(ms),
+            status: YPBankRecordStatus::SUCCESS,
+            description: description.to_string(),
+            extra: ExtraFields::new(),
+        }
+    }
+
+    #[test]
+    fn normalizes_descriptions() {
+        let a = record(1, " Caf\u{e9} ", 0);
+        let b = record(1, "CAFE\u{301}", 0);
+        let mut comparison = Comparison::default();
+        assert!(!comparison.matches(&a, &b));
+
+        comparison.trim = true;
+        comparison.ignore_case = true;
+        assert!(!comparison.matches(&a, &b));
+        comparison.nfc = true;
+        assert!(comparison.matches(&a, &b));
+
+        let mut comparison = Comparison::default();
+        comparison.set_ignored("ignore", "DESCRIPTION").unwrap();
+        assert!(comparison.matches(&a, &b));
+    }
+
+    #[test]
+    fn ignores_fields() {
+        let a = record(1, "x", 0);
+        let mut b = record(2, "x", 0);
+        b.extra.insert("CHANNEL".to_string(), "web".to_string());
+
+        let mut comparison = Comparison::default();
+        comparison.set_ignored("ignore", "TX_ID").unwrap();
+        assert!(!comparison.matches(&a, &b));
+        comparison.set_ignored("ignore", "CHANNEL").unwrap();
+        assert!(comparison.matches(&a, &b));
+        assert!(comparison.set_ignored("ignore", "BAD NAME").is_err());
+    }
+
+    #[test]
+    fn applies_time_tolerance() {
+        let a = record(1, "x", 1000);
+        let b = record(1, "x", 1999);
+        let mut comparison = Comparison::default();
+        assert!(!comparison.matches(&a, &b));
+        comparison.time_tolerance = Duration::from_secs(1);
+        assert!(comparison.matches(&a, &b));
+    }
+
+    #[test]
+    fn matches_multisets_and_sequences() {
+        let left = [record(1, "x", 0), record(2, "x", 0), record(2, "x", 0)];
+        let right = [record(2, "x", 0), record(1, "x", 0), record(3, "x", 0)];
+        let ids =
+            |records: Vec<&YPBankRecord>| -> Vec<u64> { records.iter().map(|r| r.tx_id).collect() };
+
+        let mut comparison = Comparison::default();
+        let (only_left, only_right) = comparison.unmatched(&left, &right);
+        assert_eq!(ids(only_left), [2]);
+        assert_eq!(ids(only_right), [3]);
+
+        comparison.order = MatchOrder::Sequence;
+        let (only_left, only_right) = comparison.unmatched(&left, &right[..2]);
+        assert_eq!(ids(only_left), [1, 2, 2]);
+        assert_eq!(ids(only_right), [2, 1]);
+    }
+
+    #[test]
+    fn reads_each_file_with_its_time_format() {
+        let dir = std::env::temp_dir().join(format!("ypbank-compare-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let header = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION";
+        let seconds = dir.join("seconds.csv").to_string_lossy().into_owned();
+        let millis = dir.join("millis.csv").to_string_lossy().into_owned();
+        std::fs::write(
+            &seconds,
+            format!("{header}\n1,DEPOSIT,0,1,100,1633036800,SUCCESS,\"\"\n"),
+        )
+        .unwrap();
+        std::fs::write(
+            &millis,
+            format!("{header}\n1,DEPOSIT,0,1,100,1633036800000,SUCCESS,\"\"\n"),
+        )
+        .unwrap();
+
+        let config = |time1: Option<&str>| {
+            let mut config = CompareConfig::default();
+            config.set_arg("file1", seconds.clone()).unwrap();
+            config.set_arg("file2", millis.clone()).unwrap();
+            if let Some(time1) = time1 {
+                config.set_arg("time-format1", time1.to_string()).unwrap();
+            }
+            config
+        };
+        assert!(matches!(
+            config(None).run(),
+            Err(CliError::Differences { .. })
+        ));
+        config(Some("s")).run().unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,320 @@
+//! Conversion of a record file to another format.
+
+use super::{
+    ErrorPolicy, GLOBAL_OPTIONS, GlobalOptions, STDIO, Subcommand, parse_value, read_file,
+    write_file,
+};
+use crate::cli::{CliConfig, Command, Opt, csv_read_options, csv_write_options, set_csv_option};
+use crate::currency::{AmountFormat, Currency};
+use crate::error::CliError;
+use crate::format::format_csv::CsvOptions;
+#[cfg(feature = "sqlite")]
+use crate::format::format_sqlite::{SqliteMode, SqliteStore};
+use crate::format::format_txt::TxtOptions;
+use crate::format::{FORMAT_NAMES, Format, FormatOptions};
+use crate::storage::YPBankStorage;
+use crate::timestamp::{TimestampFormat, parse_utc_offset};
+use chrono::FixedOffset;
+
+/// Values accepted by the timestamp format options
+pub const TIME_FORMATS: &[&str] = &["s", "ms", "us", "iso8601"];
+/// Values accepted by the amount format options
+pub const AMOUNT_FORMATS: &[&str] = &["minor", "decimal"];
+/// Values accepted by `--sqlite-mode`
+#[cfg(feature = "sqlite")]
+pub const SQLITE_MODES: &[&str] = &["replace", "append", "upsert"];
+
+/// Options of the `convert` subcommand
+#[derive(Default)]
+pub struct ConvertConfig {
+    global: GlobalOptions,
+    inputs: Vec<String>,
+    input_format: Option<Format>,
+    output_format: Option<Format>,
+    output: String,
+    force: bool,
+    input_time: TimestampFormat,
+    output_time: Option<TimestampFormat>,
+    output_timezone: Option<FixedOffset>,
+    input_amount: AmountFormat,
+    output_amount: AmountFormat,
+    currency: Option<Currency>,
+    input_csv: CsvOptions,
+    output_csv: CsvOptions,
+    #[cfg(feature = "sqlite")]
+    sqlite_mode: Option<SqliteMode>,
+}
+
+impl ConvertConfig {
+    /// Timestamp format of the output, with the time zone applied
+    fn output_time(&self) -> TimestampFormat {
+        match (self.output_time, self.output_timezone) {
+            (_, Some(offset)) => TimestampFormat::Iso8601(offset),
+            (Some(format), None) => format,
+            (None, None) => TimestampFormat::default(),
+        }
+    }
+
+    fn input_options(&self) -> FormatOptions {
+        FormatOptions {
+            csv: CsvOptions {
+                timestamp: self.input_time,
+                amount: self.input_amount,
+                currency: self.currency,
+                ..self.input_csv.clone()
+            },
+            txt: TxtOptions {
+                timestamp: self.input_time,
+                amount: self.input_amount,
+                currency: self.currency,
+                ..TxtOptions::default()
+            },
+            ..FormatOptions::default()
+        }
+        .with_limits(self.global.limits)
+    }
+
+    fn output_options(&self) -> FormatOptions {
+        FormatOptions {
+            csv: CsvOptions {
+                timestamp: self.output_time(),
+                amount: self.output_amount,
+                currency: self.currency,
+                ..self.output_csv.clone()
+            },
+            txt: TxtOptions {
+                timestamp: self.output_time(),
+                amount: self.output_amount,
+                currency: self.currency,
+                ..TxtOptions::default()
+            },
+            ..FormatOptions::default()
+        }
+    }
+}
+
+impl CliConfig for ConvertConfig {
+    fn command() -> Command {
+        let command = Command::new(
+            "convert",
+            "Converts YPBank transaction files between formats",
+        )
+        .opt(
+            Opt::value(
+                "input",
+                "FILE",
+                "Input files or glob patterns, concatenated; - for stdin",
+            )
+            .short('i')
+            .required()
+            .positional()
+            .multiple(),
+        )
+        .opt(
+            Opt::value(
+                "input-format",
+                "FMT",
+                "Input format; detected from the extension if omitted",
+            )
+            .choices(FORMAT_NAMES),
+        )
+        .opt(
+            Opt::value(
+                "output-format",
+                "FMT",
+                "Output format; detected from the extension if omitted",
+            )
+            .choices(FORMAT_NAMES),
+        )
+        .opt(Opt::value("output", "FILE", "Output file; stdout if omitted or -").short('o'))
+        .opt(Opt::flag("force", "Overwrite the output file if it exists").short('f'))
+        .opt(
+            Opt::value("input-time-format", "UNIT", "Timestamp format of the input")
+                .choices(TIME_FORMATS)
+                .default("ms"),
+        )
+        .opt(
+            Opt::value(
+                "output-time-format",
+                "UNIT",
+                "Timestamp format of the output",
+            )
+            .choices(TIME_FORMATS),
+        )
+        .opt(Opt::value(
+            "output-timezone",
+            "OFFSET",
+            "Render ISO-8601 output timestamps at this UTC offset, e.g. +03:00",
+        ))
+        .opt(
+            Opt::value("input-amount-format", "FMT", "Amount format of the input")
+                .choices(AMOUNT_FORMATS)
+                .default("minor"),
+        )
+        .opt(
+            Opt::value("output-amount-format", "FMT", "Amount format of the output")
+                .choices(AMOUNT_FORMATS)
+                .default("minor"),
+        )
+        .opt(Opt::value(
+            "currency",
+            "CODE",
+            "ISO 4217 currency of records that do not specify one",
+        ))
+        .opt(Opt::value(
+            "columns",
+            "LIST",
+            "Output CSV columns; same as --output-csv-columns",
+        ))
+        .opts(csv_read_options("input-csv-"))
+        .opts(csv_write_options("output-csv-"));
+        #[cfg(feature = "sqlite")]
+        let command = command.opt(
+            Opt::value(
+                "sqlite-mode",
+                "MODE",
+                "How records are written into an existing SQLite output [default: replace]",
+            )
+            .choices(SQLITE_MODES),
+        );
+        command.opts(GlobalOptions::options())
+    }
+
+    fn set_arg(&mut self, flag: &str, value: String) -> Result<(), CliError> {
+        match flag {
+            "input" => self.inputs.push(value),
+            "input-format" => self.input_format = Some(parse_value(flag, value)?),
+            "output-format" => self.output_format = Some(parse_value(flag, value)?),
+            "output" => self.output = value.clone(),
+            "force" => self.force = parse_value(flag, value)?,
+            "input-time-format" => self.input_time = parse_value(flag, value)?,
+            "output-time-format" => self.output_time = Some(parse_value(flag, value)?),
+            "input-amount-format" => self.input_amount = parse_value(flag, value)?,
+            "output-amount-format" => self.output_amount = parse_value(flag, value)?,
+            "currency" => self.currency = Some(parse_value(flag, value)?),
+            "columns" => set_csv_option(&mut self.output_csv, flag, "columns", value)?,
+            #[cfg(feature = "sqlite")]
+            "sqlite-mode" => self.sqlite_mode = Some(parse_value(flag, value)?),
+            "output-timezone" => {
+                self.output_timezone =
+                    Some(parse_utc_offset(&value).ok_or(CliError::InvalidValue {
+                        name: format!("--{}", flag),
+                        value,
+                    })?)
+            }
+            _ if flag.starts_with("input-csv-") => set_csv_option(
+                &mut self.input_csv,
+                flag,
+                &flag["input-csv-".len()..],
+                value,
+            )?,
+            _ if flag.starts_with("output-csv-") => set_csv_option(
+                &mut self.output_csv,
+                flag,
+                &flag["output-csv-".len()..],
+                value,
+            )?,
+            _ if GLOBAL_OPTIONS.contains(&flag) => self.global.set(flag, value)?,
+            _ => {
+                return Err(CliError::UnknownArgument {
+                    name: format!("--{}", flag),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_args(&self) -> Result<(), CliError> {
+        #[cfg(feature = "sqlite")]
+        if let Some(mode) = self.sqlite_mode {
+            let invalid = |requirement: &str| CliError::InvalidValue {
+                name: "--sqlite-mode".to_string(),
+                value: format!("{} (requires {})", mode, requirement),
+            };
+            let to_stdout = matches!(self.output.as_str(), "" | STDIO);
+            let format = match self.output_format {
+                None if !to_stdout && self.global.detect_format => Format::from_path(&self.output),
+                format => format,
+            };
+            if format != Some(Format::Sqlite) {
+                return Err(invalid("SQLite output"));
+            }
+            if mode != SqliteMode::Replace && to_stdout {
+                return Err(invalid("--output FILE"));
+            }
+        }
+        if let (Some(TimestampFormat::Unix(unit)), Some(_)) =
+            (self.output_time, self.output_timezone)
+        {
+            return Err(CliError::InvalidValue {
+                name: "--output-time-format".to_string(),
+                value: format!("{unit} (--output-timezone requires iso8601)"),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Subcommand for ConvertConfig {
+    fn run(self) -> Result<(), CliError> {
+        let output_format = match self.output.as_str() {
+            "" | STDIO => self
+                .output_format
+                .ok_or_else(|| CliError::MissingArgument {
+                    name: "--output-format".to_string(),
+                })?,
+            output => self
+                .global
+                .resolve_format(self.output_format, output, "output-format")?,
+        };
+
+        // Read and concatenate the inputs
+        let input_options = self.input_options();
+        let mut storage = YPBankStorage::new();
+        let result = self.global.for_each_file(&self.inputs, |input| {
+            let input_format =
+                self.global
+                    .resolve_format(self.input_format, input, "input-format")?;
+            let records = read_file(input, input_format, &input_options)?;
+            self.global.info(&format!(
+                "read {} records from '{}' ({})",
+                records.records().len(),
+                input,
+                input_format
+            ));
+            storage.extend(records.into_records());
+            Ok(())
+        });
+        if result.is_err() && self.global.on_error == ErrorPolicy::Abort {
+            return result;
+        }
+
+        // Write the output; with `--on-error continue` it holds the records
+        // of the inputs that could be read
+        let count = storage.records().len();
+        #[cfg(feature = "sqlite")]
+        if let Some(mode @ (SqliteMode::Append | SqliteMode::Upsert)) = self.sqlite_mode {
+            // Appending changes the database in place instead of replacing it
+            let path = self.output.clone();
+            SqliteStore::open(&path)
+                .and_then(|mut store| store.write(&storage, mode))
+                .map_err(|e| CliError::File {
+                    path,
+                    error: Box::new(e.into()),
+                })?;
+            self.global
+                .info(&format!("wrote {} records as {}", count, output_format));
+            return result;
+        }
+        write_file(
+            &self.output,
+            output_format,
+            storage,
+            &self.output_options(),
+            self.force,
+        )?;
+        self.global
+            .info(&format!("wrote {} records as {}", count, output_format));
+        result
+    }
+}
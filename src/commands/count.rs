@@ -0,0 +1,48 @@
+//! Counting of the records of files.
+
+use super::{GlobalOptions, InputOptions, Subcommand, in_file, io_error, open_records};
+use crate::cli::{CliConfig, Command};
+use crate::error::CliError;
+use std::io::{self, Write};
+
+/// Options of the `count` subcommand
+#[derive(Default)]
+pub struct CountConfig {
+    input: InputOptions,
+}
+
+impl CliConfig for CountConfig {
+    fn command() -> Command {
+        Command::new("count", "Prints the number of records in files")
+            .opts(InputOptions::options())
+            .opts(GlobalOptions::options())
+    }
+
+    fn set_arg(&mut self, flag: &str, value: String) -> Result<(), CliError> {
+        self.input.set(flag, value)
+    }
+}
+
+impl Subcommand for CountConfig {
+    fn run(self) -> Result<(), CliError> {
+        let input = &self.input;
+        let options = input.read_options();
+        let mut out = io::stdout().lock();
+        let (mut files, mut total) = (0, 0);
+        let result = input.global.for_each_file(&input.files, |file| {
+            let format = input.global.resolve_format(input.format, file, "format")?;
+            let mut count = 0;
+            for record in open_records(file, format, &options)? {
+                record.map_err(|e| in_file(file, e.into()))?;
+                count += 1;
+            }
+            files += 1;
+            total += count;
+            writeln!(out, "{} {}", count, file).map_err(io_error)
+        });
+        if files > 1 {
+            writeln!(out, "{} total", total).map_err(io_error)?;
+        }
+        result
+    }
+}
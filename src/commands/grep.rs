@@ -0,0 +1,84 @@
+//! Search of records by field values, see [`crate::filter`].
+
+use super::{GlobalOptions, InputOptions, OutputOptions, Subcommand, io_error, parse_value};
+use crate::cli::{CliConfig, Command, Opt};
+use crate::error::CliError;
+use crate::filter::RecordFilter;
+use std::io::{self, Write};
+
+/// Options of the `grep` subcommand
+#[derive(Default)]
+pub struct GrepConfig {
+    input: InputOptions,
+    output: OutputOptions,
+    pattern: String,
+    ignore_case: bool,
+    exact: bool,
+    invert_match: bool,
+    count: bool,
+}
+
+impl CliConfig for GrepConfig {
+    fn command() -> Command {
+        Command::new("grep", "Prints the records with a field matching a pattern")
+            .opt(
+                Opt::value(
+                    "pattern",
+                    "PATTERN",
+                    "FIELD=TEXT to search one field, e.g. STATUS=FAILURE, or TEXT to search all",
+                )
+                .required()
+                .positional(),
+            )
+            .opts(InputOptions::options())
+            .opts(OutputOptions::options())
+            .opt(Opt::flag("ignore-case", "Compare ignoring case").short('i'))
+            .opt(Opt::flag("exact", "Match whole field values only").short('x'))
+            .opt(Opt::flag(
+                "invert-match",
+                "Print the records that do not match",
+            ))
+            .opt(Opt::flag("count", "Print only the number of matching records").short('c'))
+            .opts(GlobalOptions::options())
+    }
+
+    fn set_arg(&mut self, flag: &str, value: String) -> Result<(), CliError> {
+        match flag {
+            "pattern" => self.pattern = value,
+            "ignore-case" => self.ignore_case = parse_value(flag, value)?,
+            "exact" => self.exact = parse_value(flag, value)?,
+            "invert-match" => self.invert_match = parse_value(flag, value)?,
+            "count" => self.count = parse_value(flag, value)?,
+            _ if flag.starts_with("output-") => self.output.set(flag, value)?,
+            _ => self.input.set(flag, value)?,
+        }
+        Ok(())
+    }
+}
+
+impl Subcommand for GrepConfig {
+    fn run(self) -> Result<(), CliError> {
+        let filter = RecordFilter {
+            exact: self.exact,
+            ignore_case: self.ignore_case,
+            ..RecordFilter::parse(&self.pattern)
+        };
+        let mut out = self.output.stdout_records();
+        let mut count = 0;
+        let result = self.input.for_each_record(|_, record| {
+            if filter.matches(&record) != self.invert_match {
+                count += 1;
+                if !self.count {
+                    out.write_record(&record)?;
+                }
+            }
+            Ok(true)
+        });
+        if self.count {
+            writeln!(io::stdout(), "{}", count).map_err(io_error)?;
+        } else {
+            out.finish()?;
+        }
+        result
+    }
+}
@@ -0,0 +1,54 @@
+//! Printing of the first records of files.
+
+use super::{GlobalOptions, InputOptions, OutputOptions, Subcommand, parse_value};
+use crate::cli::{CliConfig, Command, Opt, ValueKind};
+use crate::error::CliError;
+
+/// Options of the `head` subcommand
+#[derive(Default)]
+pub struct HeadConfig {
+    input: InputOptions,
+    output: OutputOptions,
+    lines: usize,
+}
+
+impl CliConfig for HeadConfig {
+    fn command() -> Command {
+        Command::new("head", "Prints the first records of files")
+            .opts(InputOptions::options())
+            .opts(OutputOptions::options())
+            .opt(
+                Opt::value("lines", "N", "Number of records to print")
+                    .short('n')
+                    .default("10")
+                    .kind(ValueKind::Integer),
+            )
+            .opts(GlobalOptions::options())
+    }
+
+    fn set_arg(&mut self, flag: &str, value: String) -> Result<(), CliError> {
+        match flag {
+            "lines" => self.lines = parse_value(flag, value)?,
+            _ if flag.starts_with("output-") => self.output.set(flag, value)?,
+            _ => self.input.set(flag, value)?,
+        }
+        Ok(())
+    }
+}
+
+impl Subcommand for HeadConfig {
+    fn run(self) -> Result<(), CliError> {
+        let mut out = self.output.stdout_records();
+        let mut left = self.lines;
+        let result = match left {
+            0 => Ok(()),
+            _ => self.input.for_each_record(|_, record| {
+                out.write_record(&record)?;
+                left -= 1;
+                Ok(left > 0)
+            }),
+        };
+        out.finish()?;
+        result
+    }
+}
@@ -0,0 +1,951 @@
+//! Subcommands of the `ypbank` tool and the options they share.
+
+use crate::cli::{
+    CliConfig, Command, Dispatch, MultiCommand, Opt, Parsed, ValueKind, csv_read_options,
+    csv_write_options, parse_command, set_csv_option,
+};
+use crate::currency::AmountFormat;
+use crate::error::{CliError, ErrorClass};
+use crate::format::format_csv::CsvOptions;
+use crate::format::format_txt::TxtOptions;
+use crate::format::record_reader::RecordReader;
+use crate::format::record_writer::RecordWriter;
+use crate::format::{FORMAT_NAMES, Format, FormatOptions};
+use crate::json;
+use crate::parser::ParseLimits;
+use crate::storage::{YPBankRecord, YPBankStorage};
+use crate::timestamp::TimestampFormat;
+use convert::{AMOUNT_FORMATS, TIME_FORMATS};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, StdoutLock, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, ExitCode};
+use std::str::FromStr;
+use strum_macros::{Display, EnumString};
+
+/// `cat` subcommand.
+pub mod cat;
+/// `compare` subcommand.
+pub mod compare;
+/// `convert` subcommand.
+pub mod convert;
+/// `count` subcommand.
+pub mod count;
+/// `grep` subcommand.
+pub mod grep;
+/// `head` subcommand.
+pub mod head;
+/// `reconcile` subcommand.
+pub mod reconcile;
+/// `serve` subcommand.
+#[cfg(feature = "server")]
+pub mod serve;
+/// `stats` subcommand.
+pub mod stats;
+/// `tail` subcommand.
+pub mod tail;
+/// `validate` subcommand.
+pub mod validate;
+/// `view` subcommand.
+#[cfg(feature = "tui")]
+pub mod view;
+
+/// File name standing for stdin in inputs and stdout in outputs
+pub const STDIO: &str = "-";
+
+/// Names of the options handled by [`GlobalOptions::set`]
+pub const GLOBAL_OPTIONS: [&str; 9] = [
+    "verbose",
+    "quiet",
+    "on-error",
+    "detect-format",
+    "error-format",
+    "max-record-size",
+    "max-description-len",
+    "max-line-len",
+    "max-records",
+];
+
+/// A subcommand that can be run once its options are parsed
+pub trait Subcommand: CliConfig {
+    /// Runs the subcommand
+    fn run(self) -> Result<(), CliError>;
+}
+
+/// What to do when one of several input files fails
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum ErrorPolicy {
+    /// Stop at the first failing file
+    #[default]
+    Abort,
+    /// Process the remaining files and report all failures at the end
+    Continue,
+}
+
+/// How errors are reported on stderr
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum ErrorFormat {
+    /// A human-readable message followed by the usage for usage errors
+    #[default]
+    Text,
+    /// A single JSON object per run, see [`report_error`]
+    Json,
+}
+
+impl ErrorFormat {
+    /// Finds `--error-format` among raw arguments
+    ///
+    /// Errors may have to be reported before the arguments could be parsed,
+    /// so the option is looked up directly; the last valid value wins.
+    pub fn from_args(args: &[String]) -> Self {
+        let mut format = ErrorFormat::default();
+        let mut rest = args.iter().skip(1).take_while(|arg| *arg != "--");
+        while let Some(arg) = rest.next() {
+            let value = match arg.strip_prefix("--error-format") {
+                Some("") => rest.next().map(String::as_str),
+                Some(value) => value.strip_prefix('='),
+                None => None,
+            };
+            if let Some(value) = value.and_then(|v| v.parse().ok()) {
+                format = value;
+            }
+        }
+        format
+    }
+}
+
+/// Options shared by every subcommand
+#[derive(Debug, Clone)]
+pub struct GlobalOptions {
+    /// Report progress on stderr
+    pub verbose: bool,
+    /// Suppress informational output on stdout
+    pub quiet: bool,
+    /// Behaviour when an input file fails
+    pub on_error: ErrorPolicy,
+    /// Detect omitted formats from file extensions
+    pub detect_format: bool,
+    /// How errors are reported
+    pub error_format: ErrorFormat,
+    /// Bounds on the input files
+    pub limits: ParseLimits,
+}
+
+impl Default for GlobalOptions {
+    fn default() -> Self {
+        Self {
+            verbose: false,
+            quiet: false,
+            on_error: ErrorPolicy::default(),
+            detect_format: true,
+            error_format: ErrorFormat::default(),
+            limits: ParseLimits::default(),
+        }
+    }
+}
+
+impl GlobalOptions {
+    /// Describes the options listed in [`GLOBAL_OPTIONS`]
+    pub fn options() -> Vec<Opt> {
+        vec![
+            Opt::flag("verbose", "Report progress on stderr").short('v'),
+            Opt::flag("quiet", "Suppress informational output").short('q'),
+            Opt::value("on-error", "POLICY", "What to do when an input file fails")
+                .choices(&["abort", "continue"])
+                .default("abort"),
+            Opt::flag(
+                "detect-format",
+                "Detect omitted formats from file extensions",
+            )
+            .default("true"),
+            Opt::value("error-format", "FMT", "How errors are reported on stderr")
+                .choices(&["text", "json"])
+                .default("text"),
+            Opt::value(
+                "max-record-size",
+                "BYTES",
+                "Largest accepted record [default: 1048576]",
+            )
+            .kind(ValueKind::Integer),
+            Opt::value(
+                "max-description-len",
+                "BYTES",
+                "Longest accepted description [default: 65536]",
+            )
+            .kind(ValueKind::Integer),
+            Opt::value(
+                "max-line-len",
+                "BYTES",
+                "Longest accepted CSV or TXT line [default: 1048576]",
+            )
+            .kind(ValueKind::Integer),
+            Opt::value(
+                "max-records",
+                "N",
+                "Largest accepted number of records per file",
+            )
+            .kind(ValueKind::Integer),
+        ]
+    }
+
+    /// Applies one of the options listed in [`GLOBAL_OPTIONS`]
+    pub fn set(&mut self, flag: &str, value: String) -> Result<(), CliError> {
+        match flag {
+            "verbose" => self.verbose = parse_value(flag, value)?,
+            "quiet" => self.quiet = parse_value(flag, value)?,
+            "on-error" => self.on_error = parse_value(flag, value)?,
+            "detect-format" => self.detect_format = parse_value(flag, value)?,
+            "error-format" => self.error_format = parse_value(flag, value)?,
+            "max-record-size" => self.limits.max_record_size = parse_value(flag, value)?,
+            "max-description-len" => self.limits.max_description_len = parse_value(flag, value)?,
+            "max-line-len" => self.limits.max_line_len = parse_value(flag, value)?,
+            "max-records" => self.limits.max_records = parse_value(flag, value)?,
+            _ => {
+                return Err(CliError::UnknownArgument {
+                    name: format!("--{}", flag),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Options for reading files with the given CSV options and the limits
+    pub fn read_options(&self, csv: &CsvOptions) -> FormatOptions {
+        FormatOptions {
+            csv: csv.clone(),
+            ..FormatOptions::default()
+        }
+        .with_limits(self.limits)
+    }
+
+    /// Returns the given format, or detects it from the path if allowed
+    pub fn resolve_format(
+        &self,
+        format: Option<Format>,
+        path: &str,
+        flag: &str,
+    ) -> Result<Format, CliError> {
+        format
+            .or_else(|| {
+                if self.detect_format {
+                    Format::from_path(path)
+                } else {
+                    None
+                }
+            })
+            .ok_or_else(|| CliError::MissingArgument {
+                name: format!("--{} (cannot detect the format of '{}')", flag, path),
+            })
+    }
+
+    /// Prints a progress message on stderr in verbose mode
+    pub fn info(&self, message: &str) {
+        if self.verbose {
+            eprintln!("{message}");
+        }
+    }
+
+    /// Prints an informational message on stdout unless quiet
+    ///
+    /// A closed stdout is ignored rather than aborting the program.
+    pub fn say(&self, message: &str) {
+        if !self.quiet {
+            let _ = writeln!(io::stdout(), "{message}");
+        }
+    }
+
+    /// Runs `f` for every file according to the error policy
+    ///
+    /// Glob patterns among `files` are expanded first, see [`expand_files`].
+    pub fn for_each_file<F>(&self, files: &[String], mut f: F) -> Result<(), CliError>
+    where
+        F: FnMut(&str) -> Result<(), CliError>,
+    {
+        let mut errors = Vec::new();
+        for file in expand_files(files)? {
+            if let Err(e) = f(&file) {
+                match self.on_error {
+                    ErrorPolicy::Abort => return Err(e),
+                    ErrorPolicy::Continue => errors.push(e),
+                }
+            }
+        }
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(CliError::Multiple(errors)),
+        }
+    }
+
+    /// Streams the records of files to `f` as they are read, until it returns
+    /// `false`
+    ///
+    /// Files are handled as in [`GlobalOptions::for_each_file`] and opened
+    /// with [`open_records`], so a huge file is never held in memory.
+    pub fn for_each_record<F>(
+        &self,
+        files: &[String],
+        format: Option<Format>,
+        options: &FormatOptions,
+        mut f: F,
+    ) -> Result<(), CliError>
+    where
+        F: FnMut(&str, YPBankRecord) -> Result<bool, CliError>,
+    {
+        let mut done = false;
+        self.for_each_file(files, |file| {
+            if done {
+                return Ok(());
+            }
+            let format = self.resolve_format(format, file, "format")?;
+            let mut count = 0;
+            for record in open_records(file, format, options)? {
+                count += 1;
+                if !f(file, record.map_err(|e| in_file(file, e.into()))?)? {
+                    done = true;
+                    break;
+                }
+            }
+            self.info(&format!("read {} records from '{}'", count, file));
+            Ok(())
+        })
+    }
+}
+
+/// Input files of the subcommands streaming records: `cat`, `head`, `tail`,
+/// `grep` and `count`, and the global options
+#[derive(Default)]
+pub struct InputOptions {
+    /// Options shared by every subcommand
+    pub global: GlobalOptions,
+    /// Input files or glob patterns
+    pub files: Vec<String>,
+    /// Format of the inputs; detected from the extensions if omitted
+    pub format: Option<Format>,
+    /// Options of CSV inputs
+    pub csv: CsvOptions,
+}
+
+impl InputOptions {
+    /// Describes the input options; the global ones are described by
+    /// [`GlobalOptions::options`]
+    pub fn options() -> Vec<Opt> {
+        let mut options = vec![
+            Opt::value("files", "FILE", "Input files or glob patterns; - for stdin")
+                .required()
+                .positional()
+                .multiple(),
+            Opt::value(
+                "format",
+                "FMT",
+                "Input format; detected from the extension if omitted",
+            )
+            .choices(FORMAT_NAMES),
+        ];
+        options.extend(csv_read_options("csv-"));
+        options
+    }
+
+    /// Applies an input option or one of the [`GLOBAL_OPTIONS`]
+    pub fn set(&mut self, flag: &str, value: String) -> Result<(), CliError> {
+        match flag {
+            "files" => self.files.push(value),
+            "format" => self.format = Some(parse_value(flag, value)?),
+            _ if flag.starts_with("csv-") => {
+                set_csv_option(&mut self.csv, flag, &flag["csv-".len()..], value)?
+            }
+            _ => self.global.set(flag, value)?,
+        }
+        Ok(())
+    }
+
+    /// Options for reading the inputs
+    pub fn read_options(&self) -> FormatOptions {
+        self.global.read_options(&self.csv)
+    }
+
+    /// Streams the records of the inputs to `f`, see
+    /// [`GlobalOptions::for_each_record`]
+    pub fn for_each_record<F>(&self, f: F) -> Result<(), CliError>
+    where
+        F: FnMut(&str, YPBankRecord) -> Result<bool, CliError>,
+    {
+        self.global
+            .for_each_record(&self.files, self.format, &self.read_options(), f)
+    }
+}
+
+/// Records printed to stdout by `cat`, `head`, `tail` and `grep`
+#[derive(Default)]
+pub struct OutputOptions {
+    /// Output format; TXT if omitted
+    pub format: Option<Format>,
+    /// Representation of timestamps
+    pub time: TimestampFormat,
+    /// Representation of amounts
+    pub amount: AmountFormat,
+    /// Options of CSV output
+    pub csv: CsvOptions,
+}
+
+impl OutputOptions {
+    /// Describes the output options
+    pub fn options() -> Vec<Opt> {
+        let mut options = vec![
+            Opt::value("output-format", "FMT", "Output format")
+                .choices(FORMAT_NAMES)
+                .default("txt"),
+            Opt::value(
+                "output-time-format",
+                "UNIT",
+                "Timestamp format of the output",
+            )
+            .choices(TIME_FORMATS)
+            .default("ms"),
+            Opt::value("output-amount-format", "FMT", "Amount format of the output")
+                .choices(AMOUNT_FORMATS)
+                .default("minor"),
+        ];
+        options.extend(csv_write_options("output-csv-"));
+        options
+    }
+
+    /// Applies one of the options of [`OutputOptions::options`], which all
+    /// start with `output-`
+    pub fn set(&mut self, flag: &str, value: String) -> Result<(), CliError> {
+        match flag {
+            "output-format" => self.format = Some(parse_value(flag, value)?),
+            "output-time-format" => self.time = parse_value(flag, value)?,
+            "output-amount-format" => self.amount = parse_value(flag, value)?,
+            _ if flag.starts_with("output-csv-") => {
+                set_csv_option(&mut self.csv, flag, &flag["output-csv-".len()..], value)?
+            }
+            _ => {
+                return Err(CliError::UnknownArgument {
+                    name: format!("--{}", flag),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Options for writing the records
+    pub fn write_options(&self) -> FormatOptions {
+        FormatOptions {
+            csv: CsvOptions {
+                timestamp: self.time,
+                amount: self.amount,
+                ..self.csv.clone()
+            },
+            txt: TxtOptions {
+                timestamp: self.time,
+                amount: self.amount,
+                ..TxtOptions::default()
+            },
+            ..FormatOptions::default()
+        }
+    }
+
+    /// Creates a streaming writer of the records to stdout
+    ///
+    /// CSV is streamed too: without `--output-csv-columns` the header has the
+    /// standard columns, and a record with a currency or extra fields fails,
+    /// see [`RecordWriter::streaming`].
+    pub fn stdout_records(&self) -> RecordWriter<BufWriter<StdoutLock<'static>>> {
+        let stdout = BufWriter::new(io::stdout().lock());
+        let format = self.format.unwrap_or(Format::Txt);
+        RecordWriter::streaming(format, stdout, &self.write_options())
+    }
+}
+
+/// Describes the `ypbank` program and all of its subcommands
+pub fn program() -> MultiCommand {
+    let program = MultiCommand::new("ypbank", "Tools for YPBank transaction files")
+        .version(env!("CARGO_PKG_VERSION"))
+        .global(GlobalOptions::options())
+        .subcommand(convert::ConvertConfig::command())
+        .subcommand(compare::CompareConfig::command())
+        .subcommand(validate::ValidateConfig::command())
+        .subcommand(reconcile::ReconcileConfig::command())
+        .subcommand(stats::StatsConfig::command())
+        .subcommand(cat::CatConfig::command())
+        .subcommand(head::HeadConfig::command())
+        .subcommand(tail::TailConfig::command())
+        .subcommand(grep::GrepConfig::command())
+        .subcommand(count::CountConfig::command());
+    #[cfg(feature = "server")]
+    let program = program.subcommand(serve::ServeConfig::command());
+    #[cfg(feature = "tui")]
+    let program = program.subcommand(view::ViewConfig::command());
+    program
+}
+
+/// Entry point of the `ypbank` binary
+pub fn main(args: &[String]) -> ExitCode {
+    let program = program();
+    match program.dispatch(args) {
+        Ok(Dispatch::Run {
+            name,
+            command,
+            args,
+        }) => match name.as_str() {
+            "convert" => run::<convert::ConvertConfig>(&command, &args),
+            "compare" => run::<compare::CompareConfig>(&command, &args),
+            "validate" => run::<validate::ValidateConfig>(&command, &args),
+            "reconcile" => run::<reconcile::ReconcileConfig>(&command, &args),
+            "stats" => run::<stats::StatsConfig>(&command, &args),
+            "cat" => run::<cat::CatConfig>(&command, &args),
+            "head" => run::<head::HeadConfig>(&command, &args),
+            "tail" => run::<tail::TailConfig>(&command, &args),
+            "grep" => run::<grep::GrepConfig>(&command, &args),
+            "count" => run::<count::CountConfig>(&command, &args),
+            #[cfg(feature = "server")]
+            "serve" => run::<serve::ServeConfig>(&command, &args),
+            #[cfg(feature = "tui")]
+            "view" => run::<view::ViewConfig>(&command, &args),
+            _ => unreachable!("dispatch only returns known subcommands"),
+        },
+        Ok(Dispatch::Help(text) | Dispatch::Version(text)) => {
+            println!("{text}");
+            ExitCode::SUCCESS
+        }
+        Err(e) => report_error(&e, ErrorFormat::from_args(args), &program.usage()),
+    }
+}
+
+/// Parses the arguments of a subcommand and runs it, reporting errors
+pub fn run<T: Subcommand>(command: &Command, args: &[String]) -> ExitCode {
+    let result = match parse_command::<T>(command, args) {
+        Ok(Parsed::Run(config)) => config.run(),
+        Ok(Parsed::Help(text) | Parsed::Version(text)) => {
+            println!("{text}");
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        // The reader of our output went away, e.g. `ypbank cat ... | head`
+        Err(e) if e.is_broken_pipe() => ExitCode::SUCCESS,
+        Err(e) => report_error(&e, ErrorFormat::from_args(args), &command.usage()),
+    }
+}
+
+/// Prints an error on stderr and returns the exit code of its class
+///
+/// The JSON format prints one object such as
+/// `{"class":"parse","exit_code":4,"message":"...","errors":["..."]}`,
+/// where `errors` lists the individual errors of [`CliError::Multiple`].
+pub fn report_error(error: &CliError, format: ErrorFormat, usage: &str) -> ExitCode {
+    let class = error.class();
+    match format {
+        ErrorFormat::Text => {
+            eprintln!("Error: {error}");
+            if class == ErrorClass::Usage {
+                eprintln!("{usage}");
+            }
+        }
+        ErrorFormat::Json => eprintln!("{}", error_json(error)),
+    }
+    ExitCode::from(class.exit_code())
+}
+
+/// The JSON object printed by [`report_error`]
+pub(crate) fn error_json(error: &CliError) -> String {
+    let errors: Vec<String> = match error {
+        CliError::Multiple(errors) => errors
+            .iter()
+            .map(|e| json::string(&e.to_string()))
+            .collect(),
+        error => vec![json::string(&error.to_string())],
+    };
+    format!(
+        "{{\"class\":{},\"exit_code\":{},\"message\":{},\"errors\":[{}]}}",
+        json::string(&error.class().to_string()),
+        error.class().exit_code(),
+        json::string(&error.to_string()),
+        errors.join(",")
+    )
+}
+
+/// Parses an option value with [`FromStr`]
+pub fn parse_value<T: FromStr>(flag: &str, value: String) -> Result<T, CliError> {
+    value.parse().map_err(|_| CliError::InvalidValue {
+        name: format!("--{}", flag),
+        value,
+    })
+}
+
+/// Expands glob patterns such as `*.csv` into the matching paths
+///
+/// Other names, including [`STDIO`], are kept as they are. A pattern that
+/// matches no file is an error.
+pub fn expand_files(files: &[String]) -> Result<Vec<String>, CliError> {
+    let mut expanded = Vec::new();
+    for file in files {
+        if !file.contains(['*', '?', '[']) {
+            expanded.push(file.clone());
+            continue;
+        }
+        let invalid = |reason: &str| CliError::InvalidValue {
+            name: "<FILE>".to_string(),
+            value: format!("{} ({})", file, reason),
+        };
+        let paths = glob::glob(file).map_err(|e| invalid(e.msg))?;
+        let mut matched: Vec<String> = paths
+            .filter_map(Result::ok)
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect();
+        if matched.is_empty() {
+            return Err(invalid("no matching files"));
+        }
+        matched.sort();
+        expanded.append(&mut matched);
+    }
+    Ok(expanded)
+}
+
+/// Reads all records of a file, or of stdin for [`STDIO`], in the given format
+///
+/// Errors are wrapped in [`CliError::File`] to name the file.
+pub fn read_file(
+    path: &str,
+    format: Format,
+    options: &FormatOptions,
+) -> Result<YPBankStorage, CliError> {
+    let read = || -> Result<YPBankStorage, CliError> {
+        if path == STDIO {
+            return Ok(format.read(&mut io::stdin().lock(), options)?);
+        }
+        let file = File::open(path).map_err(io_error)?;
+        Ok(format.read(&mut BufReader::new(file), options)?)
+    };
+    read().map_err(|e| CliError::File {
+        path: path.to_string(),
+        error: Box::new(e),
+    })
+}
+
+/// Opens a streaming reader of a file, or of stdin for [`STDIO`]
+///
+/// Errors are wrapped in [`CliError::File`] to name the file; errors of the
+/// records read later are not.
+pub fn open_records(
+    path: &str,
+    format: Format,
+    options: &FormatOptions,
+) -> Result<RecordReader<Box<dyn Read>>, CliError> {
+    let input: Box<dyn Read> = match path {
+        STDIO => Box::new(io::stdin().lock()),
+        path => Box::new(File::open(path).map_err(|e| in_file(path, io_error(e)))?),
+    };
+    RecordReader::new(format, input, options).map_err(|e| in_file(path, e.into()))
+}
+
+/// Wraps an error into [`CliError::File`] naming the file
+pub fn in_file(path: &str, error: CliError) -> CliError {
+    CliError::File {
+        path: path.to_string(),
+        error: Box::new(error),
+    }
+}
+
+/// Writes all records to a file, or to stdout if `path` is empty or [`STDIO`]
+///
+/// The file is written as described in [`write_output`].
+pub fn write_file(
+    path: &str,
+    format: Format,
+    storage: YPBankStorage,
+    options: &FormatOptions,
+    overwrite: bool,
+) -> Result<(), CliError> {
+    write_output(path, overwrite, |mut w| {
+        Ok(format.write(storage, &mut w, options)?)
+    })
+}
+
+/// Runs `write` on a file, or on stdout if `path` is empty or [`STDIO`]
+///
+/// A file is first written to a temporary file in the same directory, synced
+/// to disk and then renamed over `path`, so a failed write never leaves a
+/// truncated output behind. An existing file is replaced only if `overwrite`
+/// is set; otherwise the temporary file is hard-linked to `path`, so a file
+/// created there during the write is not clobbered either. Errors are wrapped
+/// in [`CliError::File`] to name the file.
+pub fn write_output<F>(path: &str, overwrite: bool, write: F) -> Result<(), CliError>
+where
+    F: FnOnce(&mut dyn Write) -> Result<(), CliError>,
+{
+    if path.is_empty() || path == STDIO {
+        let mut writer = BufWriter::new(io::stdout().lock());
+        write(&mut writer)?;
+        return writer.flush().map_err(io_error);
+    }
+
+    let target = Path::new(path);
+    if !overwrite && target.exists() {
+        return Err(CliError::OutputExists {
+            path: path.to_string(),
+        });
+    }
+    let temp = temp_path(target);
+    let write_temp = || -> Result<(), CliError> {
+        let file = File::options()
+            .write(true)
+            .create_new(true)
+            .open(&temp)
+            .map_err(io_error)?;
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        let file = writer.into_inner().map_err(|e| io_error(e.into_error()))?;
+        file.sync_all().map_err(io_error)?;
+        if overwrite {
+            fs::rename(&temp, target).map_err(io_error)?;
+        } else {
+            // Unlike a rename, a link fails if the file appeared meanwhile
+            match fs::hard_link(&temp, target) {
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    return Err(CliError::OutputExists {
+                        path: path.to_string(),
+                    });
+                }
+                result => {
+                    result.map_err(io_error)?;
+                    fs::remove_file(&temp).map_err(io_error)?;
+                }
+            }
+        }
+        sync_dir(target).map_err(io_error)
+    };
+    write_temp().map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        match e {
+            CliError::OutputExists { .. } => e,
+            e => CliError::File {
+                path: path.to_string(),
+                error: Box::new(e),
+            },
+        }
+    })
+}
+
+/// Makes the creation or replacement of `target` durable
+fn sync_dir(target: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let dir = match target.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = target;
+    Ok(())
+}
+
+/// Hidden temporary file next to `target`, e.g. `dir/.out.csv.1234.tmp`
+fn temp_path(target: &Path) -> PathBuf {
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    target.with_file_name(format!(".{}.{}.tmp", name, process::id()))
+}
+
+/// Wraps an I/O error into [`CliError::IO`]
+pub fn io_error(error: io::Error) -> CliError {
+    CliError::IO {
+        message: error.to_string(),
+        error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_formats() {
+        let mut global = GlobalOptions::default();
+        let format = global.resolve_format(None, "a.csv", "format").unwrap();
+        assert_eq!(format, Format::Csv);
+        let format = global.resolve_format(Some(Format::Txt), "a.csv", "format");
+        assert_eq!(format.unwrap(), Format::Txt);
+        assert!(global.resolve_format(None, "a.dat", "format").is_err());
+
+        global.detect_format = false;
+        assert!(global.resolve_format(None, "a.csv", "format").is_err());
+    }
+
+    #[test]
+    fn expands_glob_patterns() {
+        let dir = std::env::temp_dir().join(format!("ypbank-glob-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["b.csv", "a.csv", "c.txt"] {
+            File::create(dir.join(name)).unwrap();
+        }
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+
+        let files = expand_files(&[path("*.csv"), STDIO.to_string(), path("c.txt")]).unwrap();
+        assert_eq!(
+            files,
+            [
+                path("a.csv"),
+                path("b.csv"),
+                STDIO.to_string(),
+                path("c.txt")
+            ]
+        );
+        let missing = expand_files(&[path("*.bin")]);
+        assert!(matches!(missing, Err(CliError::InvalidValue { .. })));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn writes_files_atomically() {
+        let dir = std::env::temp_dir().join(format!("ypbank-write-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let out = dir.join("out.txt").to_string_lossy().into_owned();
+        let options = FormatOptions::default();
+        fs::write(&out, "old").unwrap();
+
+        let refused = write_file(&out, Format::Txt, YPBankStorage::new(), &options, false);
+        assert!(matches!(refused, Err(CliError::OutputExists { .. })));
+        assert_eq!(fs::read_to_string(&out).unwrap(), "old");
+
+        write_file(&out, Format::Txt, YPBankStorage::new(), &options, true).unwrap();
+        assert_eq!(fs::read_to_string(&out).unwrap(), "");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // A file created while the output is written is kept
+        fs::remove_file(&out).unwrap();
+        let raced = write_output(&out, false, |w| {
+            w.write_all(b"new").map_err(io_error)?;
+            fs::write(&out, "other").map_err(io_error)
+        });
+        assert!(matches!(raced, Err(CliError::OutputExists { .. })));
+        assert_eq!(fs::read_to_string(&out).unwrap(), "other");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_file(&out).unwrap();
+        write_output(&out, false, |w| w.write_all(b"new").map_err(io_error)).unwrap();
+        assert_eq!(fs::read_to_string(&out).unwrap(), "new");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn streams_records_until_stopped() {
+        let dir = std::env::temp_dir().join(format!("ypbank-stream-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.csv").to_string_lossy().into_owned();
+        let mut data =
+            "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n"
+                .to_string();
+        for id in 1..=3 {
+            data += &format!("{id},DEPOSIT,0,1,100,1633036800000,SUCCESS,\"\"\n");
+        }
+        fs::write(&path, data).unwrap();
+
+        let global = GlobalOptions::default();
+        let options = FormatOptions::default();
+        let mut ids = Vec::new();
+        let files = [path.clone(), path.clone(), path];
+        global
+            .for_each_record(&files, None, &options, |_, record| {
+                ids.push(record.tx_id);
+                Ok(ids.len() < 4)
+            })
+            .unwrap();
+        assert_eq!(ids, [1, 2, 3, 1]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sets_input_and_output_options() {
+        let mut input = InputOptions::default();
+        input.set("files", "a.csv".to_string()).unwrap();
+        input.set("csv-delimiter", ";".to_string()).unwrap();
+        input.set("max-records", "5".to_string()).unwrap();
+        assert!(input.set("output-format", "csv".to_string()).is_err());
+        let options = input.read_options();
+        assert_eq!(input.files, ["a.csv"]);
+        assert_eq!(options.csv.dialect.delimiter, ';');
+        assert_eq!(options.txt.limits.max_records, 5);
+
+        let mut output = OutputOptions::default();
+        output.set("output-time-format", "s".to_string()).unwrap();
+        output
+            .set("output-amount-format", "decimal".to_string())
+            .unwrap();
+        output
+            .set("output-csv-header", "absent".to_string())
+            .unwrap();
+        assert!(output.set("csv-delimiter", ";".to_string()).is_err());
+        let options = output.write_options();
+        let records = crate::storage::sample_storage().into_records();
+        let mut writer = RecordWriter::new(Format::Csv, Vec::new(), &options);
+        writer.write_record(&records[0]).unwrap();
+        let csv = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert_eq!(
+            csv,
+            "1,TRANSFER,1,2,15.00 EUR,1700000000,PENDING,\"Перевод \"\"за обед\"\", кафе\",EUR,mobile\n"
+        );
+    }
+
+    #[test]
+    fn finds_error_format() {
+        let args = |list: &[&str]| -> Vec<String> { list.iter().map(|s| s.to_string()).collect() };
+        let format = ErrorFormat::from_args(&args(&["ypbank", "--error-format", "json", "cat"]));
+        assert_eq!(format, ErrorFormat::Json);
+        let format = ErrorFormat::from_args(&args(&["ypbank", "cat", "--error-format=json"]));
+        assert_eq!(format, ErrorFormat::Json);
+        let format = ErrorFormat::from_args(&args(&["ypbank", "--", "--error-format=json"]));
+        assert_eq!(format, ErrorFormat::Text);
+    }
+
+    #[test]
+    fn formats_errors_as_json() {
+        let error = CliError::Multiple(vec![
+            CliError::MissingArgument {
+                name: "--input".to_string(),
+            },
+            CliError::InvalidValue {
+                name: "--quote".to_string(),
+                value: "\"\t".to_string(),
+            },
+        ]);
+        assert_eq!(
+            error_json(&error),
+            r#"{"class":"usage","exit_code":2,"message":"2 errors:\n  - missing required argument: --input\n  - invalid value for argument --quote: \"\t","errors":["missing required argument: --input","invalid value for argument --quote: \"\t"]}"#
+        );
+    }
+
+    #[test]
+    fn applies_error_policy() {
+        let files = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let fail = |file: &str| match file {
+            "b" => Ok(()),
+            _ => Err(CliError::InvalidFormat {
+                name: file.to_string(),
+            }),
+        };
+
+        let mut global = GlobalOptions::default();
+        let mut seen = Vec::new();
+        let result = global.for_each_file(&files, |file| {
+            seen.push(file.to_string());
+            fail(file)
+        });
+        assert!(matches!(result, Err(CliError::InvalidFormat { name }) if name == "a"));
+        assert_eq!(seen, ["a"]);
+
+        global.on_error = ErrorPolicy::Continue;
+        let result = global.for_each_file(&files, fail);
+        assert!(matches!(result, Err(CliError::Multiple(errors)) if errors.len() == 2));
+    }
+}
@@ -0,0 +1,163 @@
+//! Reconciliation of the ledger with the bank and processor feeds.
+
+use super::{
+    GLOBAL_OPTIONS, GlobalOptions, Subcommand, io_error, parse_value, read_file, write_output,
+};
+use crate::cli::{CliConfig, Command, Opt, csv_read_options, set_csv_option};
+use crate::error::CliError;
+use crate::format::format_csv::CsvOptions;
+use crate::format::{FORMAT_NAMES, Format};
+use crate::reconcile::reconcile;
+use strum_macros::{Display, EnumString};
+
+/// Names of the reconciled sources, in report order
+pub const SOURCES: [&str; 3] = ["ledger", "bank", "processor"];
+
+/// Format of the discrepancy report
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum ReportFormat {
+    /// One discrepancy per line
+    #[default]
+    Csv,
+    /// A single JSON object
+    Json,
+}
+
+/// A reconciled source file
+#[derive(Default)]
+struct Source {
+    file: String,
+    format: Option<Format>,
+    csv: CsvOptions,
+}
+
+/// Options of the `reconcile` subcommand
+#[derive(Default)]
+pub struct ReconcileConfig {
+    global: GlobalOptions,
+    sources: [Source; 3],
+    output: String,
+    force: bool,
+    report_format: ReportFormat,
+}
+
+impl CliConfig for ReconcileConfig {
+    fn command() -> Command {
+        let mut command = Command::new(
+            "reconcile",
+            "Matches ledger, bank and processor records by TX_ID and reports discrepancies",
+        )
+        .opt(
+            Opt::value("ledger", "LEDGER", "Our ledger")
+                .required()
+                .positional(),
+        )
+        .opt(
+            Opt::value("bank", "BANK", "Bank feed")
+                .required()
+                .positional(),
+        )
+        .opt(
+            Opt::value("processor", "PROCESSOR", "Processor feed")
+                .required()
+                .positional(),
+        );
+        for source in SOURCES {
+            command = command.opt(
+                Opt::value(
+                    format!("{source}-format"),
+                    "FMT",
+                    "Format of the file; detected from the extension if omitted",
+                )
+                .choices(FORMAT_NAMES),
+            );
+        }
+        command = command
+            .opt(Opt::value("output", "FILE", "Report file; stdout if omitted or -").short('o'))
+            .opt(Opt::flag("force", "Overwrite the report file if it exists").short('f'))
+            .opt(
+                Opt::value("report-format", "FMT", "Format of the report")
+                    .choices(&["csv", "json"])
+                    .default("csv"),
+            );
+        for source in SOURCES {
+            command = command.opts(csv_read_options(&format!("{source}-csv-")));
+        }
+        command.opts(GlobalOptions::options())
+    }
+
+    fn set_arg(&mut self, flag: &str, value: String) -> Result<(), CliError> {
+        match flag {
+            "output" => self.output = value.clone(),
+            "force" => self.force = parse_value(flag, value)?,
+            "report-format" => self.report_format = parse_value(flag, value)?,
+            _ if GLOBAL_OPTIONS.contains(&flag) => self.global.set(flag, value)?,
+            _ => {
+                let (index, option) = SOURCES
+                    .iter()
+                    .enumerate()
+                    .find_map(|(i, name)| Some((i, flag.strip_prefix(name)?)))
+                    .ok_or_else(|| CliError::UnknownArgument {
+                        name: format!("--{}", flag),
+                    })?;
+                let source = &mut self.sources[index];
+                match option {
+                    "" => source.file = value,
+                    "-format" => source.format = Some(parse_value(flag, value)?),
+                    _ if option.starts_with("-csv-") => {
+                        set_csv_option(&mut source.csv, flag, &option["-csv-".len()..], value)?
+                    }
+                    _ => {
+                        return Err(CliError::UnknownArgument {
+                            name: format!("--{}", flag),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Subcommand for ReconcileConfig {
+    fn run(self) -> Result<(), CliError> {
+        let mut storages = Vec::new();
+        for (name, source) in SOURCES.iter().zip(&self.sources) {
+            let format = self.global.resolve_format(
+                source.format,
+                &source.file,
+                &format!("{name}-format"),
+            )?;
+            let options = self.global.read_options(&source.csv);
+            let storage = read_file(&source.file, format, &options)?;
+            self.global.info(&format!(
+                "read {} records from {} '{}'",
+                storage.records().len(),
+                name,
+                source.file
+            ));
+            storages.push(storage);
+        }
+
+        let named: Vec<(&str, _)> = SOURCES.iter().copied().zip(&storages).collect();
+        let report = reconcile(&named);
+        self.global.info(&format!(
+            "{} transactions agree, {} discrepancies",
+            report.matched,
+            report.discrepancies.len()
+        ));
+        write_output(&self.output, self.force, |mut w| {
+            match self.report_format {
+                ReportFormat::Csv => report.write_csv(&mut w),
+                ReportFormat::Json => report.write_json(&mut w),
+            }
+            .map_err(io_error)
+        })?;
+
+        match report.discrepancies.len() {
+            0 => Ok(()),
+            count => Err(CliError::Discrepancies { count }),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! Local HTTP service, see [`crate::server`].
+
+use super::{GLOBAL_OPTIONS, GlobalOptions, Subcommand, parse_value};
+use crate::cli::{CliConfig, Command, Opt};
+use crate::error::CliError;
+use crate::server::{Server, ServerOptions};
+
+/// Options of the `serve` subcommand
+#[derive(Default)]
+pub struct ServeConfig {
+    global: GlobalOptions,
+    listen: String,
+    workers: usize,
+    max_body_size: u64,
+}
+
+impl CliConfig for ServeConfig {
+    fn command() -> Command {
+        Command::new("serve", "Serves conversion, validation and stats over HTTP")
+            .opt(Opt::value("listen", "ADDR", "Address to listen on").default("127.0.0.1:8080"))
+            .opt(Opt::value("workers", "N", "Number of requests served at once").default("4"))
+            // 64 MiB, server::DEFAULT_MAX_BODY_SIZE
+            .opt(Opt::value("max-body-size", "BYTES", "Largest request body").default("67108864"))
+            .opts(GlobalOptions::options())
+    }
+
+    fn set_arg(&mut self, flag: &str, value: String) -> Result<(), CliError> {
+        match flag {
+            "listen" => self.listen = value,
+            "workers" => self.workers = parse_value(flag, value)?,
+            "max-body-size" => self.max_body_size = parse_value(flag, value)?,
+            _ if GLOBAL_OPTIONS.contains(&flag) => self.global.set(flag, value)?,
+            _ => {
+                return Err(CliError::UnknownArgument {
+                    name: format!("--{}", flag),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Subcommand for ServeConfig {
+    fn run(self) -> Result<(), CliError> {
+        let server = Server::bind(
+            &self.listen,
+            ServerOptions {
+                limits: self.global.limits,
+                max_body_size: self.max_body_size,
+                workers: self.workers,
+                verbose: self.global.verbose,
+            },
+        )?;
+        self.global
+            .say(&format!("listening on http://{}", server.local_addr()));
+        server.run();
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+//! Summary statistics of record files.
+
+use super::{
+    GLOBAL_OPTIONS, GlobalOptions, Subcommand, in_file, io_error, open_records, parse_value,
+};
+use crate::cli::{CliConfig, Command, Opt, csv_read_options, set_csv_option};
+use crate::error::CliError;
+use crate::format::format_csv::CsvOptions;
+use crate::format::{FORMAT_NAMES, Format};
+use crate::json;
+use crate::storage::YPBankRecord;
+use crate::timestamp::Timestamp;
+use chrono::FixedOffset;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+
+/// Options of the `stats` subcommand
+#[derive(Default)]
+pub struct StatsConfig {
+    global: GlobalOptions,
+    files: Vec<String>,
+    format: Option<Format>,
+    csv: CsvOptions,
+}
+
+/// Counts and totals over a set of records
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    /// Number of records
+    pub records: usize,
+    /// Number of records per transaction type
+    pub by_type: BTreeMap<String, usize>,
+    /// Number of records per status
+    pub by_status: BTreeMap<String, usize>,
+    /// Sum of amounts in minor units per currency code; `-` if unknown
+    pub totals: BTreeMap<String, u128>,
+    /// Earliest timestamp
+    pub first: Option<Timestamp>,
+    /// Latest timestamp
+    pub last: Option<Timestamp>,
+}
+
+impl Stats {
+    /// Adds a record to the statistics
+    pub fn add(&mut self, record: &YPBankRecord) {
+        self.records += 1;
+        *self.by_type.entry(record.tx_type.to_string()).or_default() += 1;
+        *self.by_status.entry(record.status.to_string()).or_default() += 1;
+        let currency = record.currency.map_or("-", |c| c.code());
+        *self.totals.entry(currency.to_string()).or_default() += u128::from(record.amount);
+        self.first = Some(
+            self.first
+                .map_or(record.timestamp, |t| t.min(record.timestamp)),
+        );
+        self.last = Some(
+            self.last
+                .map_or(record.timestamp, |t| t.max(record.timestamp)),
+        );
+    }
+
+    /// Writes the statistics as a JSON object
+    ///
+    /// The maps are objects keyed by type, status and currency code; `first`
+    /// and `last` are ISO-8601 strings in UTC, or `null` without records.
+    pub fn write_json<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let utc = FixedOffset::east_opt(0).unwrap();
+        let time = |t: Option<Timestamp>| {
+            t.and_then(|t| t.to_iso8601(utc))
+                .map_or("null".to_string(), |t| json::string(&t))
+        };
+        writeln!(
+            w,
+            "{{\"records\":{},\"by_type\":{},\"by_status\":{},\"totals\":{},\"first\":{},\"last\":{}}}",
+            self.records,
+            json_object(&self.by_type),
+            json_object(&self.by_status),
+            json_object(&self.totals),
+            time(self.first),
+            time(self.last)
+        )
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "records: {}", self.records)?;
+        for (tx_type, count) in &self.by_type {
+            writeln!(f, "type {}: {}", tx_type, count)?;
+        }
+        for (status, count) in &self.by_status {
+            writeln!(f, "status {}: {}", status, count)?;
+        }
+        for (currency, total) in &self.totals {
+            writeln!(f, "total {}: {}", currency, total)?;
+        }
+        let utc = FixedOffset::east_opt(0).unwrap();
+        if let (Some(first), Some(last)) = (self.first, self.last) {
+            let format = |t: Timestamp| t.to_iso8601(utc).unwrap_or_default();
+            writeln!(f, "first: {}", format(first))?;
+            writeln!(f, "last: {}", format(last))?;
+        }
+        Ok(())
+    }
+}
+
+fn json_object<V: fmt::Display>(map: &BTreeMap<String, V>) -> String {
+    let fields: Vec<String> = map
+        .iter()
+        .map(|(key, value)| format!("{}:{}", json::string(key), value))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+impl CliConfig for StatsConfig {
+    fn command() -> Command {
+        Command::new("stats", "Prints counts and totals of the records in files")
+            .opt(
+                Opt::value("files", "FILE", "Input files or glob patterns; - for stdin")
+                    .required()
+                    .positional()
+                    .multiple(),
+            )
+            .opt(
+                Opt::value(
+                    "format",
+                    "FMT",
+                    "Input format; detected from the extension if omitted",
+                )
+                .choices(FORMAT_NAMES),
+            )
+            .opts(csv_read_options("csv-"))
+            .opts(GlobalOptions::options())
+    }
+
+    fn set_arg(&mut self, flag: &str, value: String) -> Result<(), CliError> {
+        match flag {
+            "files" => self.files.push(value),
+            "format" => self.format = Some(parse_value(flag, value)?),
+            _ if flag.starts_with("csv-") => {
+                set_csv_option(&mut self.csv, flag, &flag["csv-".len()..], value)?
+            }
+            _ if GLOBAL_OPTIONS.contains(&flag) => self.global.set(flag, value)?,
+            _ => {
+                return Err(CliError::UnknownArgument {
+                    name: format!("--{}", flag),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Subcommand for StatsConfig {
+    fn run(self) -> Result<(), CliError> {
+        let options = self.global.read_options(&self.csv);
+        let mut stats = Stats::default();
+        let result = self.global.for_each_file(&self.files, |file| {
+            let format = self.global.resolve_format(self.format, file, "format")?;
+            // A file that fails part way adds none of its records
+            let mut with_file = stats.clone();
+            for record in open_records(file, format, &options)? {
+                with_file.add(&record.map_err(|e| in_file(file, e.into()))?);
+            }
+            self.global.info(&format!(
+                "read {} records from '{}'",
+                with_file.records - stats.records,
+                file
+            ));
+            stats = with_file;
+            Ok(())
+        });
+        write!(io::stdout(), "{}", stats).map_err(io_error)?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::currency::Currency;
+    use crate::storage::{ExtraFields, YPBankRecordStatus, YPBankRecordType};
+
+    fn record(
+        tx_type: YPBankRecordType,
+        amount: u64,
+        currency: Option<&str>,
+        ms: u64,
+    ) -> YPBankRecord {
+        YPBankRecord {
+            tx_id: 1,
+            tx_type,
+            from_user_id: 0,
+            to_user_id: 1,
+            amount,
+            currency: currency.and_then(Currency::from_code),
+            timestamp: Timestamp::from_millis(ms),
+            status: YPBankRecordStatus::SUCCESS,
+            description: "test".to_string(),
+            extra: ExtraFields::new(),
+        }
+    }
+
+    #[test]
+    fn collects_counts_and_totals() {
+        let mut stats = Stats::default();
+        stats.add(&record(YPBankRecordType::DEPOSIT, 100, Some("EUR"), 2000));
+        stats.add(&record(YPBankRecordType::DEPOSIT, 50, Some("EUR"), 1000));
+        stats.add(&record(YPBankRecordType::WITHDRAWAL, 7, None, 3000));
+
+        assert_eq!(stats.records, 3);
+        assert_eq!(stats.by_type["DEPOSIT"], 2);
+        assert_eq!(stats.by_type["WITHDRAWAL"], 1);
+        assert_eq!(stats.by_status["SUCCESS"], 3);
+        assert_eq!(stats.totals["EUR"], 150);
+        assert_eq!(stats.totals["-"], 7);
+        assert_eq!(stats.first, Some(Timestamp::from_millis(1000)));
+        assert_eq!(stats.last, Some(Timestamp::from_millis(3000)));
+
+        let text = stats.to_string();
+        assert!(text.contains("records: 3\n"));
+        assert!(text.contains("first: 1970-01-01T00:00:01Z\n"));
+
+        let mut json = Vec::new();
+        stats.write_json(&mut json).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "{\"records\":3,\"by_type\":{\"DEPOSIT\":2,\"WITHDRAWAL\":1},\
+             \"by_status\":{\"SUCCESS\":3},\"totals\":{\"-\":7,\"EUR\":150},\
+             \"first\":\"1970-01-01T00:00:01Z\",\"last\":\"1970-01-01T00:00:03Z\"}\n"
+        );
+    }
+}
@@ -0,0 +1,62 @@
+//! Printing of the last records of files.
+
+use super::{GlobalOptions, InputOptions, OutputOptions, Subcommand, parse_value};
+use crate::cli::{CliConfig, Command, Opt, ValueKind};
+use crate::error::CliError;
+use std::collections::VecDeque;
+
+/// Records reserved up front, so a huge `--lines` is not allocated at once
+const MAX_CAPACITY: usize = 1024;
+
+/// Options of the `tail` subcommand
+#[derive(Default)]
+pub struct TailConfig {
+    input: InputOptions,
+    output: OutputOptions,
+    lines: usize,
+}
+
+impl CliConfig for TailConfig {
+    fn command() -> Command {
+        Command::new("tail", "Prints the last records of files")
+            .opts(InputOptions::options())
+            .opts(OutputOptions::options())
+            .opt(
+                Opt::value("lines", "N", "Number of records to print")
+                    .short('n')
+                    .default("10")
+                    .kind(ValueKind::Integer),
+            )
+            .opts(GlobalOptions::options())
+    }
+
+    fn set_arg(&mut self, flag: &str, value: String) -> Result<(), CliError> {
+        match flag {
+            "lines" => self.lines = parse_value(flag, value)?,
+            _ if flag.starts_with("output-") => self.output.set(flag, value)?,
+            _ => self.input.set(flag, value)?,
+        }
+        Ok(())
+    }
+}
+
+impl Subcommand for TailConfig {
+    fn run(self) -> Result<(), CliError> {
+        let mut last = VecDeque::with_capacity(self.lines.min(MAX_CAPACITY));
+        let result = self.input.for_each_record(|_, record| {
+            if self.lines > 0 {
+                if last.len() == self.lines {
+                    last.pop_front();
+                }
+                last.push_back(record);
+            }
+            Ok(true)
+        });
+        let mut out = self.output.stdout_records();
+        for record in &last {
+            out.write_record(record)?;
+        }
+        out.finish()?;
+        result
+    }
+}
@@ -0,0 +1,85 @@
+//! Validation of record files.
+
+use super::{GLOBAL_OPTIONS, GlobalOptions, Subcommand, in_file, open_records, parse_value};
+use crate::cli::{CliConfig, Command, Opt, csv_read_options, set_csv_option};
+use crate::error::{CliError, ErrorClass};
+use crate::format::format_csv::CsvOptions;
+use crate::format::{FORMAT_NAMES, Format};
+
+/// Options of the `validate` subcommand
+#[derive(Default)]
+pub struct ValidateConfig {
+    global: GlobalOptions,
+    files: Vec<String>,
+    format: Option<Format>,
+    csv: CsvOptions,
+}
+
+impl CliConfig for ValidateConfig {
+    fn command() -> Command {
+        Command::new("validate", "Checks that files contain only valid records")
+            .opt(
+                Opt::value("files", "FILE", "Input files or glob patterns; - for stdin")
+                    .required()
+                    .positional()
+                    .multiple(),
+            )
+            .opt(
+                Opt::value(
+                    "format",
+                    "FMT",
+                    "Input format; detected from the extension if omitted",
+                )
+                .choices(FORMAT_NAMES),
+            )
+            .opts(csv_read_options("csv-"))
+            .opts(GlobalOptions::options())
+    }
+
+    fn set_arg(&mut self, flag: &str, value: String) -> Result<(), CliError> {
+        match flag {
+            "files" => self.files.push(value),
+            "format" => self.format = Some(parse_value(flag, value)?),
+            _ if flag.starts_with("csv-") => {
+                set_csv_option(&mut self.csv, flag, &flag["csv-".len()..], value)?
+            }
+            _ if GLOBAL_OPTIONS.contains(&flag) => self.global.set(flag, value)?,
+            _ => {
+                return Err(CliError::UnknownArgument {
+                    name: format!("--{}", flag),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Subcommand for ValidateConfig {
+    fn run(self) -> Result<(), CliError> {
+        let options = self.global.read_options(&self.csv);
+        let (mut invalid, mut total) = (0, 0);
+        self.global.for_each_file(&self.files, |file| {
+            total += 1;
+            let format = self.global.resolve_format(self.format, file, "format")?;
+            let count = open_records(file, format, &options).and_then(|mut records| {
+                records
+                    .try_fold(0, |n, record| record.map(|_| n + 1))
+                    .map_err(|e| in_file(file, e.into()))
+            });
+            match count {
+                Ok(count) => self.global.say(&format!("{}: OK, {} records", file, count)),
+                // Invalid records are the finding of the check, not a failure
+                Err(e) if e.class() == ErrorClass::Parse => {
+                    invalid += 1;
+                    self.global.say(&format!("INVALID: {}", e));
+                }
+                Err(e) => return Err(e),
+            }
+            Ok(())
+        })?;
+        match invalid {
+            0 => Ok(()),
+            _ => Err(CliError::Validation { invalid, total }),
+        }
+    }
+}
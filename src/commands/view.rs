@@ -0,0 +1,77 @@
+//! Interactive viewer of a record file, see [`crate::viewer`].
+
+use super::{GLOBAL_OPTIONS, GlobalOptions, STDIO, Subcommand, io_error, parse_value};
+use crate::cli::{CliConfig, Command, Opt, csv_read_options, set_csv_option};
+use crate::error::CliError;
+use crate::format::format_csv::CsvOptions;
+use crate::format::{FORMAT_NAMES, Format};
+use crate::viewer::Viewer;
+use std::fs::File;
+use std::io;
+
+/// Options of the `view` subcommand
+#[derive(Default)]
+pub struct ViewConfig {
+    global: GlobalOptions,
+    file: String,
+    format: Option<Format>,
+    csv: CsvOptions,
+}
+
+impl CliConfig for ViewConfig {
+    fn command() -> Command {
+        Command::new("view", "Browses the records of a file in the terminal")
+            .opt(
+                Opt::value("file", "FILE", "Input file; - for stdin")
+                    .required()
+                    .positional(),
+            )
+            .opt(
+                Opt::value(
+                    "format",
+                    "FMT",
+                    "Input format; detected from the extension if omitted",
+                )
+                .choices(FORMAT_NAMES),
+            )
+            .opts(csv_read_options("csv-"))
+            .opts(GlobalOptions::options())
+    }
+
+    fn set_arg(&mut self, flag: &str, value: String) -> Result<(), CliError> {
+        match flag {
+            "file" => self.file = value,
+            "format" => self.format = Some(parse_value(flag, value)?),
+            _ if flag.starts_with("csv-") => {
+                set_csv_option(&mut self.csv, flag, &flag["csv-".len()..], value)?
+            }
+            _ if GLOBAL_OPTIONS.contains(&flag) => self.global.set(flag, value)?,
+            _ => {
+                return Err(CliError::UnknownArgument {
+                    name: format!("--{}", flag),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Subcommand for ViewConfig {
+    fn run(self) -> Result<(), CliError> {
+        let format = self
+            .global
+            .resolve_format(self.format, &self.file, "format")?;
+        let options = self.global.read_options(&self.csv);
+        let viewer = match self.file.as_str() {
+            STDIO => Viewer::new("stdin", format, io::stdin(), &options),
+            path => {
+                let file = File::open(path).map_err(|e| CliError::File {
+                    path: path.to_string(),
+                    error: Box::new(io_error(e)),
+                })?;
+                Viewer::new(path, format, file, &options)
+            }
+        };
+        viewer.run().map_err(io_error)
+    }
+}
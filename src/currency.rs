@@ -0,0 +1,413 @@
+//! ISO 4217 currencies and decimal rendering of amounts
+
+use crate::error::ParserError;
+use std::fmt;
+use std::str::FromStr;
+use strum_macros::{Display, EnumString};
+
+/// ISO 4217 alphabetic codes with their minor-unit exponents, sorted by code
+const CURRENCIES: &[(&str, u8)] = &[
+    ("AED", 2),
+    ("AFN", 2),
+    ("ALL", 2),
+    ("AMD", 2),
+    ("ANG", 2),
+    ("AOA", 2),
+    ("ARS", 2),
+    ("AUD", 2),
+    ("AWG", 2),
+    ("AZN", 2),
+    ("BAM", 2),
+    ("BBD", 2),
+    ("BDT", 2),
+    ("BGN", 2),
+    ("BHD", 3),
+    ("BIF", 0),
+    ("BMD", 2),
+    ("BND", 2),
+    ("BOB", 2),
+    ("BOV", 2),
+    ("BRL", 2),
+    ("BSD", 2),
+    ("BTN", 2),
+    ("BWP", 2),
+    ("BYN", 2),
+    ("BZD", 2),
+    ("CAD", 2),
+    ("CDF", 2),
+    ("CHE", 2),
+    ("CHF", 2),
+    ("CHW", 2),
+    ("CLF", 4),
+    ("CLP", 0),
+    ("CNY", 2),
+    ("COP", 2),
+    ("COU", 2),
+    ("CRC", 2),
+    ("CUP", 2),
+    ("CVE", 2),
+    ("CZK", 2),
+    ("DJF", 0),
+    ("DKK", 2),
+    ("DOP", 2),
+    ("DZD", 2),
+    ("EGP", 2),
+    ("ERN", 2),
+    ("ETB", 2),
+    ("EUR", 2),
+    ("FJD", 2),
+    ("FKP", 2),
+    ("GBP", 2),
+    ("GEL", 2),
+    ("GHS", 2),
+    ("GIP", 2),
+    ("GMD", 2),
+    ("GNF", 0),
+    ("GTQ", 2),
+    ("GYD", 2),
+    ("HKD", 2),
+    ("HNL", 2),
+    ("HTG", 2),
+    ("HUF", 2),
+    ("IDR", 2),
+    ("ILS", 2),
+    ("INR", 2),
+    ("IQD", 3),
+    ("IRR", 2),
+    ("ISK", 0),
+    ("JMD", 2),
+    ("JOD", 3),
+    ("JPY", 0),
+    ("KES", 2),
+    ("KGS", 2),
+    ("KHR", 2),
+    ("KMF", 0),
+    ("KPW", 2),
+    ("KRW", 0),
+    ("KWD", 3),
+    ("KYD", 2),
+    ("KZT", 2),
+    ("LAK", 2),
+    ("LBP", 2),
+    ("LKR", 2),
+    ("LRD", 2),
+    ("LSL", 2),
+    ("LYD", 3),
+    ("MAD", 2),
+    ("MDL", 2),
+    ("MGA", 2),
+    ("MKD", 2),
+    ("MMK", 2),
+    ("MNT", 2),
+    ("MOP", 2),
+    ("MRU", 2),
+    ("MUR", 2),
+    ("MVR", 2),
+    ("MWK", 2),
+    ("MXN", 2),
+    ("MXV", 2),
+    ("MYR", 2),
+    ("MZN", 2),
+    ("NAD", 2),
+    ("NGN", 2),
+    ("NIO", 2),
+    ("NOK", 2),
+    ("NPR", 2),
+    ("NZD", 2),
+    ("OMR", 3),
+    ("PAB", 2),
+    ("PEN", 2),
+    ("PGK", 2),
+    ("PHP", 2),
+    ("PKR", 2),
+    ("PLN", 2),
+    ("PYG", 0),
+    ("QAR", 2),
+    ("RON", 2),
+    ("RSD", 2),
+    ("RUB", 2),
+    ("RWF", 0),
+    ("SAR", 2),
+    ("SBD", 2),
+    ("SCR", 2),
+    ("SDG", 2),
+    ("SEK", 2),
+    ("SGD", 2),
+    ("SHP", 2),
+    ("SLE", 2),
+    ("SOS", 2),
+    ("SRD", 2),
+    ("SSP", 2),
+    ("STN", 2),
+    ("SVC", 2),
+    ("SYP", 2),
+    ("SZL", 2),
+    ("THB", 2),
+    ("TJS", 2),
+    ("TMT", 2),
+    ("TND", 3),
+    ("TOP", 2),
+    ("TRY", 2),
+    ("TTD", 2),
+    ("TWD", 2),
+    ("TZS", 2),
+    ("UAH", 2),
+    ("UGX", 0),
+    ("USD", 2),
+    ("USN", 2),
+    ("UYI", 0),
+    ("UYU", 2),
+    ("UYW", 4),
+    ("UZS", 2),
+    ("VED", 2),
+    ("VES", 2),
+    ("VND", 0),
+    ("VUV", 0),
+    ("WST", 2),
+    ("XAF", 0),
+    ("XCD", 2),
+    ("XCG", 2),
+    ("XOF", 0),
+    ("XPF", 0),
+    ("YER", 2),
+    ("ZAR", 2),
+    ("ZMW", 2),
+    ("ZWG", 2),
+];
+
+/// An ISO 4217 currency
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Currency {
+    code: &'static str,
+    exponent: u8,
+}
+
+impl Currency {
+    /// Looks up a currency by its alphabetic code, e.g. `EUR`
+    pub fn from_code(code: &str) -> Option<Self> {
+        CURRENCIES
+            .binary_search_by(|(c, _)| (*c).cmp(code))
+            .ok()
+            .map(|i| Self {
+                code: CURRENCIES[i].0,
+                exponent: CURRENCIES[i].1,
+            })
+    }
+
+    /// Alphabetic currency code
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+
+    /// Number of decimal digits of the minor unit
+    pub fn exponent(&self) -> u32 {
+        self.exponent as u32
+    }
+
+    /// Renders an amount in minor units as a decimal, e.g. `12345` as `123.45`
+    pub fn format_decimal(&self, minor: u64) -> String {
+        let exponent = self.exponent as usize;
+        if exponent == 0 {
+            return minor.to_string();
+        }
+        let digits = format!("{:0>width$}", minor, width = exponent + 1);
+        let (int, frac) = digits.split_at(digits.len() - exponent);
+        format!("{}.{}", int, frac)
+    }
+
+    /// Parses a decimal such as `123.45` into minor units without rounding
+    pub fn parse_decimal(&self, s: &str) -> Option<u64> {
+        let exponent = self.exponent as usize;
+        let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+        let is_digits = |p: &str| p.bytes().all(|b| b.is_ascii_digit());
+        if int.is_empty()
+            || s.ends_with('.')
+            || !is_digits(int)
+            || !is_digits(frac)
+            || frac.len() > exponent
+        {
+            return None;
+        }
+        let scale = 10u64.checked_pow(exponent as u32)?;
+        let frac_scale = 10u64.pow((exponent - frac.len()) as u32);
+        let frac = if frac.is_empty() {
+            0
+        } else {
+            frac.parse::<u64>().ok()?
+        };
+        int.parse::<u64>()
+            .ok()?
+            .checked_mul(scale)?
+            .checked_add(frac * frac_scale)
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code)
+    }
+}
+
+impl FromStr for Currency {
+    type Err = strum::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_code(s).ok_or(strum::ParseError::VariantNotFound)
+    }
+}
+
+/// Serialized as the alphabetic code
+#[cfg(feature = "serde")]
+impl serde::Serialize for Currency {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Currency {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        Self::from_code(&code).ok_or_else(|| {
+            serde::de::Error::invalid_value(
+                serde::de::Unexpected::Str(&code),
+                &"an ISO 4217 currency code",
+            )
+        })
+    }
+}
+
+/// Textual representation of amounts in the CSV and TXT formats
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum AmountFormat {
+    /// Integer count of minor units, e.g. `12345`
+    #[default]
+    Minor,
+    /// Decimal followed by the currency code, e.g. `123.45 EUR`
+    Decimal,
+}
+
+/// Parses an `AMOUNT` field into minor units and its currency.
+///
+/// `currency` is the currency known for the record from elsewhere; a
+/// decimal amount may name its own, which must agree with it.
+pub fn parse_amount(
+    s: &str,
+    format: AmountFormat,
+    currency: Option<Currency>,
+) -> Result<(u64, Option<Currency>), ParserError> {
+    match format {
+        AmountFormat::Minor => {
+            let amount = s
+                .parse::<u64>()
+                .map_err(|_| invalid_record("invalid AMOUNT"))?;
+            Ok((amount, currency))
+        }
+        AmountFormat::Decimal => {
+            let (value, code) = match s.split_once(' ') {
+                Some((value, code)) => (value, Some(code.trim())),
+                None => (s, None),
+            };
+            let currency = match (code.map(Currency::from_code), currency) {
+                (Some(None), _) => return Err(invalid_record("invalid AMOUNT currency")),
+                (Some(Some(own)), Some(other)) if own != other => {
+                    return Err(invalid_record("AMOUNT currency does not match CURRENCY"));
+                }
+                (Some(Some(own)), _) => own,
+                (None, Some(other)) => other,
+                (None, None) => return Err(invalid_record("AMOUNT has no currency")),
+            };
+            let amount = currency
+                .parse_decimal(value)
+                .ok_or_else(|| invalid_record("invalid AMOUNT"))?;
+            Ok((amount, Some(currency)))
+        }
+    }
+}
+
+/// Renders an amount in minor units in the given format
+pub fn format_amount(
+    amount: u64,
+    format: AmountFormat,
+    currency: Option<Currency>,
+) -> Result<String, ParserError> {
+    match format {
+        AmountFormat::Minor => Ok(amount.to_string()),
+        AmountFormat::Decimal => {
+            let currency = currency.ok_or_else(|| invalid_record("AMOUNT has no currency"))?;
+            Ok(format!("{} {}", currency.format_decimal(amount), currency))
+        }
+    }
+}
+
+fn invalid_record(msg: &str) -> ParserError {
+    ParserError::InvalidRecord {
+        message: msg.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn currency(code: &str) -> Currency {
+        Currency::from_code(code).unwrap()
+    }
+
+    #[test]
+    fn test_table_is_sorted() {
+        assert!(CURRENCIES.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn test_exponents() {
+        assert_eq!(currency("EUR").exponent(), 2);
+        assert_eq!(currency("JPY").exponent(), 0);
+        assert_eq!(currency("KWD").exponent(), 3);
+        assert_eq!(Currency::from_code("XYZ"), None);
+    }
+
+    #[test]
+    fn test_format_decimal() {
+        assert_eq!(currency("EUR").format_decimal(12345), "123.45");
+        assert_eq!(currency("EUR").format_decimal(5), "0.05");
+        assert_eq!(currency("JPY").format_decimal(500), "500");
+        assert_eq!(currency("KWD").format_decimal(1000), "1.000");
+    }
+
+    #[test]
+    fn test_parse_decimal() {
+        assert_eq!(currency("EUR").parse_decimal("123.45"), Some(12345));
+        assert_eq!(currency("EUR").parse_decimal("123.4"), Some(12340));
+        assert_eq!(currency("EUR").parse_decimal("123"), Some(12300));
+        assert_eq!(currency("EUR").parse_decimal("0.001"), None);
+        assert_eq!(currency("EUR").parse_decimal("1."), None);
+        assert_eq!(currency("EUR").parse_decimal("-1.00"), None);
+        assert_eq!(currency("JPY").parse_decimal("1.0"), None);
+        assert_eq!(
+            currency("EUR").parse_decimal("184467440737095516.15"),
+            Some(u64::MAX)
+        );
+        assert_eq!(currency("EUR").parse_decimal("184467440737095516.16"), None);
+    }
+
+    #[test]
+    fn test_parse_amount() {
+        let eur = Some(currency("EUR"));
+        assert_eq!(
+            parse_amount("123.45 EUR", AmountFormat::Decimal, None).unwrap(),
+            (12345, eur)
+        );
+        assert_eq!(
+            parse_amount("123.45", AmountFormat::Decimal, eur).unwrap(),
+            (12345, eur)
+        );
+        assert!(parse_amount("123.45", AmountFormat::Decimal, None).is_err());
+        assert!(parse_amount("123.45 USD", AmountFormat::Decimal, eur).is_err());
+        assert_eq!(
+            parse_amount("12345", AmountFormat::Minor, eur).unwrap(),
+            (12345, eur)
+        );
+    }
+}
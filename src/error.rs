@@ -1,6 +1,7 @@
 //! Error types used throughout the parser library
 
-use std::io::Error as IoError;
+use std::io::{Error as IoError, ErrorKind};
+use strum_macros::Display;
 use thiserror::Error;
 
 /// Error types for CLI operations
@@ -34,6 +35,15 @@ pub enum CliError {
         name: String,
     },
 
+    /// An argument was provided with a value that cannot be used
+    #[error("invalid value for argument {name}: {value}")]
+    InvalidValue {
+        /// The name of the argument
+        name: String,
+        /// The rejected value
+        value: String,
+    },
+
     /// An I/O error occurred during CLI processing
     #[error("I/O error: {message}")]
     IO {
@@ -43,11 +53,123 @@ pub enum CliError {
         error: IoError,
     },
 
+    /// The output file exists and overwriting it was not allowed
+    #[error("output file already exists: {path} (use --force to overwrite)")]
+    OutputExists {
+        /// Path of the output file
+        path: String,
+    },
+
+    /// An error that occurred while processing a file
+    #[error("{path}: {error}")]
+    File {
+        /// Path of the file
+        path: String,
+        /// The error itself
+        #[source]
+        error: Box<CliError>,
+    },
+
+    /// The compared files hold different records
+    #[error("the transaction records in '{file1}' and '{file2}' differ")]
+    Differences {
+        /// First compared file
+        file1: String,
+        /// Second compared file
+        file2: String,
+    },
+
+    /// Reconciled sources disagree on some transactions
+    #[error("{count} discrepancies found")]
+    Discrepancies {
+        /// Number of discrepancies
+        count: usize,
+    },
+
+    /// Some of the validated files contain invalid records
+    #[error("{invalid} of {total} files are invalid")]
+    Validation {
+        /// Number of invalid files
+        invalid: usize,
+        /// Number of checked files
+        total: usize,
+    },
+
+    /// Several errors found at once
+    #[error("{}", join_errors(.0))]
+    Multiple(Vec<CliError>),
+
     /// A parser error propagated from the parsing stage
-    #[error("parser error")]
+    #[error("parser error: {0}")]
     Parser(#[from] ParserError),
 }
 
+impl CliError {
+    /// Class of the error, which determines the exit code
+    ///
+    /// Several errors at once take the class of the first one.
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            CliError::UnknownArgument { .. }
+            | CliError::MissingArgument { .. }
+            | CliError::MissingValue { .. }
+            | CliError::InvalidFormat { .. }
+            | CliError::InvalidValue { .. } => ErrorClass::Usage,
+            CliError::IO { .. }
+            | CliError::OutputExists { .. }
+            | CliError::Parser(ParserError::IO { .. }) => ErrorClass::Io,
+            CliError::Parser(_) => ErrorClass::Parse,
+            CliError::Differences { .. } | CliError::Discrepancies { .. } => {
+                ErrorClass::Differences
+            }
+            CliError::Validation { .. } => ErrorClass::Validation,
+            CliError::File { error, .. } => error.class(),
+            CliError::Multiple(errors) => errors
+                .first()
+                .map_or(ErrorClass::Usage, |error| error.class()),
+        }
+    }
+
+    /// Whether the error is a write to a closed pipe, e.g. stdout piped into `head`
+    pub fn is_broken_pipe(&self) -> bool {
+        match self {
+            CliError::IO { error, .. } | CliError::Parser(ParserError::IO { error, .. }) => {
+                error.kind() == ErrorKind::BrokenPipe
+            }
+            CliError::File { error, .. } => error.is_broken_pipe(),
+            _ => false,
+        }
+    }
+}
+
+fn join_errors(errors: &[CliError]) -> String {
+    let lines: Vec<String> = errors.iter().map(|e| format!("\n  - {}", e)).collect();
+    format!("{} errors:{}", errors.len(), lines.concat())
+}
+
+/// Class of a [`CliError`], reported as the exit code of the binaries
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum ErrorClass {
+    /// Compared files or reconciled sources differ
+    Differences = 1,
+    /// Invalid command line
+    Usage = 2,
+    /// A file could not be read or written
+    Io = 3,
+    /// Input records could not be parsed
+    Parse = 4,
+    /// Validated files contain invalid records
+    Validation = 5,
+}
+
+impl ErrorClass {
+    /// Exit code of the binaries for this class
+    pub fn exit_code(self) -> u8 {
+        self as u8
+    }
+}
+
 /// Error types for parser operations
 #[derive(Error, Debug)]
 pub enum ParserError {
@@ -58,6 +180,67 @@ pub enum ParserError {
         message: String,
     },
 
+    /// A record is larger than [`ParseLimits::max_record_size`](crate::parser::ParseLimits)
+    #[error("record of {size} bytes exceeds the limit of {max} bytes")]
+    RecordTooLarge {
+        /// Size of the record in bytes
+        size: usize,
+        /// The limit
+        max: usize,
+    },
+
+    /// A description is longer than [`ParseLimits::max_description_len`](crate::parser::ParseLimits)
+    #[error("DESCRIPTION of {len} bytes exceeds the limit of {max} bytes")]
+    DescriptionTooLong {
+        /// Length of the description in bytes
+        len: usize,
+        /// The limit
+        max: usize,
+    },
+
+    /// A line is longer than [`ParseLimits::max_line_len`](crate::parser::ParseLimits)
+    #[error("line exceeds the limit of {max} bytes")]
+    LineTooLong {
+        /// The limit
+        max: usize,
+    },
+
+    /// The input holds more than [`ParseLimits::max_records`](crate::parser::ParseLimits) records
+    #[error("input exceeds the limit of {max} records")]
+    TooManyRecords {
+        /// The limit
+        max: usize,
+    },
+
+    /// A journal segment is damaged before its last record
+    #[error("corrupt journal segment {path} at offset {offset}: {message}")]
+    CorruptJournal {
+        /// Path of the segment
+        path: String,
+        /// Offset of the first record that cannot be read
+        offset: u64,
+        /// Why the record cannot be read
+        message: String,
+    },
+
+    /// A serde data format failed to read or write the records
+    #[cfg(feature = "serde")]
+    #[error("{format} error: {message}")]
+    Serde {
+        /// Name of the data format, e.g. `JSON`
+        format: &'static str,
+        /// Human-readable error description
+        message: String,
+    },
+
+    /// The SQLite database could not be read or written
+    #[cfg(feature = "sqlite")]
+    #[error("SQLite error: {message}")]
+    Sqlite {
+        /// Human-readable error description
+        message: String,
+    },
+
     /// An I/O error occurred while reading or writing record data
     #[error("I/O error: {message}")]
     IO {
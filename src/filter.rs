@@ -0,0 +1,131 @@
+//! Conditions on the field values of records, used by `ypbank grep` and the
+//! terminal viewer.
+
+use crate::storage::{STANDARD_FIELDS, YPBankRecord, is_extra_field_name};
+
+/// A condition on the fields of a record
+///
+/// Written as `FIELD=text`, with the field named as its CSV column, e.g.
+/// `STATUS`, or as an extra field; a pattern without a field name, e.g.
+/// `rent`, looks at every field. CSV column names are accepted in any case.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecordFilter {
+    /// Field to look at, `None` for every field
+    pub field: Option<String>,
+    /// Text to find in the field value
+    pub text: String,
+    /// Match the whole value rather than a part of it
+    pub exact: bool,
+    /// Compare ignoring case
+    pub ignore_case: bool,
+}
+
+impl RecordFilter {
+    /// Parses a pattern matching a part of a value, respecting case
+    pub fn parse(pattern: &str) -> Self {
+        let field = pattern.split_once('=').and_then(|(name, text)| {
+            let name = name.trim();
+            let standard = STANDARD_FIELDS.contains(&name.to_ascii_uppercase().as_str());
+            (standard || is_extra_field_name(name)).then(|| (name.to_string(), text))
+        });
+        match field {
+            Some((name, text)) => Self {
+                field: Some(name),
+                text: text.to_string(),
+                ..Self::default()
+            },
+            None => Self {
+                text: pattern.to_string(),
+                ..Self::default()
+            },
+        }
+    }
+
+    /// Whether the record has a field value matching the condition
+    pub fn matches(&self, record: &YPBankRecord) -> bool {
+        let text = if self.ignore_case {
+            self.text.to_lowercase()
+        } else {
+            self.text.clone()
+        };
+        let matches = |value: String| {
+            let value = if self.ignore_case {
+                value.to_lowercase()
+            } else {
+                value
+            };
+            if self.exact {
+                value == text
+            } else {
+                value.contains(&text)
+            }
+        };
+        match &self.field {
+            Some(name) => record
+                .field(name)
+                .or_else(|| record.field(&name.to_ascii_uppercase()))
+                .is_some_and(matches),
+            None if text.is_empty() && !self.exact => true,
+            None => STANDARD_FIELDS
+                .iter()
+                .filter_map(|name| record.field(name))
+                .chain(record.extra.values().cloned())
+                .any(matches),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{ExtraFields, YPBankRecordStatus, YPBankRecordType};
+    use crate::timestamp::Timestamp;
+
+    fn record() -> YPBankRecord {
+        YPBankRecord {
+            tx_id: 120,
+            tx_type: YPBankRecordType::TRANSFER,
+            from_user_id: 7,
+            to_user_id: 42,
+            amount: 5000,
+            currency: None,
+            timestamp: Timestamp::from_millis(1633036800000),
+            status: YPBankRecordStatus::PENDING,
+            description: "Rent for March".to_string(),
+            extra: ExtraFields::from([("channel".to_string(), "mobile".to_string())]),
+        }
+    }
+
+    #[test]
+    fn parses_patterns() {
+        let filter = RecordFilter::parse("status=PENDING");
+        assert_eq!(filter.field.as_deref(), Some("status"));
+        assert_eq!(filter.text, "PENDING");
+        let filter = RecordFilter::parse("a b=c");
+        assert_eq!(filter.field, None);
+        assert_eq!(filter.text, "a b=c");
+    }
+
+    #[test]
+    fn matches_fields() {
+        let record = record();
+        let matches = |pattern: &str, exact: bool, ignore_case: bool| {
+            let filter = RecordFilter {
+                exact,
+                ignore_case,
+                ..RecordFilter::parse(pattern)
+            };
+            filter.matches(&record)
+        };
+        assert!(matches("TX_ID=12", false, false));
+        assert!(!matches("TX_ID=12", true, false));
+        assert!(matches("tx_id=120", true, false));
+        assert!(!matches("STATUS=pending", false, false));
+        assert!(matches("STATUS=pending", false, true));
+        assert!(matches("channel=mob", false, false));
+        assert!(!matches("MERCHANT_ID=", false, false));
+        assert!(matches("march", false, true));
+        assert!(matches("mobile", true, false));
+        assert!(!matches("rent", false, false));
+    }
+}
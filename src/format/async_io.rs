@@ -0,0 +1,381 @@
+//! Async streaming readers and writers over tokio's `AsyncRead`/`AsyncWrite`.
+//!
+//! Records are decoded and encoded one at a time by the same code as the
+//! sync parsers; only the I/O differs.
+
+use super::format_bin;
+use super::format_csv::{CsvDecoder, CsvEncoder, CsvOptions};
+use super::format_txt::{self, TxtDecoder, TxtOptions};
+use super::{Format, FormatOptions};
+use crate::error::ParserError;
+use crate::parser::ParseLimits;
+use crate::storage::{YPBankRecord, YPBankStorage};
+use std::io::ErrorKind;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+
+enum Decoder {
+    Bin,
+    Csv(CsvDecoder),
+    Txt(TxtDecoder),
+}
+
+/// Streaming reader of records in one format
+pub struct AsyncRecordReader<R> {
+    reader: BufReader<R>,
+    decoder: Decoder,
+    limits: ParseLimits,
+    count: usize,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRecordReader<R> {
+    /// Creates a reader of `format` data with the options of that format
+    ///
+    /// The serde data formats are decoded as a whole and cannot be streamed.
+    pub fn new(format: Format, r: R, options: &FormatOptions) -> Result<Self, ParserError> {
+        let (decoder, limits) = match format {
+            Format::Bin => (Decoder::Bin, options.bin.limits),
+            Format::Csv => (
+                Decoder::Csv(CsvDecoder::new(options.csv.clone())?),
+                options.csv.limits,
+            ),
+            Format::Txt => (
+                Decoder::Txt(TxtDecoder::new(options.txt.clone())),
+                options.txt.limits,
+            ),
+            #[allow(unreachable_patterns)]
+            _ => return Err(not_streamable(format)),
+        };
+        Ok(Self {
+            reader: BufReader::new(r),
+            decoder,
+            limits,
+            count: 0,
+            done: false,
+        })
+    }
+
+    /// Reads the next record, `None` at the end of input
+    ///
+    /// After an error the reader is done, like [`super::record_reader::RecordReader`].
+    pub async fn next_record(&mut self) -> Result<Option<YPBankRecord>, ParserError> {
+        if self.done {
+            return Ok(None);
+        }
+        let record = self.read_record().await;
+        if !matches!(record, Ok(Some(_))) {
+            self.done = true;
+        }
+        record
+    }
+
+    async fn read_record(&mut self) -> Result<Option<YPBankRecord>, ParserError> {
+        let reader = &mut self.reader;
+        let limits = &self.limits;
+        let record = match &mut self.decoder {
+            Decoder::Bin => read_bin_record(reader, limits).await?,
+            Decoder::Csv(decoder) => loop {
+                match read_line(reader, limits).await? {
+                    Some(line) => {
+                        if let Some(record) = decoder.decode_line(&line)? {
+                            break Some(record);
+                        }
+                    }
+                    None => {
+                        decoder.finish()?;
+                        break None;
+                    }
+                }
+            },
+            Decoder::Txt(decoder) => loop {
+                match read_line(reader, limits).await? {
+                    Some(line) => {
+                        if let Some(record) = decoder.decode_line(&line)? {
+                            break Some(record);
+                        }
+                    }
+                    None => break decoder.finish()?,
+                }
+            },
+        };
+        if record.is_some() {
+            self.limits.check_record_count(self.count)?;
+            self.count += 1;
+        }
+        Ok(record)
+    }
+
+    /// Reads all remaining records
+    pub async fn read_all(mut self) -> Result<YPBankStorage, ParserError> {
+        let mut storage = YPBankStorage::new();
+        while let Some(record) = self.next_record().await? {
+            storage.push(record);
+        }
+        Ok(storage)
+    }
+}
+
+async fn read_bin_record<R: AsyncBufRead + Unpin>(
+    r: &mut R,
+    limits: &ParseLimits,
+) -> Result<Option<YPBankRecord>, ParserError> {
+    let mut magic = [0u8; 4];
+    match r.read_exact(&mut magic).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(io_error(e)),
+    }
+    format_bin::check_magic(&magic)?;
+
+    let record_size = r
+        .read_u32()
+        .await
+        .map_err(|_| invalid_record("truncated field"))? as usize;
+    limits.check_record_size(record_size)?;
+    let mut body = Vec::new();
+    (&mut *r)
+        .take(record_size as u64)
+        .read_to_end(&mut body)
+        .await
+        .map_err(io_error)?;
+    if body.len() != record_size {
+        return Err(invalid_record("invalid record body"));
+    }
+    format_bin::parse_record_body(&body, limits).map(Some)
+}
+
+async fn read_line<R: AsyncBufRead + Unpin>(
+    r: &mut R,
+    limits: &ParseLimits,
+) -> Result<Option<String>, ParserError> {
+    let mut line = Vec::new();
+    (&mut *r)
+        .take(limits.line_cap())
+        .read_until(b'\n', &mut line)
+        .await
+        .map_err(io_error)?;
+    limits.finish_line(line)
+}
+
+enum Encoder {
+    Bin,
+    Csv {
+        options: CsvOptions,
+        encoder: Option<CsvEncoder>,
+    },
+    Txt(TxtOptions),
+    Buffered(Box<FormatOptions>, YPBankStorage),
+}
+
+/// Streaming writer of records in one format
+///
+/// Like [`super::record_writer::RecordWriter`], CSV is streamed only with
+/// fixed columns; with [`CsvOptions::extra_columns`] the records are buffered
+/// and encoded in [`AsyncRecordWriter::finish`].
+pub struct AsyncRecordWriter<W> {
+    writer: W,
+    encoder: Encoder,
+    count: usize,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncRecordWriter<W> {
+    /// Creates a writer of `format` data with the options of that format
+    ///
+    /// The serde data formats are encoded as a whole and cannot be streamed.
+    pub fn new(format: Format, w: W, options: &FormatOptions) -> Result<Self, ParserError> {
+        let encoder = match format {
+            Format::Bin => Encoder::Bin,
+            Format::Csv if !options.csv.extra_columns => Encoder::Csv {
+                options: options.csv.clone(),
+                encoder: None,
+            },
+            Format::Csv => Encoder::Buffered(Box::new(options.clone()), YPBankStorage::new()),
+            Format::Txt => Encoder::Txt(options.txt.clone()),
+            #[allow(unreachable_patterns)]
+            _ => return Err(not_streamable(format)),
+        };
+        Ok(Self {
+            writer: w,
+            encoder,
+            count: 0,
+        })
+    }
+
+    /// Writes one record
+    pub async fn write_record(&mut self, record: &YPBankRecord) -> Result<(), ParserError> {
+        let data = match &mut self.encoder {
+            Encoder::Bin => format_bin::encode_record(record)?,
+            Encoder::Txt(options) => format_txt::encode_record(record, self.count == 0, options)?,
+            Encoder::Csv {
+                encoder: Some(encoder),
+                ..
+            } => encoder.encode(record)?,
+            Encoder::Csv { options, encoder } => {
+                let created = CsvEncoder::new(&[], options.clone())?;
+                let mut data = created.header();
+                data.extend(created.encode(record)?);
+                *encoder = Some(created);
+                data
+            }
+            Encoder::Buffered(_, storage) => {
+                storage.push(record.clone());
+                Vec::new()
+            }
+        };
+        self.writer.write_all(&data).await.map_err(io_error)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Writes the CSV header if no record was written, or the buffered
+    /// records, flushes and returns the underlying writer
+    pub async fn finish(mut self) -> Result<W, ParserError> {
+        let data = match self.encoder {
+            Encoder::Csv {
+                options,
+                encoder: None,
+            } => CsvEncoder::new(&[], options)?.header(),
+            Encoder::Buffered(options, storage) => {
+                let mut data = Vec::new();
+                Format::Csv.write(storage, &mut data, &options)?;
+                data
+            }
+            _ => Vec::new(),
+        };
+        self.writer.write_all(&data).await.map_err(io_error)?;
+        self.writer.flush().await.map_err(io_error)?;
+        Ok(self.writer)
+    }
+}
+
+fn not_streamable(format: Format) -> ParserError {
+    invalid_record(&format!("{} records cannot be streamed", format))
+}
+
+fn invalid_record(msg: &str) -> ParserError {
+    ParserError::InvalidRecord {
+        message: msg.to_string(),
+    }
+}
+
+fn io_error(e: std::io::Error) -> ParserError {
+    ParserError::IO {
+        message: e.to_string(),
+        error: e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::sample_storage;
+
+    const FORMATS: [Format; 3] = [Format::Bin, Format::Csv, Format::Txt];
+
+    #[tokio::test]
+    async fn streams_the_same_bytes_as_the_sync_parsers() {
+        let records = sample_storage().into_records();
+        let options = FormatOptions::default();
+        for format in FORMATS {
+            let mut writer = AsyncRecordWriter::new(format, Vec::new(), &options).unwrap();
+            for record in &records {
+                writer.write_record(record).await.unwrap();
+            }
+            let data = writer.finish().await.unwrap();
+
+            let mut expected = Vec::new();
+            format
+                .write(sample_storage(), &mut expected, &options)
+                .unwrap();
+            assert_eq!(data, expected, "{}", format);
+
+            let mut reader = AsyncRecordReader::new(format, &data[..], &options).unwrap();
+            for record in &records {
+                assert_eq!(reader.next_record().await.unwrap().as_ref(), Some(record));
+            }
+            assert_eq!(reader.next_record().await.unwrap(), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn empty_csv_still_gets_a_header() {
+        let options = FormatOptions::default();
+        let writer = AsyncRecordWriter::new(Format::Csv, Vec::new(), &options).unwrap();
+        let data = writer.finish().await.unwrap();
+        let reader = AsyncRecordReader::new(Format::Csv, &data[..], &options).unwrap();
+        assert!(reader.read_all().await.unwrap().records().is_empty());
+    }
+
+    #[tokio::test]
+    async fn csv_columns_of_records_in_either_order() {
+        let mut records = sample_storage().into_records();
+        records[1].extra.insert("REFERENCE".into(), "r-1".into());
+        let options = FormatOptions::default();
+        for _ in 0..2 {
+            records.reverse();
+            let mut writer = AsyncRecordWriter::new(Format::Csv, Vec::new(), &options).unwrap();
+            for record in &records {
+                writer.write_record(record).await.unwrap();
+            }
+            let data = writer.finish().await.unwrap();
+            assert!(
+                String::from_utf8_lossy(&data)
+                    .lines()
+                    .next()
+                    .unwrap()
+                    .ends_with(",CURRENCY,CHANNEL,REFERENCE")
+            );
+
+            let reader = AsyncRecordReader::new(Format::Csv, &data[..], &options).unwrap();
+            assert_eq!(reader.read_all().await.unwrap().records(), records);
+        }
+    }
+
+    #[tokio::test]
+    async fn stops_after_an_error() {
+        let mut reader = AsyncRecordReader::new(
+            Format::Txt,
+            &b"TX_ID: x\n\nTX_ID: 1\n"[..],
+            &FormatOptions::default(),
+        )
+        .unwrap();
+        assert!(reader.next_record().await.is_err());
+        assert_eq!(reader.next_record().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn honours_limits() {
+        for format in FORMATS {
+            let mut data = Vec::new();
+            format
+                .write(sample_storage(), &mut data, &FormatOptions::default())
+                .unwrap();
+            let options = FormatOptions::default().with_limits(ParseLimits {
+                max_records: 1,
+                ..ParseLimits::default()
+            });
+            let reader = AsyncRecordReader::new(format, &data[..], &options).unwrap();
+            assert!(matches!(
+                reader.read_all().await,
+                Err(ParserError::TooManyRecords { max: 1 })
+            ));
+        }
+
+        let options = FormatOptions::default().with_limits(ParseLimits {
+            max_line_len: 16,
+            ..ParseLimits::default()
+        });
+        let mut reader = AsyncRecordReader::new(
+            Format::Txt,
+            &b"TX_ID: 1\nDESCRIPTION: \"a long line\"\n"[..],
+            &options,
+        )
+        .unwrap();
+        assert!(matches!(
+            reader.next_record().await,
+            Err(ParserError::LineTooLong { max: 16 })
+        ));
+    }
+}
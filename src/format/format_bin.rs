@@ -1,50 +1,82 @@
 //! Binary format parser for YPBank records.
 
+use crate::currency::Currency;
 use crate::error::ParserError;
-use crate::parser::Parser;
-use crate::storage::{YPBankRecord, YPBankRecordStatus, YPBankRecordType, YPBankStorage};
+use crate::parser::{ParseLimits, Parser};
+use crate::storage::{
+    ExtraFields, YPBankRecord, YPBankRecordStatus, YPBankRecordType, YPBankStorage,
+    is_extra_field_name,
+};
+use crate::timestamp::{TimeUnit, Timestamp};
 use std::io::{Cursor, ErrorKind, Read, Write};
 
 const MAGIC: [u8; 4] = [0x59, 0x50, 0x42, 0x4E]; // 'YPBN'
 
+/// Options controlling how binary data is read.
+#[derive(Debug, Clone, Default)]
+pub struct BinOptions {
+    /// Bounds on the input.
+    pub limits: ParseLimits,
+}
+
 /// Parser for the custom binary (`YPBN`) record format.
 pub struct BinParser {
     /// In-memory storage populated after parsing.
     pub storage: YPBankStorage,
 }
 
-impl Parser for BinParser {
-    fn from_read<R: Read>(r: &mut R) -> Result<YPBankStorage, ParserError> {
+impl BinParser {
+    /// Reads data from reader using the given options
+    pub fn read_with_options<R: Read>(
+        r: &mut R,
+        options: &BinOptions,
+    ) -> Result<YPBankStorage, ParserError> {
+        let limits = &options.limits;
         let mut storage = YPBankStorage::new();
-        loop {
-            // Read record header
-            let mut magic = [0u8; 4];
-            match r.read_exact(&mut magic) {
-                Ok(()) => {}
-                Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
-                Err(e) => return Err(io_error(e)),
-            }
-            if magic != MAGIC {
-                return Err(invalid_record("invalid record header"));
-            }
-
-            // Record size
-            let record_size = read_u32_be(r)? as usize;
-            let mut body = vec![0u8; record_size];
-            r.read_exact(&mut body)
-                .map_err(|_| invalid_record("invalid record body"))?;
-            storage.push(parse_record_body(&body)?);
+        while let Some(record) = read_record(r, limits)? {
+            limits.push(&mut storage, record)?;
         }
         Ok(storage)
     }
+}
+
+/// Reads the next record, `None` at the end of input
+pub(crate) fn read_record<R: Read>(
+    r: &mut R,
+    limits: &ParseLimits,
+) -> Result<Option<YPBankRecord>, ParserError> {
+    // Read record header
+    let mut magic = [0u8; 4];
+    match r.read_exact(&mut magic) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(io_error(e)),
+    }
+    check_magic(&magic)?;
+
+    // Record size; the body is read incrementally so that a forged size
+    // cannot force a huge allocation up front
+    let record_size = read_u32_be(r)? as usize;
+    limits.check_record_size(record_size)?;
+    let mut body = Vec::new();
+    r.by_ref()
+        .take(record_size as u64)
+        .read_to_end(&mut body)
+        .map_err(io_error)?;
+    if body.len() != record_size {
+        return Err(invalid_record("invalid record body"));
+    }
+    parse_record_body(&body, limits).map(Some)
+}
+
+impl Parser for BinParser {
+    fn from_read<R: Read>(r: &mut R) -> Result<YPBankStorage, ParserError> {
+        Self::read_with_options(r, &BinOptions::default())
+    }
 
     fn write_to<W: Write>(&mut self, w: &mut W) -> Result<(), ParserError> {
         for record in self.storage.records() {
-            let body = serialize_record(record);
-            w.write_all(&MAGIC).map_err(io_error)?;
-            w.write_all(&(body.len() as u32).to_be_bytes())
-                .map_err(io_error)?;
-            w.write_all(&body).map_err(io_error)?;
+            w.write_all(&encode_record(record)?).map_err(io_error)?;
         }
         Ok(())
     }
@@ -54,39 +86,89 @@ impl Parser for BinParser {
     }
 }
 
-fn parse_record_body(body: &[u8]) -> Result<YPBankRecord, ParserError> {
+/// Fails unless a record header starts with the `YPBN` magic
+pub(crate) fn check_magic(magic: &[u8; 4]) -> Result<(), ParserError> {
+    if *magic != MAGIC {
+        return Err(invalid_record("invalid record header"));
+    }
+    Ok(())
+}
+
+/// Decodes a record body, shared by the sync and async readers
+pub(crate) fn parse_record_body(
+    body: &[u8],
+    limits: &ParseLimits,
+) -> Result<YPBankRecord, ParserError> {
     let mut cur = Cursor::new(body);
     let tx_id = read_u64_be(&mut cur)?;
     let tx_type = read_tx_type(&mut cur)?;
     let from_user_id = read_u64_be(&mut cur)?;
     let to_user_id = read_u64_be(&mut cur)?;
     let amount = read_i64_be(&mut cur)?.unsigned_abs();
-    let timestamp = read_u64_be(&mut cur)?;
+    let timestamp = Timestamp::from_millis(read_u64_be(&mut cur)?);
     let status = read_status(&mut cur)?;
     let desc_len = read_u32_be(&mut cur)? as usize;
-
-    let mut desc_bytes = vec![0u8; desc_len];
-    cur.read_exact(&mut desc_bytes)
-        .map_err(|_| invalid_record("DESCRIPTION length exceeds body"))?;
-
-    let description = String::from_utf8(desc_bytes)
-        .map_err(|_| invalid_record("DESCRIPTION is not valid UTF-8"))?;
-
+    limits.check_description(desc_len)?;
+    let description = read_string(&mut cur, desc_len, "DESCRIPTION")?;
     let description = description.trim_matches('"').to_string();
 
+    // Optional extension block after the fixed fields
+    let mut currency = None;
+    let mut extra = ExtraFields::new();
+    if (cur.position() as usize) < body.len() {
+        for _ in 0..read_u16_be(&mut cur)? {
+            let key_len = read_u8(&mut cur)? as usize;
+            let key = read_string(&mut cur, key_len, "extension key")?;
+            let value_len = read_u32_be(&mut cur)? as usize;
+            let value = read_string(&mut cur, value_len, "extension value")?;
+            if key == "CURRENCY" && currency.is_none() {
+                currency = Some(
+                    Currency::from_code(&value)
+                        .ok_or_else(|| invalid_record("invalid CURRENCY"))?,
+                );
+            } else if is_extra_field_name(&key) && !extra.contains_key(&key) {
+                extra.insert(key, value);
+            } else {
+                return Err(invalid_record(&format!("invalid extension key: {}", key)));
+            }
+        }
+        if (cur.position() as usize) != body.len() {
+            return Err(invalid_record("trailing bytes after extension block"));
+        }
+    }
+
     Ok(YPBankRecord {
         tx_id,
         tx_type,
         from_user_id,
         to_user_id,
         amount,
+        currency,
         timestamp,
         status,
         description,
+        extra,
     })
 }
 
-fn serialize_record(record: &YPBankRecord) -> Vec<u8> {
+/// A record with its header, shared by the sync and async writers
+pub(crate) fn encode_record(record: &YPBankRecord) -> Result<Vec<u8>, ParserError> {
+    let body = serialize_record(record)?;
+    let size = u32::try_from(body.len()).map_err(|_| invalid_record("record too large"))?;
+    let mut out = Vec::with_capacity(8 + body.len());
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&size.to_be_bytes());
+    out.extend_from_slice(&body);
+    Ok(out)
+}
+
+fn serialize_record(record: &YPBankRecord) -> Result<Vec<u8>, ParserError> {
+    // Enclosing quotes are stripped on reading
+    if record.description.starts_with('"') || record.description.ends_with('"') {
+        return Err(invalid_record(
+            "DESCRIPTION cannot start or end with a double quote",
+        ));
+    }
     let desc = record.description.as_bytes();
     let mut out = Vec::with_capacity(46 + desc.len());
     out.extend_from_slice(&record.tx_id.to_be_bytes());
@@ -97,12 +179,17 @@ fn serialize_record(record: &YPBankRecord) -> Vec<u8> {
     });
     out.extend_from_slice(&record.from_user_id.to_be_bytes());
     out.extend_from_slice(&record.to_user_id.to_be_bytes());
-    let amount_i64: i64 = match record.tx_type {
-        YPBankRecordType::WITHDRAWAL => -(record.amount as i64),
-        _ => record.amount as i64,
-    };
+    let amount_i64 = match record.tx_type {
+        YPBankRecordType::WITHDRAWAL => 0i64.checked_sub_unsigned(record.amount),
+        _ => i64::try_from(record.amount).ok(),
+    }
+    .ok_or_else(|| invalid_record("AMOUNT out of range"))?;
     out.extend_from_slice(&amount_i64.to_be_bytes());
-    out.extend_from_slice(&record.timestamp.to_be_bytes());
+    let timestamp = record
+        .timestamp
+        .to_unix_exact(TimeUnit::Millis)
+        .map_err(|e| invalid_record(&e.to_string()))?;
+    out.extend_from_slice(&timestamp.to_be_bytes());
     out.push(match record.status {
         YPBankRecordStatus::SUCCESS => 0,
         YPBankRecordStatus::FAILURE => 1,
@@ -110,7 +197,49 @@ fn serialize_record(record: &YPBankRecord) -> Vec<u8> {
     });
     out.extend_from_slice(&(desc.len() as u32).to_be_bytes());
     out.extend_from_slice(desc);
-    out
+
+    let currency = record.currency.map(|c| ("CURRENCY", c.code()));
+    let extra = record.extra.iter().map(|(k, v)| (k.as_str(), v.as_str()));
+    let entries: Vec<(&str, &str)> = currency.into_iter().chain(extra).collect();
+    if !entries.is_empty() {
+        let count = u16::try_from(entries.len())
+            .map_err(|_| invalid_record("too many extension entries"))?;
+        out.extend_from_slice(&count.to_be_bytes());
+        for (key, value) in entries {
+            let key_len = u8::try_from(key.len())
+                .map_err(|_| invalid_record(&format!("extension key too long: {}", key)))?;
+            out.push(key_len);
+            out.extend_from_slice(key.as_bytes());
+            out.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            out.extend_from_slice(value.as_bytes());
+        }
+    }
+    Ok(out)
+}
+
+fn read_u8(r: &mut impl Read) -> Result<u8, ParserError> {
+    let mut b = [0u8; 1];
+    r.read_exact(&mut b)
+        .map_err(|_| invalid_record("truncated field"))?;
+    Ok(b[0])
+}
+
+fn read_u16_be(r: &mut impl Read) -> Result<u16, ParserError> {
+    let mut b = [0u8; 2];
+    r.read_exact(&mut b)
+        .map_err(|_| invalid_record("truncated field"))?;
+    Ok(u16::from_be_bytes(b))
+}
+
+fn read_string(r: &mut Cursor<&[u8]>, len: usize, name: &str) -> Result<String, ParserError> {
+    let remaining = r.get_ref().len() - r.position() as usize;
+    if len > remaining {
+        return Err(invalid_record(&format!("{} length exceeds body", name)));
+    }
+    let mut bytes = vec![0u8; len];
+    r.read_exact(&mut bytes)
+        .map_err(|_| invalid_record(&format!("truncated {}", name)))?;
+    String::from_utf8(bytes).map_err(|_| invalid_record(&format!("{} is not valid UTF-8", name)))
 }
 
 fn read_u32_be(r: &mut impl Read) -> Result<u32, ParserError> {
@@ -175,6 +304,7 @@ fn io_error(e: std::io::Error) -> ParserError {
 mod tests {
     use super::*;
     use crate::storage::{YPBankRecord, YPBankRecordStatus, YPBankRecordType};
+    use crate::timestamp::Timestamp;
 
     fn sample_record() -> YPBankRecord {
         YPBankRecord {
@@ -183,9 +313,11 @@ mod tests {
             from_user_id: 1,
             to_user_id: 2,
             amount: 1000,
-            timestamp: 1700000001,
+            currency: None,
+            timestamp: Timestamp::from_millis(1700000001),
             status: YPBankRecordStatus::PENDING,
             description: "test deposit".to_string(),
+            extra: ExtraFields::new(),
         }
     }
 
@@ -211,7 +343,7 @@ mod tests {
         let record = sample_record();
 
         // Manually build the binary representation
-        let body = serialize_record(&record);
+        let body = serialize_record(&record).unwrap();
         let mut data = Vec::new();
         data.extend_from_slice(&MAGIC);
         data.extend_from_slice(&(body.len() as u32).to_be_bytes());
@@ -226,7 +358,7 @@ mod tests {
 
     #[test]
     fn test_invalid_magic() {
-        let body = serialize_record(&sample_record());
+        let body = serialize_record(&sample_record()).unwrap();
         let mut data = Vec::new();
         data.extend_from_slice(&[0xDE, 0xAD, 0xBE, 0xEF]);
         data.extend_from_slice(&(body.len() as u32).to_be_bytes());
@@ -256,4 +388,115 @@ mod tests {
             result
         );
     }
+
+    #[test]
+    fn test_extension_block_round_trip() {
+        let mut record = sample_record();
+        record.currency = Currency::from_code("EUR");
+        record.extra.insert("CHANNEL".into(), "web".into());
+        record.extra.insert("REFERENCE".into(), "INV-123".into());
+        let mut storage = YPBankStorage::new();
+        storage.push(record.clone());
+
+        let mut buf = Vec::new();
+        BinParser::from_storage(storage)
+            .write_to(&mut buf)
+            .expect("write failed");
+        let parsed = BinParser::from_read(&mut Cursor::new(buf)).expect("read failed");
+        assert_eq!(parsed.records(), &[record]);
+    }
+
+    #[test]
+    fn test_no_extension_block_without_extra_fields() {
+        let body = serialize_record(&sample_record()).unwrap();
+        assert_eq!(body.len(), 46 + "test deposit".len());
+    }
+
+    #[test]
+    fn test_truncated_extension_block() {
+        let mut body = serialize_record(&sample_record()).unwrap();
+        body.extend_from_slice(&1u16.to_be_bytes());
+        body.push(7);
+        body.extend_from_slice(b"CHAN");
+        let mut data = Vec::new();
+        data.extend_from_slice(&MAGIC);
+        data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        data.extend_from_slice(&body);
+
+        let result = BinParser::from_read(&mut Cursor::new(data));
+        assert!(
+            matches!(result, Err(ParserError::InvalidRecord { .. })),
+            "expected InvalidRecord for truncated extension block, got: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_oversized_lengths() {
+        // Record and description sizes far beyond the actual data
+        let mut data = Vec::new();
+        data.extend_from_slice(&MAGIC);
+        data.extend_from_slice(&u32::MAX.to_be_bytes());
+        data.extend_from_slice(&[0u8; 10]);
+        let unlimited = BinOptions {
+            limits: ParseLimits::unlimited(),
+        };
+        let result = BinParser::read_with_options(&mut Cursor::new(&data), &unlimited);
+        assert!(matches!(result, Err(ParserError::InvalidRecord { .. })));
+        let result = BinParser::from_read(&mut Cursor::new(&data));
+        assert!(matches!(result, Err(ParserError::RecordTooLarge { .. })));
+
+        let mut body = serialize_record(&sample_record()).unwrap();
+        let desc_len = body.len() - "test deposit".len() - 4;
+        body[desc_len..desc_len + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            parse_record_body(&body, &ParseLimits::unlimited()),
+            Err(ParserError::InvalidRecord { .. })
+        ));
+        assert!(matches!(
+            parse_record_body(&body, &ParseLimits::default()),
+            Err(ParserError::DescriptionTooLong { .. })
+        ));
+    }
+
+    #[test]
+    fn test_record_count_limit() {
+        let mut storage = YPBankStorage::new();
+        storage.push(sample_record());
+        storage.push(sample_record());
+        let mut data = Vec::new();
+        BinParser::from_storage(storage)
+            .write_to(&mut data)
+            .unwrap();
+
+        let options = BinOptions {
+            limits: ParseLimits {
+                max_records: 1,
+                ..ParseLimits::default()
+            },
+        };
+        let result = BinParser::read_with_options(&mut Cursor::new(data), &options);
+        assert!(matches!(
+            result,
+            Err(ParserError::TooManyRecords { max: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_amount_range() {
+        let mut record = sample_record();
+        record.tx_type = YPBankRecordType::WITHDRAWAL;
+        record.amount = i64::MIN.unsigned_abs();
+        let body = serialize_record(&record).unwrap();
+        assert_eq!(
+            parse_record_body(&body, &ParseLimits::default()).unwrap(),
+            record
+        );
+
+        record.tx_type = YPBankRecordType::DEPOSIT;
+        assert!(matches!(
+            serialize_record(&record),
+            Err(ParserError::InvalidRecord { .. })
+        ));
+    }
 }
@@ -1,121 +1,483 @@
 //! CSV format parser for YPBank records.
 
+use crate::currency::{AmountFormat, Currency, format_amount, parse_amount};
 use crate::error::ParserError;
-use crate::parser::Parser;
-use crate::storage::{YPBankRecord, YPBankRecordStatus, YPBankRecordType, YPBankStorage};
-use std::io::{BufRead, BufReader, Read, Write};
+use crate::parser::{ParseLimits, Parser};
+use crate::storage::{
+    ExtraFields, YPBankRecord, YPBankRecordStatus, YPBankRecordType, YPBankStorage,
+    is_extra_field_name,
+};
+use crate::timestamp::{Timestamp, TimestampFormat};
+use std::collections::{BTreeSet, HashMap};
+use std::io::{BufReader, Read, Write};
 use std::str::FromStr;
+use strum_macros::{Display, EnumString};
+
+/// Columns of the CSV record format, in the default header order.
+pub const DEFAULT_COLUMNS: [CsvColumn; 8] = [
+    CsvColumn::TxId,
+    CsvColumn::TxType,
+    CsvColumn::FromUserId,
+    CsvColumn::ToUserId,
+    CsvColumn::Amount,
+    CsvColumn::Timestamp,
+    CsvColumn::Status,
+    CsvColumn::Description,
+];
+
+/// A named column of the CSV record format.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Display, EnumString)]
+pub enum CsvColumn {
+    /// `TX_ID` column
+    #[strum(serialize = "TX_ID")]
+    TxId,
+    /// `TX_TYPE` column
+    #[strum(serialize = "TX_TYPE")]
+    TxType,
+    /// `FROM_USER_ID` column
+    #[strum(serialize = "FROM_USER_ID")]
+    FromUserId,
+    /// `TO_USER_ID` column
+    #[strum(serialize = "TO_USER_ID")]
+    ToUserId,
+    /// `AMOUNT` column
+    #[strum(serialize = "AMOUNT")]
+    Amount,
+    /// `TIMESTAMP` column
+    #[strum(serialize = "TIMESTAMP")]
+    Timestamp,
+    /// `STATUS` column
+    #[strum(serialize = "STATUS")]
+    Status,
+    /// `DESCRIPTION` column
+    #[strum(serialize = "DESCRIPTION")]
+    Description,
+    /// Optional `CURRENCY` column
+    #[strum(serialize = "CURRENCY")]
+    Currency,
+}
+
+/// Presence of the header line in CSV data.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum CsvHeader {
+    /// The first line must be a header
+    Required,
+    /// There is no header; columns follow [`CsvOptions::columns`]
+    Absent,
+    /// A header is detected on read and always written
+    Auto,
+}
+
+/// Line terminator emitted by the CSV writer.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum CsvLineTerminator {
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    Crlf,
+}
+
+impl CsvLineTerminator {
+    fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            CsvLineTerminator::Lf => b"\n",
+            CsvLineTerminator::Crlf => b"\r\n",
+        }
+    }
+}
+
+/// Lexical conventions of a CSV file.
+#[derive(Debug, Clone)]
+pub struct CsvDialect {
+    /// Field separator
+    pub delimiter: char,
+    /// Quote character; a quote inside a quoted field is doubled
+    pub quote: char,
+    /// Whether the data starts with a header line
+    pub header: CsvHeader,
+    /// Write a UTF-8 byte order mark; a leading mark is always skipped on read
+    pub bom: bool,
+    /// Line terminator used by the writer; both are accepted on read
+    pub line_terminator: CsvLineTerminator,
+    /// Trim surrounding whitespace from fields on read
+    pub trim: bool,
+}
 
-const HEADER: &str = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION";
+impl Default for CsvDialect {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            quote: '"',
+            header: CsvHeader::Required,
+            bom: false,
+            line_terminator: CsvLineTerminator::Lf,
+            trim: true,
+        }
+    }
+}
+
+/// Options controlling how CSV data is read and written.
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    /// Columns emitted by the writer, in order; also the column order of
+    /// data read without a header.
+    pub columns: Vec<CsvColumn>,
+    /// Append columns for `CURRENCY` and extra fields present in the records
+    /// but not listed in `columns`.
+    pub extra_columns: bool,
+    /// Ignore malformed column names and surplus fields instead of failing on them.
+    pub lenient: bool,
+    /// Delimiter, quoting and header conventions.
+    pub dialect: CsvDialect,
+    /// Representation of the `TIMESTAMP` column.
+    pub timestamp: TimestampFormat,
+    /// Representation of the `AMOUNT` column.
+    pub amount: AmountFormat,
+    /// Currency of records that do not specify one.
+    pub currency: Option<Currency>,
+    /// Bounds on the input.
+    pub limits: ParseLimits,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            columns: DEFAULT_COLUMNS.to_vec(),
+            extra_columns: true,
+            lenient: false,
+            dialect: CsvDialect::default(),
+            timestamp: TimestampFormat::default(),
+            amount: AmountFormat::default(),
+            currency: None,
+            limits: ParseLimits::default(),
+        }
+    }
+}
 
 /// Parser for the CSV record format.
 pub struct CsvParser {
     /// In-memory storage populated after parsing.
     pub storage: YPBankStorage,
+    options: CsvOptions,
 }
 
-impl Parser for CsvParser {
-    fn from_read<R: Read>(r: &mut R) -> Result<YPBankStorage, ParserError> {
+impl CsvParser {
+    /// Creates new parser from storage with the given options
+    pub fn with_options(storage: YPBankStorage, options: CsvOptions) -> Self {
+        Self { storage, options }
+    }
+
+    /// Reads data from reader using the given options
+    pub fn read_with_options<R: Read>(
+        r: &mut R,
+        options: &CsvOptions,
+    ) -> Result<YPBankStorage, ParserError> {
+        let mut decoder = CsvDecoder::new(options.clone())?;
         let mut storage = YPBankStorage::new();
         let mut reader = BufReader::new(r);
-        parse_header(&mut reader)?;
-        loop {
-            let mut line = String::new();
-            let bytes_read = reader.read_line(&mut line).map_err(io_error)?;
-            if bytes_read == 0 {
-                break; // EOF
+        while let Some(line) = options.limits.read_line(&mut reader)? {
+            if let Some(record) = decoder.decode_line(&line)? {
+                options.limits.push(&mut storage, record)?;
             }
-            if line.trim().is_empty() {
-                continue; // skip empty lines
-            }
-            let record = parse_record(&line)?;
-            storage.push(record);
         }
+        decoder.finish()?;
         Ok(storage)
     }
+}
+
+/// Line-by-line CSV decoding shared by the sync and async readers
+pub(crate) struct CsvDecoder {
+    options: CsvOptions,
+    first_line: bool,
+    layout: Option<Vec<Slot>>,
+}
+
+impl CsvDecoder {
+    pub(crate) fn new(options: CsvOptions) -> Result<Self, ParserError> {
+        check_dialect(&options.dialect)?;
+        Ok(Self {
+            options,
+            first_line: true,
+            layout: None,
+        })
+    }
+
+    /// Decodes a line without its terminator; `None` for a header or an empty line
+    pub(crate) fn decode_line(&mut self, line: &str) -> Result<Option<YPBankRecord>, ParserError> {
+        let line = if std::mem::take(&mut self.first_line) {
+            line.strip_prefix('\u{feff}').unwrap_or(line)
+        } else {
+            line
+        };
+        if line.trim().is_empty() {
+            return Ok(None);
+        }
+        let options = &self.options;
+        let dialect = &options.dialect;
+        let fields = split_fields(line, dialect);
+        let layout = match &mut self.layout {
+            Some(layout) => layout,
+            layout => {
+                let is_header = match dialect.header {
+                    CsvHeader::Required => true,
+                    CsvHeader::Absent => false,
+                    CsvHeader::Auto => looks_like_header(&fields, dialect),
+                };
+                if is_header {
+                    *layout = Some(parse_header(&fields, options)?);
+                    return Ok(None);
+                }
+                let columns: Vec<_> = options.columns.iter().copied().map(Slot::Column).collect();
+                check_layout(&columns)?;
+                layout.insert(columns)
+            }
+        };
+        parse_record(&fields, layout, options).map(Some)
+    }
+
+    /// Checks the end of the input
+    pub(crate) fn finish(&self) -> Result<(), ParserError> {
+        if self.layout.is_none() && self.options.dialect.header == CsvHeader::Required {
+            return Err(invalid_record("missing CSV header"));
+        }
+        Ok(())
+    }
+}
+
+/// CSV encoding shared by the sync and async writers
+pub(crate) struct CsvEncoder {
+    options: CsvOptions,
+    columns: Vec<CsvColumn>,
+    extra_names: Vec<String>,
+}
+
+impl CsvEncoder {
+    /// Chooses the columns for writing `records`
+    pub(crate) fn new(records: &[YPBankRecord], options: CsvOptions) -> Result<Self, ParserError> {
+        check_dialect(&options.dialect)?;
+        let (columns, extra_names) = output_columns(records, &options);
+        Ok(Self {
+            options,
+            columns,
+            extra_names,
+        })
+    }
+
+    /// The byte order mark and the header line, as configured
+    pub(crate) fn header(&self) -> Vec<u8> {
+        let dialect = &self.options.dialect;
+        let mut out = Vec::new();
+        if dialect.bom {
+            out.extend_from_slice(BOM);
+        }
+        if dialect.header != CsvHeader::Absent {
+            let names: Vec<String> = self
+                .columns
+                .iter()
+                .map(|c| c.to_string())
+                .chain(self.extra_names.iter().cloned())
+                .collect();
+            out.extend_from_slice(names.join(&dialect.delimiter.to_string()).as_bytes());
+            out.extend_from_slice(dialect.line_terminator.as_bytes());
+        }
+        out
+    }
+
+    /// A record line with its terminator
+    ///
+    /// Fails if the record has a currency or extra fields that the columns
+    /// chosen up front cannot hold.
+    pub(crate) fn encode(&self, record: &YPBankRecord) -> Result<Vec<u8>, ParserError> {
+        if self.options.extra_columns {
+            if record.currency.is_some() && !self.columns.contains(&CsvColumn::Currency) {
+                return Err(invalid_record(
+                    "CURRENCY is not in the streamed CSV columns",
+                ));
+            }
+            if let Some(name) = record.extra.keys().find(|k| !self.extra_names.contains(k)) {
+                return Err(invalid_record(&format!(
+                    "extra field {} is not in the streamed CSV columns",
+                    name
+                )));
+            }
+        }
+        let line = serialize_record(record, &self.columns, &self.extra_names, &self.options)?;
+        let mut out = line.into_bytes();
+        out.extend_from_slice(self.options.dialect.line_terminator.as_bytes());
+        Ok(out)
+    }
+}
+
+impl Parser for CsvParser {
+    fn from_read<R: Read>(r: &mut R) -> Result<YPBankStorage, ParserError> {
+        Self::read_with_options(r, &CsvOptions::default())
+    }
 
     fn write_to<W: Write>(&mut self, w: &mut W) -> Result<(), ParserError> {
-        w.write_all(HEADER.as_bytes()).map_err(io_error)?;
-        w.write_all(b"\n").map_err(io_error)?;
-        for record in self.storage.records() {
-            w.write_all(serialize_record(record).as_bytes())
-                .map_err(io_error)?;
-            w.write_all(b"\n").map_err(io_error)?;
+        let records = self.storage.records();
+        let encoder = CsvEncoder::new(records, self.options.clone())?;
+        w.write_all(&encoder.header()).map_err(io_error)?;
+        for record in records {
+            w.write_all(&encoder.encode(record)?).map_err(io_error)?;
         }
         Ok(())
     }
 
     fn from_storage(storage: YPBankStorage) -> Self {
-        Self { storage }
+        Self::with_options(storage, CsvOptions::default())
+    }
+}
+
+/// Parses a comma-separated list of column names, e.g. `TX_ID,AMOUNT,STATUS`
+pub fn parse_columns(s: &str) -> Result<Vec<CsvColumn>, ParserError> {
+    let mut columns = Vec::new();
+    for name in s.split(',') {
+        let column = CsvColumn::from_str(name.trim())
+            .map_err(|_| invalid_record(&format!("unknown CSV column: {}", name.trim())))?;
+        if columns.contains(&column) {
+            return Err(invalid_record(&format!("duplicate CSV column: {}", column)));
+        }
+        columns.push(column);
     }
+    Ok(columns)
 }
 
-fn parse_header(r: &mut impl BufRead) -> Result<(), ParserError> {
-    let mut header = String::new();
-    r.read_line(&mut header).map_err(io_error)?;
-    if header.trim() != HEADER {
-        return Err(invalid_record("invalid CSV header"));
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+fn check_dialect(dialect: &CsvDialect) -> Result<(), ParserError> {
+    if dialect.delimiter == dialect.quote {
+        return Err(invalid_record("CSV delimiter and quote must differ"));
     }
     Ok(())
 }
 
-fn parse_record(line: &str) -> Result<YPBankRecord, ParserError> {
-    let mut parts = line.splitn(8, ',');
-
-    let tx_id = parts
-        .next()
-        .ok_or_else(|| invalid_record("missing TX_ID"))?
-        .trim()
-        .parse::<u64>()
-        .map_err(|_| invalid_record("invalid TX_ID"))?;
-
-    let tx_type = parse_tx_type(
-        parts
-            .next()
-            .ok_or_else(|| invalid_record("missing TX_TYPE"))?
-            .trim(),
-    )?;
-
-    let from_user_id = parts
-        .next()
-        .ok_or_else(|| invalid_record("missing FROM_USER_ID"))?
-        .trim()
-        .parse::<u64>()
-        .map_err(|_| invalid_record("invalid FROM_USER_ID"))?;
-
-    let to_user_id = parts
-        .next()
-        .ok_or_else(|| invalid_record("missing TO_USER_ID"))?
-        .trim()
-        .parse::<u64>()
-        .map_err(|_| invalid_record("invalid TO_USER_ID"))?;
-
-    let amount = parts
-        .next()
-        .ok_or_else(|| invalid_record("missing AMOUNT"))?
-        .trim()
-        .parse::<u64>()
-        .map_err(|_| invalid_record("invalid AMOUNT"))?;
-
-    let timestamp = parts
-        .next()
-        .ok_or_else(|| invalid_record("missing TIMESTAMP"))?
-        .trim()
-        .parse::<u64>()
-        .map_err(|_| invalid_record("invalid TIMESTAMP"))?;
-
-    let status = parse_status(
-        parts
-            .next()
-            .ok_or_else(|| invalid_record("missing STATUS"))?
-            .trim(),
-    )?;
-
-    let description_raw = parts
-        .next()
-        .ok_or_else(|| invalid_record("missing DESCRIPTION"))?
-        .trim();
-    let description = parse_description(description_raw)?;
+/// Columns written for the records: the configured ones, then `CURRENCY`
+/// and extra fields if enabled.
+fn output_columns(records: &[YPBankRecord], options: &CsvOptions) -> (Vec<CsvColumn>, Vec<String>) {
+    let mut columns = options.columns.clone();
+    if !options.extra_columns {
+        return (columns, Vec::new());
+    }
+    let has_currency = records.iter().any(|r| r.currency.is_some()) || options.currency.is_some();
+    if has_currency && !columns.contains(&CsvColumn::Currency) {
+        columns.push(CsvColumn::Currency);
+    }
+    let extra_names: BTreeSet<&String> = records.iter().flat_map(|r| r.extra.keys()).collect();
+    (columns, extra_names.into_iter().cloned().collect())
+}
+
+/// Every data row carries numeric fields, while a header carries none.
+fn looks_like_header(fields: &[&str], dialect: &CsvDialect) -> bool {
+    !fields
+        .iter()
+        .any(|f| field_value(f, dialect).parse::<u64>().is_ok())
+}
+
+/// Meaning of a column position in the data
+#[derive(Debug, PartialEq)]
+enum Slot {
+    Column(CsvColumn),
+    Extra(String),
+    Ignored,
+}
+
+/// Maps each header position to its column.
+fn parse_header(fields: &[&str], options: &CsvOptions) -> Result<Vec<Slot>, ParserError> {
+    let mut layout = Vec::new();
+    for field in fields {
+        let name = field_value(field, &options.dialect);
+        let name = name.trim();
+        match CsvColumn::from_str(name) {
+            Ok(column) if layout.contains(&Slot::Column(column)) => {
+                return Err(invalid_record(&format!("duplicate CSV column: {}", name)));
+            }
+            Ok(column) => layout.push(Slot::Column(column)),
+            Err(_) if layout.contains(&Slot::Extra(name.to_string())) => {
+                return Err(invalid_record(&format!("duplicate CSV column: {}", name)));
+            }
+            Err(_) if is_extra_field_name(name) => layout.push(Slot::Extra(name.to_string())),
+            Err(_) if options.lenient => layout.push(Slot::Ignored),
+            Err(_) => {
+                return Err(invalid_record(&format!(
+                    "invalid CSV header: unknown column {}",
+                    name
+                )));
+            }
+        }
+    }
+    check_layout(&layout)?;
+    Ok(layout)
+}
+
+fn check_layout(layout: &[Slot]) -> Result<(), ParserError> {
+    for column in DEFAULT_COLUMNS {
+        if !layout.contains(&Slot::Column(column)) {
+            return Err(invalid_record(&format!(
+                "invalid CSV header: missing column {}",
+                column
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn parse_record(
+    values: &[&str],
+    layout: &[Slot],
+    options: &CsvOptions,
+) -> Result<YPBankRecord, ParserError> {
+    let dialect = &options.dialect;
+    if values.len() > layout.len() && !options.lenient {
+        return Err(invalid_record("too many fields"));
+    }
+
+    let mut fields = HashMap::new();
+    let mut extra = ExtraFields::new();
+    for (slot, value) in layout.iter().zip(values) {
+        match slot {
+            Slot::Column(column) => {
+                let value = if dialect.trim { value.trim() } else { value };
+                fields.insert(*column, value);
+            }
+            Slot::Extra(name) => {
+                let value = field_value(value, dialect);
+                if value.contains('\r') {
+                    return Err(invalid_record(&format!("invalid extra field: {}", name)));
+                }
+                if !value.is_empty() {
+                    extra.insert(name.clone(), value);
+                }
+            }
+            Slot::Ignored => {}
+        }
+    }
+    let field = |column: CsvColumn| {
+        fields
+            .get(&column)
+            .copied()
+            .ok_or_else(|| invalid_record(&format!("missing {}", column)))
+    };
+    let value = |column: CsvColumn| field(column).map(|f| field_value(f, dialect));
+
+    let tx_id = parse_u64(&value(CsvColumn::TxId)?, "TX_ID")?;
+    let tx_type = parse_tx_type(&value(CsvColumn::TxType)?)?;
+    let from_user_id = parse_u64(&value(CsvColumn::FromUserId)?, "FROM_USER_ID")?;
+    let to_user_id = parse_u64(&value(CsvColumn::ToUserId)?, "TO_USER_ID")?;
+    let currency = match fields.get(&CsvColumn::Currency) {
+        Some(code) if !field_value(code, dialect).is_empty() => Some(
+            Currency::from_code(&field_value(code, dialect))
+                .ok_or_else(|| invalid_record("invalid CURRENCY"))?,
+        ),
+        _ => options.currency,
+    };
+    let (amount, currency) = parse_amount(&value(CsvColumn::Amount)?, options.amount, currency)?;
+    let timestamp = Timestamp::parse(&value(CsvColumn::Timestamp)?, &options.timestamp)
+        .ok_or_else(|| invalid_record("invalid TIMESTAMP"))?;
+    let status = parse_status(&value(CsvColumn::Status)?)?;
+    let description = parse_description(field(CsvColumn::Description)?, dialect.quote)?;
+    options.limits.check_description(description.len())?;
 
     Ok(YPBankRecord {
         tx_id,
@@ -123,12 +485,47 @@ fn parse_record(line: &str) -> Result<YPBankRecord, ParserError> {
         from_user_id,
         to_user_id,
         amount,
+        currency,
         timestamp,
         status,
         description,
+        extra,
     })
 }
 
+/// Splits a line on the delimiter, ignoring delimiters inside quotes.
+fn split_fields<'a>(line: &'a str, dialect: &CsvDialect) -> Vec<&'a str> {
+    let mut fields = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        if c == dialect.quote {
+            quoted = !quoted;
+        } else if c == dialect.delimiter && !quoted {
+            fields.push(&line[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    fields.push(&line[start..]);
+    fields
+}
+
+/// Returns the field contents with enclosing quotes removed, if any.
+fn field_value(field: &str, dialect: &CsvDialect) -> String {
+    let field = if dialect.trim { field.trim() } else { field };
+    unquote(field, dialect.quote).unwrap_or_else(|| field.to_string())
+}
+
+fn unquote(s: &str, quote: char) -> Option<String> {
+    let inner = s.strip_prefix(quote)?.strip_suffix(quote)?;
+    Some(inner.replace(&quote.to_string().repeat(2), &quote.to_string()))
+}
+
+fn parse_u64(s: &str, name: &str) -> Result<u64, ParserError> {
+    s.parse::<u64>()
+        .map_err(|_| invalid_record(&format!("invalid {}", name)))
+}
+
 fn parse_tx_type(s: &str) -> Result<YPBankRecordType, ParserError> {
     YPBankRecordType::from_str(s).map_err(|_| invalid_record("invalid TX_TYPE"))
 }
@@ -137,28 +534,72 @@ fn parse_status(s: &str) -> Result<YPBankRecordStatus, ParserError> {
     YPBankRecordStatus::from_str(s).map_err(|_| invalid_record("invalid STATUS"))
 }
 
-fn parse_description(s: &str) -> Result<String, ParserError> {
-    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
-        Ok(s[1..s.len() - 1].to_string())
-    } else {
-        Err(invalid_record(
-            "DESCRIPTION must be enclosed in double quotes",
-        ))
+fn parse_description(s: &str, quote: char) -> Result<String, ParserError> {
+    let description = unquote(s, quote)
+        .ok_or_else(|| invalid_record("DESCRIPTION must be enclosed in quotes"))?;
+    // A line break could not be written back
+    if description.contains('\r') {
+        return Err(invalid_record("DESCRIPTION contains a line break"));
     }
+    Ok(description)
+}
+
+fn serialize_record(
+    record: &YPBankRecord,
+    columns: &[CsvColumn],
+    extra_names: &[String],
+    options: &CsvOptions,
+) -> Result<String, ParserError> {
+    let dialect = &options.dialect;
+    if record.description.contains(['\r', '\n']) {
+        return Err(invalid_record("DESCRIPTION contains a line break"));
+    }
+    let mut fields = Vec::with_capacity(columns.len() + extra_names.len());
+    for column in columns {
+        fields.push(match column {
+            CsvColumn::TxId => record.tx_id.to_string(),
+            CsvColumn::TxType => record.tx_type.to_string(),
+            CsvColumn::FromUserId => record.from_user_id.to_string(),
+            CsvColumn::ToUserId => record.to_user_id.to_string(),
+            CsvColumn::Amount => format_amount(
+                record.amount,
+                options.amount,
+                record.currency.or(options.currency),
+            )?,
+            CsvColumn::Timestamp => record
+                .timestamp
+                .format(&options.timestamp)
+                .map_err(|e| invalid_record(&e.to_string()))?,
+            CsvColumn::Status => record.status.to_string(),
+            CsvColumn::Description => quote(&record.description, dialect.quote),
+            CsvColumn::Currency => record
+                .currency
+                .or(options.currency)
+                .map(|c| c.to_string())
+                .unwrap_or_default(),
+        });
+    }
+    for name in extra_names {
+        let value = record.extra.get(name).map(String::as_str).unwrap_or("");
+        if !is_extra_field_name(name) || value.contains(['\r', '\n']) {
+            return Err(invalid_record(&format!("invalid extra field: {}", name)));
+        }
+        fields.push(quote_if_needed(value, dialect));
+    }
+    Ok(fields.join(&dialect.delimiter.to_string()))
+}
+
+fn quote(s: &str, quote: char) -> String {
+    let escaped = s.replace(quote, &quote.to_string().repeat(2));
+    format!("{quote}{escaped}{quote}")
 }
 
-fn serialize_record(record: &YPBankRecord) -> String {
-    format!(
-        "{},{},{},{},{},{},{},\"{}\"",
-        record.tx_id,
-        record.tx_type,
-        record.from_user_id,
-        record.to_user_id,
-        record.amount,
-        record.timestamp,
-        record.status,
-        record.description
-    )
+fn quote_if_needed(s: &str, dialect: &CsvDialect) -> String {
+    if s.contains([dialect.delimiter, dialect.quote]) || s.trim() != s {
+        quote(s, dialect.quote)
+    } else {
+        s.to_string()
+    }
 }
 
 fn invalid_record(msg: &str) -> ParserError {
@@ -177,6 +618,7 @@ fn io_error(e: std::io::Error) -> ParserError {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::timestamp::TimeUnit;
     use std::io::Cursor;
 
     fn sample_record() -> YPBankRecord {
@@ -186,9 +628,11 @@ mod tests {
             from_user_id: 1,
             to_user_id: 2,
             amount: 500,
-            timestamp: 1700000000,
+            currency: None,
+            timestamp: Timestamp::from_millis(1700000000),
             status: YPBankRecordStatus::SUCCESS,
             description: "test transfer".to_string(),
+            extra: ExtraFields::new(),
         }
     }
 
@@ -250,4 +694,291 @@ mod tests {
             result
         );
     }
+
+    #[test]
+    fn test_read_reordered_columns() {
+        let record = sample_record();
+        let text = concat!(
+            "DESCRIPTION,STATUS,TIMESTAMP,AMOUNT,TO_USER_ID,FROM_USER_ID,TX_TYPE,TX_ID\n",
+            "\"test transfer\",SUCCESS,1700000000,500,2,1,TRANSFER,43\n",
+        );
+        let mut cursor = Cursor::new(text);
+        let parsed = CsvParser::from_read(&mut cursor).expect("read failed");
+        assert_eq!(parsed.records(), &[record]);
+    }
+
+    #[test]
+    fn test_description_with_commas() {
+        let text = concat!(
+            "TX_ID,DESCRIPTION,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS\n",
+            "43,\"Payment for services, invoice #123\",TRANSFER,1,2,500,1700000000,SUCCESS\n",
+        );
+        let mut cursor = Cursor::new(text);
+        let parsed = CsvParser::from_read(&mut cursor).expect("read failed");
+        assert_eq!(
+            parsed.records()[0].description,
+            "Payment for services, invoice #123"
+        );
+    }
+
+    #[test]
+    fn test_rejects_values_that_cannot_be_written() {
+        let text = concat!(
+            "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n",
+            "43,TRANSFER,1,2,500,1700000000,SUCCESS,\"a\rb\"\n",
+        );
+        let result = CsvParser::from_read(&mut Cursor::new(text));
+        assert!(matches!(result, Err(ParserError::InvalidRecord { .. })));
+        let text = concat!(
+            "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION,CHANNEL\n",
+            "43,TRANSFER,1,2,500,1700000000,SUCCESS,\"ab\",a\rb\n",
+        );
+        let result = CsvParser::from_read(&mut Cursor::new(text));
+        assert!(matches!(result, Err(ParserError::InvalidRecord { .. })));
+    }
+
+    #[test]
+    fn test_unknown_column_strict_and_lenient() {
+        let text = concat!(
+            "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,CHANNEL ID,TIMESTAMP,STATUS,DESCRIPTION\n",
+            "43,TRANSFER,1,2,500,web,1700000000,SUCCESS,\"test transfer\"\n",
+        );
+        let result = CsvParser::from_read(&mut Cursor::new(text));
+        assert!(
+            matches!(result, Err(ParserError::InvalidRecord { .. })),
+            "expected InvalidRecord for unknown column, got: {:?}",
+            result
+        );
+
+        let options = CsvOptions {
+            lenient: true,
+            ..CsvOptions::default()
+        };
+        let parsed =
+            CsvParser::read_with_options(&mut Cursor::new(text), &options).expect("read failed");
+        assert_eq!(parsed.records(), &[sample_record()]);
+    }
+
+    #[test]
+    fn test_extra_columns_round_trip() {
+        let text = concat!(
+            "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,CHANNEL,TIMESTAMP,STATUS,DESCRIPTION\n",
+            "43,TRANSFER,1,2,500,web,1700000000,SUCCESS,\"test transfer\"\n",
+        );
+        let parsed = CsvParser::from_read(&mut Cursor::new(text)).expect("read failed");
+        let mut expected = sample_record();
+        expected.extra.insert("CHANNEL".into(), "web".into());
+        assert_eq!(parsed.records(), &[expected.clone()]);
+
+        let mut other = sample_record();
+        other.tx_id = 44;
+        other.extra.insert("REFERENCE".into(), "a, b".into());
+        let mut storage = YPBankStorage::new();
+        storage.push(expected.clone());
+        storage.push(other.clone());
+
+        let mut buf = Vec::new();
+        CsvParser::from_storage(storage)
+            .write_to(&mut buf)
+            .expect("write failed");
+        assert_eq!(
+            String::from_utf8(buf.clone()).unwrap(),
+            concat!(
+                "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION,CHANNEL,REFERENCE\n",
+                "43,TRANSFER,1,2,500,1700000000,SUCCESS,\"test transfer\",web,\n",
+                "44,TRANSFER,1,2,500,1700000000,SUCCESS,\"test transfer\",,\"a, b\"\n",
+            )
+        );
+        let reparsed = CsvParser::from_read(&mut Cursor::new(buf)).expect("read failed");
+        assert_eq!(reparsed.records(), &[expected, other]);
+    }
+
+    #[test]
+    fn test_write_selected_columns() {
+        let mut storage = YPBankStorage::new();
+        storage.push(sample_record());
+        let options = CsvOptions {
+            columns: parse_columns("TX_ID,AMOUNT,STATUS").expect("invalid columns"),
+            ..CsvOptions::default()
+        };
+
+        let mut buf = Vec::new();
+        let mut parser = CsvParser::with_options(storage, options);
+        parser.write_to(&mut buf).expect("write failed");
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "TX_ID,AMOUNT,STATUS\n43,500,SUCCESS\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_columns_rejects_unknown_and_duplicates() {
+        assert!(parse_columns("TX_ID,FOO").is_err());
+        assert!(parse_columns("TX_ID,TX_ID").is_err());
+    }
+
+    #[test]
+    fn test_read_semicolon_dialect_with_bom() {
+        let text = concat!(
+            "\u{feff}TX_ID;TX_TYPE;FROM_USER_ID;TO_USER_ID;AMOUNT;TIMESTAMP;STATUS;DESCRIPTION\r\n",
+            "43;TRANSFER;1;2;500;1700000000;SUCCESS;'test transfer'\r\n",
+        );
+        let options = CsvOptions {
+            dialect: CsvDialect {
+                delimiter: ';',
+                quote: '\'',
+                ..CsvDialect::default()
+            },
+            ..CsvOptions::default()
+        };
+        let parsed =
+            CsvParser::read_with_options(&mut Cursor::new(text), &options).expect("read failed");
+        assert_eq!(parsed.records(), &[sample_record()]);
+    }
+
+    #[test]
+    fn test_header_absent_and_auto() {
+        let text = "43,TRANSFER,1,2,500,1700000000,SUCCESS,\"test transfer\"\n";
+        for header in [CsvHeader::Absent, CsvHeader::Auto] {
+            let mut options = CsvOptions::default();
+            options.dialect.header = header;
+            let parsed = CsvParser::read_with_options(&mut Cursor::new(text), &options)
+                .expect("read failed");
+            assert_eq!(parsed.records(), &[sample_record()]);
+        }
+
+        let result = CsvParser::from_read(&mut Cursor::new(text));
+        assert!(
+            matches!(result, Err(ParserError::InvalidRecord { .. })),
+            "expected InvalidRecord for missing header, got: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_write_dialect_round_trip() {
+        let mut record = sample_record();
+        record.description = "say \"hi\"; then leave".to_string();
+        let mut storage = YPBankStorage::new();
+        storage.push(record.clone());
+        let options = CsvOptions {
+            dialect: CsvDialect {
+                delimiter: ';',
+                header: CsvHeader::Absent,
+                bom: true,
+                line_terminator: CsvLineTerminator::Crlf,
+                ..CsvDialect::default()
+            },
+            ..CsvOptions::default()
+        };
+
+        let mut buf = Vec::new();
+        let mut parser = CsvParser::with_options(storage, options.clone());
+        parser.write_to(&mut buf).expect("write failed");
+        assert_eq!(
+            String::from_utf8(buf.clone()).unwrap(),
+            "\u{feff}43;TRANSFER;1;2;500;1700000000;SUCCESS;\"say \"\"hi\"\"; then leave\"\r\n"
+        );
+
+        let parsed =
+            CsvParser::read_with_options(&mut Cursor::new(buf), &options).expect("read failed");
+        assert_eq!(parsed.records(), &[record]);
+    }
+
+    #[test]
+    fn test_timestamp_formats() {
+        let text = concat!(
+            "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n",
+            "43,TRANSFER,1,2,500,1700000,SUCCESS,\"test transfer\"\n",
+        );
+        let options = CsvOptions {
+            timestamp: TimestampFormat::Unix(TimeUnit::Seconds),
+            ..CsvOptions::default()
+        };
+        let parsed =
+            CsvParser::read_with_options(&mut Cursor::new(text), &options).expect("read failed");
+        assert_eq!(parsed.records(), &[sample_record()]);
+
+        let options = CsvOptions {
+            columns: parse_columns("TX_ID,TIMESTAMP").unwrap(),
+            timestamp: TimestampFormat::from_str("iso8601").unwrap(),
+            ..CsvOptions::default()
+        };
+        let mut buf = Vec::new();
+        let mut parser = CsvParser::with_options(parsed, options);
+        parser.write_to(&mut buf).expect("write failed");
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "TX_ID,TIMESTAMP\n43,1970-01-20T16:13:20Z\n"
+        );
+    }
+
+    #[test]
+    fn test_currency_column_and_decimal_amounts() {
+        let eur = Currency::from_code("EUR");
+        let text = concat!(
+            "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION,CURRENCY\n",
+            "43,TRANSFER,1,2,500,1700000000,SUCCESS,\"test transfer\",EUR\n",
+            "44,TRANSFER,1,2,500,1700000000,SUCCESS,\"test transfer\",\n",
+        );
+        let parsed = CsvParser::from_read(&mut Cursor::new(text)).expect("read failed");
+        assert_eq!(parsed.records()[0].currency, eur);
+        assert_eq!(parsed.records()[1].currency, None);
+
+        let options = CsvOptions {
+            columns: parse_columns("TX_ID,AMOUNT").unwrap(),
+            extra_columns: false,
+            amount: AmountFormat::Decimal,
+            currency: Currency::from_code("JPY"),
+            ..CsvOptions::default()
+        };
+        let mut buf = Vec::new();
+        let mut parser = CsvParser::with_options(parsed, options);
+        parser.write_to(&mut buf).expect("write failed");
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "TX_ID,AMOUNT\n43,5.00 EUR\n44,500 JPY\n"
+        );
+    }
+
+    #[test]
+    fn test_read_decimal_amount() {
+        let text = concat!(
+            "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n",
+            "43,TRANSFER,1,2,5.00 EUR,1700000000,SUCCESS,\"test transfer\"\n",
+        );
+        let options = CsvOptions {
+            amount: AmountFormat::Decimal,
+            ..CsvOptions::default()
+        };
+        let parsed =
+            CsvParser::read_with_options(&mut Cursor::new(text), &options).expect("read failed");
+        let mut expected = sample_record();
+        expected.currency = Currency::from_code("EUR");
+        assert_eq!(parsed.records(), &[expected]);
+    }
+
+    #[test]
+    fn test_line_limit() {
+        // A line without a newline is not buffered beyond the limit
+        let mut data = DEFAULT_COLUMNS
+            .map(|c| c.to_string())
+            .join(",")
+            .into_bytes();
+        data.push(b'\n');
+        data.extend(std::iter::repeat_n(b'1', 1 << 16));
+        let options = CsvOptions {
+            limits: ParseLimits {
+                max_line_len: 1024,
+                ..ParseLimits::default()
+            },
+            ..CsvOptions::default()
+        };
+        let result = CsvParser::read_with_options(&mut Cursor::new(data), &options);
+        assert!(matches!(
+            result,
+            Err(ParserError::LineTooLong { max: 1024 })
+        ));
+    }
 }
@@ -0,0 +1,248 @@
+//! Adapter reading and writing YPBank records in any serde data format.
+
+use crate::error::ParserError;
+use crate::parser::{ParseLimits, Parser};
+use crate::storage::{YPBankRecord, YPBankStorage, is_extra_field_name};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+
+/// A serde data format that holds a sequence of records
+///
+/// Implement it to plug another format into [`SerdeParser`].
+pub trait SerdeFormat {
+    /// Name of the format in error messages, e.g. `JSON`
+    const NAME: &'static str;
+
+    /// Deserializes a value from the reader
+    fn from_reader<T: DeserializeOwned, R: Read>(r: R) -> Result<T, String>;
+
+    /// Serializes a value to the writer
+    fn to_writer<T: Serialize, W: Write>(value: &T, w: W) -> Result<(), String>;
+}
+
+/// Options controlling how serde data is read.
+#[derive(Debug, Clone, Default)]
+pub struct SerdeOptions {
+    /// Bounds on the records; the input itself is deserialized as a whole.
+    pub limits: ParseLimits,
+}
+
+/// Parser for records in the serde data format `F`.
+pub struct SerdeParser<F> {
+    /// In-memory storage populated after parsing.
+    pub storage: YPBankStorage,
+    format: PhantomData<F>,
+}
+
+impl<F: SerdeFormat> SerdeParser<F> {
+    /// Reads data from reader using the given options
+    pub fn read_with_options<R: Read>(
+        r: &mut R,
+        options: &SerdeOptions,
+    ) -> Result<YPBankStorage, ParserError> {
+        let limits = &options.limits;
+        let records: Vec<YPBankRecord> = F::from_reader(r).map_err(serde_error::<F>)?;
+        let mut storage = YPBankStorage::new();
+        for record in records {
+            limits.check_description(record.description.len())?;
+            if let Some(key) = record.extra.keys().find(|k| !is_extra_field_name(k)) {
+                return Err(ParserError::InvalidRecord {
+                    message: format!("invalid field: {}", key),
+                });
+            }
+            limits.push(&mut storage, record)?;
+        }
+        Ok(storage)
+    }
+}
+
+impl<F: SerdeFormat> Parser for SerdeParser<F> {
+    fn from_read<R: Read>(r: &mut R) -> Result<YPBankStorage, ParserError> {
+        Self::read_with_options(r, &SerdeOptions::default())
+    }
+
+    fn write_to<W: Write>(&mut self, w: &mut W) -> Result<(), ParserError> {
+        F::to_writer(&self.storage, w).map_err(serde_error::<F>)
+    }
+
+    fn from_storage(storage: YPBankStorage) -> Self {
+        Self {
+            storage,
+            format: PhantomData,
+        }
+    }
+}
+
+fn serde_error<F: SerdeFormat>(message: String) -> ParserError {
+    ParserError::Serde {
+        format: F::NAME,
+        message,
+    }
+}
+
+/// JSON: an array of record objects.
+#[cfg(feature = "json")]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl SerdeFormat for Json {
+    const NAME: &'static str = "JSON";
+
+    fn from_reader<T: DeserializeOwned, R: Read>(r: R) -> Result<T, String> {
+        serde_json::from_reader(r).map_err(|e| e.to_string())
+    }
+
+    fn to_writer<T: Serialize, W: Write>(value: &T, mut w: W) -> Result<(), String> {
+        serde_json::to_writer_pretty(&mut w, value).map_err(|e| e.to_string())?;
+        w.write_all(b"\n").map_err(|e| e.to_string())
+    }
+}
+
+/// YAML: a sequence of record mappings.
+#[cfg(feature = "yaml")]
+pub struct Yaml;
+
+#[cfg(feature = "yaml")]
+impl SerdeFormat for Yaml {
+    const NAME: &'static str = "YAML";
+
+    fn from_reader<T: DeserializeOwned, R: Read>(r: R) -> Result<T, String> {
+        serde_yaml_ng::from_reader(r).map_err(|e| e.to_string())
+    }
+
+    fn to_writer<T: Serialize, W: Write>(value: &T, w: W) -> Result<(), String> {
+        serde_yaml_ng::to_writer(w, value).map_err(|e| e.to_string())
+    }
+}
+
+/// MessagePack: an array of maps keyed by field name.
+#[cfg(feature = "msgpack")]
+pub struct MessagePack;
+
+#[cfg(feature = "msgpack")]
+impl SerdeFormat for MessagePack {
+    const NAME: &'static str = "MessagePack";
+
+    fn from_reader<T: DeserializeOwned, R: Read>(r: R) -> Result<T, String> {
+        rmp_serde::from_read(r).map_err(|e| e.to_string())
+    }
+
+    fn to_writer<T: Serialize, W: Write>(value: &T, mut w: W) -> Result<(), String> {
+        rmp_serde::encode::write_named(&mut w, value).map_err(|e| e.to_string())
+    }
+}
+
+/// CBOR: an array of maps keyed by field name.
+#[cfg(feature = "cbor")]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl SerdeFormat for Cbor {
+    const NAME: &'static str = "CBOR";
+
+    fn from_reader<T: DeserializeOwned, R: Read>(r: R) -> Result<T, String> {
+        ciborium::from_reader(r).map_err(|e| e.to_string())
+    }
+
+    fn to_writer<T: Serialize, W: Write>(value: &T, w: W) -> Result<(), String> {
+        ciborium::into_writer(value, w).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(all(
+    test,
+    any(
+        feature = "json",
+        feature = "yaml",
+        feature = "msgpack",
+        feature = "cbor"
+    )
+))]
+mod tests {
+    #[cfg(feature = "json")]
+    use super::*;
+    use crate::storage::sample_storage;
+    #[cfg(feature = "json")]
+    use crate::timestamp::Timestamp;
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_field_names_match_csv_header() {
+        let mut records = sample_storage().into_records();
+        records[0].tx_id = u64::MAX;
+        let mut storage = YPBankStorage::new();
+        storage.extend(records);
+        let mut data = Vec::new();
+        SerdeParser::<Json>::from_storage(storage)
+            .write_to(&mut data)
+            .unwrap();
+        let json = String::from_utf8(data).unwrap();
+        for name in [
+            "\"TX_ID\": 18446744073709551615",
+            "\"TX_TYPE\": \"TRANSFER\"",
+            "\"FROM_USER_ID\": 1",
+            "\"TO_USER_ID\": 2",
+            "\"AMOUNT\": 1500",
+            "\"CURRENCY\": \"EUR\"",
+            "\"TIMESTAMP\": 1700000000000",
+            "\"STATUS\": \"PENDING\"",
+            "\"CHANNEL\": \"mobile\"",
+        ] {
+            assert!(json.contains(name), "{} in {}", name, json);
+        }
+        assert_eq!(json.matches("CURRENCY").count(), 1);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_timestamp_and_validation() {
+        let json = r#"[{"TX_ID": 1, "TX_TYPE": "DEPOSIT", "FROM_USER_ID": 0,
+            "TO_USER_ID": 5, "AMOUNT": 10, "TIMESTAMP": "2023-11-14T22:13:20Z",
+            "STATUS": "SUCCESS", "DESCRIPTION": "", "REFERENCE": "r-1"}]"#;
+        let storage = SerdeParser::<Json>::from_read(&mut json.as_bytes()).unwrap();
+        let record = &storage.records()[0];
+        assert_eq!(record.timestamp, Timestamp::from_millis(1700000000000));
+        assert_eq!(record.extra["REFERENCE"], "r-1");
+
+        let invalid = json.replace("REFERENCE", "MERCHANT ID");
+        let error = SerdeParser::<Json>::from_read(&mut invalid.as_bytes()).unwrap_err();
+        assert!(error.to_string().contains("invalid field: MERCHANT ID"));
+
+        let invalid = json.replace("DEPOSIT", "REFUND");
+        let error = SerdeParser::<Json>::from_read(&mut invalid.as_bytes()).unwrap_err();
+        assert!(matches!(error, ParserError::Serde { format: "JSON", .. }));
+
+        let options = SerdeOptions {
+            limits: ParseLimits {
+                max_records: 0,
+                ..ParseLimits::default()
+            },
+        };
+        let error = SerdeParser::<Json>::read_with_options(&mut json.as_bytes(), &options);
+        assert!(matches!(error, Err(ParserError::TooManyRecords { max: 0 })));
+    }
+
+    #[test]
+    fn test_enabled_formats_round_trip() {
+        use crate::format::{Format, FormatOptions};
+        let formats = [
+            #[cfg(feature = "json")]
+            Format::Json,
+            #[cfg(feature = "yaml")]
+            Format::Yaml,
+            #[cfg(feature = "msgpack")]
+            Format::Msgpack,
+            #[cfg(feature = "cbor")]
+            Format::Cbor,
+        ];
+        let options = FormatOptions::default();
+        for format in formats {
+            let mut data = Vec::new();
+            format.write(sample_storage(), &mut data, &options).unwrap();
+            let storage = format.read(&mut data.as_slice(), &options).unwrap();
+            assert_eq!(storage.records(), sample_storage().records(), "{}", format);
+        }
+    }
+}
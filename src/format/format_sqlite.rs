@@ -0,0 +1,401 @@
+//! SQLite database backend for YPBank records.
+
+use crate::currency::Currency;
+use crate::error::ParserError;
+use crate::parser::{ParseLimits, Parser};
+use crate::storage::{ExtraFields, YPBankRecord, YPBankStorage, is_extra_field_name};
+use crate::timestamp::{TimeUnit, Timestamp};
+use rusqlite::{Connection, params};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+use strum_macros::{Display, EnumString};
+
+/// Tables and indexes, created if missing
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS transactions (
+    id INTEGER PRIMARY KEY,
+    tx_id INTEGER NOT NULL,
+    tx_type TEXT NOT NULL,
+    from_user_id INTEGER NOT NULL,
+    to_user_id INTEGER NOT NULL,
+    amount INTEGER NOT NULL,
+    currency TEXT,
+    timestamp INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    description TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS transactions_tx_id ON transactions (tx_id);
+CREATE INDEX IF NOT EXISTS transactions_from_user_id ON transactions (from_user_id);
+CREATE INDEX IF NOT EXISTS transactions_to_user_id ON transactions (to_user_id);
+CREATE INDEX IF NOT EXISTS transactions_timestamp ON transactions (timestamp);
+CREATE TABLE IF NOT EXISTS transaction_extra (
+    transaction_id INTEGER NOT NULL REFERENCES transactions (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (transaction_id, name)
+);
+";
+
+/// How records are written into a database that already holds transactions.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum SqliteMode {
+    /// Replace all stored transactions
+    #[default]
+    Replace,
+    /// Add the records after the stored ones
+    Append,
+    /// Replace the stored transactions with the same `TX_ID` and add the rest
+    Upsert,
+}
+
+/// Options controlling how SQLite data is read.
+#[derive(Debug, Clone, Default)]
+pub struct SqliteOptions {
+    /// Bounds on the records; the database image is loaded as a whole.
+    pub limits: ParseLimits,
+}
+
+/// A SQLite database with a `transactions` table.
+///
+/// Numbers are stored as SQLite `INTEGER`, so values above `i64::MAX` are
+/// rejected, and timestamps are stored in milliseconds, so timestamps with
+/// microseconds are rejected too. Extra fields live in
+/// `transaction_extra`, keyed by the `id` of the transaction row.
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    /// Opens or creates a database file and its tables
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, ParserError> {
+        Self::init(Connection::open(path).map_err(sqlite_error)?)
+    }
+
+    /// Creates an empty in-memory database
+    pub fn in_memory() -> Result<Self, ParserError> {
+        Self::init(Connection::open_in_memory().map_err(sqlite_error)?)
+    }
+
+    /// Loads a database image, e.g. the contents of a database file, into memory
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ParserError> {
+        let mut conn = Connection::open_in_memory().map_err(sqlite_error)?;
+        // An empty file is an empty database
+        if !data.is_empty() {
+            conn.deserialize_read_exact("main", data, data.len(), false)
+                .map_err(sqlite_error)?;
+        }
+        Self::init(conn)
+    }
+
+    fn init(conn: Connection) -> Result<Self, ParserError> {
+        // Enforces `transaction_extra.transaction_id`, deleting extra fields
+        // together with their transaction
+        conn.pragma_update(None, "foreign_keys", true)
+            .map_err(sqlite_error)?;
+        conn.execute_batch(SCHEMA).map_err(sqlite_error)?;
+        Ok(Self { conn })
+    }
+
+    /// The database image, as it would be stored in a file
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ParserError> {
+        let data = self.conn.serialize("main").map_err(sqlite_error)?;
+        Ok(data.to_vec())
+    }
+
+    /// Reads all transactions in the order they were written
+    pub fn read(&self, limits: &ParseLimits) -> Result<YPBankStorage, ParserError> {
+        let mut extras: HashMap<i64, ExtraFields> = HashMap::new();
+        let mut query = self
+            .conn
+            .prepare("SELECT transaction_id, name, value FROM transaction_extra")
+            .map_err(sqlite_error)?;
+        let mut rows = query.query([]).map_err(sqlite_error)?;
+        while let Some(row) = rows.next().map_err(sqlite_error)? {
+            let name: String = row.get(1).map_err(sqlite_error)?;
+            if !is_extra_field_name(&name) {
+                return Err(invalid_record(&format!("invalid field: {}", name)));
+            }
+            extras
+                .entry(row.get(0).map_err(sqlite_error)?)
+                .or_default()
+                .insert(name, row.get(2).map_err(sqlite_error)?);
+        }
+
+        let mut storage = YPBankStorage::new();
+        let mut query = self
+            .conn
+            .prepare(
+                "SELECT id, tx_id, tx_type, from_user_id, to_user_id, amount, currency, \
+                 timestamp, status, description FROM transactions ORDER BY id",
+            )
+            .map_err(sqlite_error)?;
+        let mut rows = query.query([]).map_err(sqlite_error)?;
+        while let Some(row) = rows.next().map_err(sqlite_error)? {
+            let integer = |index, name| {
+                let value: i64 = row.get(index).map_err(sqlite_error)?;
+                u64::try_from(value).map_err(|_| invalid_record(&format!("invalid {}", name)))
+            };
+            let text = |index| row.get::<_, String>(index).map_err(sqlite_error);
+            let currency = match row.get::<_, Option<String>>(6).map_err(sqlite_error)? {
+                Some(code) => Some(
+                    Currency::from_code(&code).ok_or_else(|| invalid_record("invalid CURRENCY"))?,
+                ),
+                None => None,
+            };
+            let description = text(9)?;
+            limits.check_description(description.len())?;
+            let id: i64 = row.get(0).map_err(sqlite_error)?;
+            let record = YPBankRecord {
+                tx_id: integer(1, "TX_ID")?,
+                tx_type: FromStr::from_str(&text(2)?)
+                    .map_err(|_| invalid_record("invalid TX_TYPE"))?,
+                from_user_id: integer(3, "FROM_USER_ID")?,
+                to_user_id: integer(4, "TO_USER_ID")?,
+                amount: integer(5, "AMOUNT")?,
+                currency,
+                timestamp: Timestamp::from_millis(integer(7, "TIMESTAMP")?),
+                status: FromStr::from_str(&text(8)?)
+                    .map_err(|_| invalid_record("invalid STATUS"))?,
+                description,
+                extra: extras.remove(&id).unwrap_or_default(),
+            };
+            limits.push(&mut storage, record)?;
+        }
+        Ok(storage)
+    }
+
+    /// Writes the records in one transaction, as selected by `mode`
+    pub fn write(&mut self, storage: &YPBankStorage, mode: SqliteMode) -> Result<(), ParserError> {
+        let tx = self.conn.transaction().map_err(sqlite_error)?;
+        if mode == SqliteMode::Replace {
+            tx.execute("DELETE FROM transactions", [])
+                .map_err(sqlite_error)?;
+        }
+        {
+            let mut remove = tx
+                .prepare("DELETE FROM transactions WHERE tx_id = ?1")
+                .map_err(sqlite_error)?;
+            let mut insert = tx
+                .prepare(
+                    "INSERT INTO transactions (tx_id, tx_type, from_user_id, to_user_id, \
+                     amount, currency, timestamp, status, description) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                )
+                .map_err(sqlite_error)?;
+            let mut insert_extra = tx
+                .prepare(
+                    "INSERT INTO transaction_extra (transaction_id, name, value) \
+                     VALUES (?1, ?2, ?3)",
+                )
+                .map_err(sqlite_error)?;
+
+            for record in storage.records() {
+                let tx_id = to_integer(record.tx_id, "TX_ID")?;
+                let timestamp = record
+                    .timestamp
+                    .to_unix_exact(TimeUnit::Millis)
+                    .map_err(|e| invalid_record(&e.to_string()))?;
+                if mode == SqliteMode::Upsert {
+                    remove.execute([tx_id]).map_err(sqlite_error)?;
+                }
+                let id = insert
+                    .insert(params![
+                        tx_id,
+                        record.tx_type.to_string(),
+                        to_integer(record.from_user_id, "FROM_USER_ID")?,
+                        to_integer(record.to_user_id, "TO_USER_ID")?,
+                        to_integer(record.amount, "AMOUNT")?,
+                        record.currency.map(|c| c.code()),
+                        to_integer(timestamp, "TIMESTAMP")?,
+                        record.status.to_string(),
+                        record.description,
+                    ])
+                    .map_err(sqlite_error)?;
+                for (name, value) in &record.extra {
+                    if !is_extra_field_name(name) {
+                        return Err(invalid_record(&format!("invalid extra field: {}", name)));
+                    }
+                    insert_extra
+                        .execute(params![id, name, value])
+                        .map_err(sqlite_error)?;
+                }
+            }
+        }
+        tx.commit().map_err(sqlite_error)
+    }
+}
+
+/// Parser for SQLite database images, used as the `sqlite` format.
+pub struct SqliteParser {
+    /// In-memory storage populated after parsing.
+    pub storage: YPBankStorage,
+}
+
+impl SqliteParser {
+    /// Reads data from reader using the given options
+    pub fn read_with_options<R: Read>(
+        r: &mut R,
+        options: &SqliteOptions,
+    ) -> Result<YPBankStorage, ParserError> {
+        let mut data = Vec::new();
+        r.read_to_end(&mut data).map_err(io_error)?;
+        SqliteStore::from_bytes(&data)?.read(&options.limits)
+    }
+}
+
+impl Parser for SqliteParser {
+    fn from_read<R: Read>(r: &mut R) -> Result<YPBankStorage, ParserError> {
+        Self::read_with_options(r, &SqliteOptions::default())
+    }
+
+    fn write_to<W: Write>(&mut self, w: &mut W) -> Result<(), ParserError> {
+        let mut store = SqliteStore::in_memory()?;
+        store.write(&self.storage, SqliteMode::Replace)?;
+        w.write_all(&store.to_bytes()?).map_err(io_error)
+    }
+
+    fn from_storage(storage: YPBankStorage) -> Self {
+        Self { storage }
+    }
+}
+
+fn to_integer(value: u64, name: &str) -> Result<i64, ParserError> {
+    i64::try_from(value).map_err(|_| invalid_record(&format!("{} out of range", name)))
+}
+
+fn sqlite_error(e: rusqlite::Error) -> ParserError {
+    ParserError::Sqlite {
+        message: e.to_string(),
+    }
+}
+
+fn invalid_record(msg: &str) -> ParserError {
+    ParserError::InvalidRecord {
+        message: msg.to_string(),
+    }
+}
+
+fn io_error(e: std::io::Error) -> ParserError {
+    ParserError::IO {
+        message: e.to_string(),
+        error: e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{YPBankRecordStatus, sample_storage};
+
+    #[test]
+    fn test_round_trip_through_bytes() {
+        let mut data = Vec::new();
+        SqliteParser::from_storage(sample_storage())
+            .write_to(&mut data)
+            .unwrap();
+        let storage = SqliteParser::from_read(&mut data.as_slice()).unwrap();
+        assert_eq!(storage.records(), sample_storage().records());
+
+        let empty = SqliteParser::from_read(&mut &b""[..]).unwrap();
+        assert!(empty.records().is_empty());
+    }
+
+    #[test]
+    fn test_write_modes() {
+        let mut store = SqliteStore::in_memory().unwrap();
+        store.write(&sample_storage(), SqliteMode::Replace).unwrap();
+        store.write(&sample_storage(), SqliteMode::Append).unwrap();
+        assert_eq!(
+            store.read(&ParseLimits::default()).unwrap().records().len(),
+            4
+        );
+
+        let mut update = YPBankStorage::new();
+        let mut record = sample_storage().records()[0].clone();
+        record.status = YPBankRecordStatus::SUCCESS;
+        record.extra.clear();
+        update.push(record.clone());
+        store.write(&update, SqliteMode::Upsert).unwrap();
+        let storage = store.read(&ParseLimits::default()).unwrap();
+        let records = storage.records();
+        assert_eq!(records.len(), 3);
+        assert!(records[..2].iter().all(|r| r.tx_id == 2));
+        assert_eq!(records[2], record);
+
+        store.write(&update, SqliteMode::Replace).unwrap();
+        assert_eq!(
+            store.read(&ParseLimits::default()).unwrap().records(),
+            [record]
+        );
+    }
+
+    #[test]
+    fn test_replace_deletes_extra_fields() {
+        let mut store = SqliteStore::in_memory().unwrap();
+        store.write(&sample_storage(), SqliteMode::Replace).unwrap();
+        store
+            .write(&YPBankStorage::new(), SqliteMode::Replace)
+            .unwrap();
+        let count: i64 = store
+            .conn
+            .query_row("SELECT COUNT(*) FROM transaction_extra", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_sub_millisecond_timestamps_are_rejected() {
+        let mut storage = sample_storage();
+        let mut record = storage.records()[0].clone();
+        record.timestamp = Timestamp::from_unix(1700000000000001, TimeUnit::Micros);
+        storage.push(record);
+        let mut store = SqliteStore::in_memory().unwrap();
+        let error = store.write(&storage, SqliteMode::Append).unwrap_err();
+        assert!(error.to_string().contains("sub-millisecond"));
+    }
+
+    #[test]
+    fn test_schema_has_indexes() {
+        let store = SqliteStore::in_memory().unwrap();
+        let mut query = store
+            .conn
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'index' AND tbl_name = ?1")
+            .unwrap();
+        let names: Vec<String> = query
+            .query_map(["transactions"], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        for index in ["tx_id", "from_user_id", "to_user_id", "timestamp"] {
+            assert!(
+                names.contains(&format!("transactions_{}", index)),
+                "{}",
+                index
+            );
+        }
+    }
+
+    #[test]
+    fn test_out_of_range_values_are_rejected() {
+        let mut storage = sample_storage();
+        let mut record = storage.records()[0].clone();
+        record.amount = u64::MAX;
+        storage.push(record);
+        let mut store = SqliteStore::in_memory().unwrap();
+        let error = store.write(&storage, SqliteMode::Append).unwrap_err();
+        assert!(error.to_string().contains("AMOUNT out of range"));
+        // The failed write leaves the database untouched
+        assert!(
+            store
+                .read(&ParseLimits::default())
+                .unwrap()
+                .records()
+                .is_empty()
+        );
+    }
+}
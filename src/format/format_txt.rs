@@ -1,66 +1,128 @@
 //! Plain-text key-value format parser for YPBank records.
 
+use crate::currency::{AmountFormat, Currency, format_amount, parse_amount};
 use crate::error::ParserError;
-use crate::parser::Parser;
-use crate::storage::{YPBankRecord, YPBankRecordStatus, YPBankRecordType, YPBankStorage};
+use crate::parser::{ParseLimits, Parser};
+use crate::storage::{
+    ExtraFields, YPBankRecord, YPBankRecordStatus, YPBankRecordType, YPBankStorage,
+    is_extra_field_name,
+};
+use crate::timestamp::{Timestamp, TimestampFormat};
 use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Read, Write};
+use std::io::{BufReader, Read, Write};
 use std::str::FromStr;
 
+/// Options controlling how TXT data is read and written.
+#[derive(Debug, Clone, Default)]
+pub struct TxtOptions {
+    /// Representation of the `TIMESTAMP` field.
+    pub timestamp: TimestampFormat,
+    /// Representation of the `AMOUNT` field.
+    pub amount: AmountFormat,
+    /// Currency of records that do not specify one.
+    pub currency: Option<Currency>,
+    /// Bounds on the input.
+    pub limits: ParseLimits,
+}
+
 /// Parser for the plain-text key-value record format.
 pub struct TxtParser {
     /// In-memory storage populated after parsing.
     pub storage: YPBankStorage,
+    options: TxtOptions,
 }
 
-impl Parser for TxtParser {
-    fn from_read<R: Read>(r: &mut R) -> Result<YPBankStorage, ParserError> {
+impl TxtParser {
+    /// Creates new parser from storage with the given options
+    pub fn with_options(storage: YPBankStorage, options: TxtOptions) -> Self {
+        Self { storage, options }
+    }
+
+    /// Reads data from reader using the given options
+    pub fn read_with_options<R: Read>(
+        r: &mut R,
+        options: &TxtOptions,
+    ) -> Result<YPBankStorage, ParserError> {
+        let limits = &options.limits;
+        let mut decoder = TxtDecoder::new(options.clone());
         let mut storage = YPBankStorage::new();
-        let reader = BufReader::new(r);
-        let mut fields: HashMap<String, String> = HashMap::new();
+        let mut reader = BufReader::new(r);
+        while let Some(line) = limits.read_line(&mut reader)? {
+            if let Some(record) = decoder.decode_line(&line)? {
+                limits.push(&mut storage, record)?;
+            }
+        }
+        if let Some(record) = decoder.finish()? {
+            limits.push(&mut storage, record)?;
+        }
+        Ok(storage)
+    }
+}
 
-        for line_result in reader.lines() {
-            let line = line_result.map_err(io_error)?.trim().to_string();
+/// Line-by-line TXT decoding shared by the sync and async readers
+pub(crate) struct TxtDecoder {
+    options: TxtOptions,
+    fields: HashMap<String, String>,
+    record_size: usize,
+}
 
-            if line.starts_with('#') {
-                continue;
-            }
+impl TxtDecoder {
+    pub(crate) fn new(options: TxtOptions) -> Self {
+        Self {
+            options,
+            fields: HashMap::new(),
+            record_size: 0,
+        }
+    }
 
-            if line.is_empty() {
-                if !fields.is_empty() {
-                    storage.push(build_record(&mut fields)?);
-                }
-                continue;
-            }
+    /// Decodes a line without its terminator; a record is complete at the
+    /// empty line that ends its block
+    pub(crate) fn decode_line(&mut self, line: &str) -> Result<Option<YPBankRecord>, ParserError> {
+        let line = line.trim();
 
-            let (key, value) = parse_key_value(&line)?;
-            if fields.contains_key(key) {
-                return Err(invalid_record(&format!("duplicate field: {}", key)));
-            }
-            fields.insert(key.to_string(), value.to_string());
+        if line.starts_with('#') {
+            return Ok(None);
         }
 
-        if !fields.is_empty() {
-            storage.push(build_record(&mut fields)?);
+        if line.is_empty() {
+            return self.finish();
         }
 
-        Ok(storage)
+        self.record_size += line.len() + 1;
+        self.options.limits.check_record_size(self.record_size)?;
+        let (key, value) = parse_key_value(line)?;
+        if self.fields.contains_key(key) {
+            return Err(invalid_record(&format!("duplicate field: {}", key)));
+        }
+        self.fields.insert(key.to_string(), value.to_string());
+        Ok(None)
+    }
+
+    /// Decodes the pending record block, if any
+    pub(crate) fn finish(&mut self) -> Result<Option<YPBankRecord>, ParserError> {
+        if self.fields.is_empty() {
+            return Ok(None);
+        }
+        self.record_size = 0;
+        build_record(&mut self.fields, &self.options).map(Some)
+    }
+}
+
+impl Parser for TxtParser {
+    fn from_read<R: Read>(r: &mut R) -> Result<YPBankStorage, ParserError> {
+        Self::read_with_options(r, &TxtOptions::default())
     }
 
     fn write_to<W: Write>(&mut self, w: &mut W) -> Result<(), ParserError> {
-        let records = self.storage.records();
-        for (i, record) in records.iter().enumerate() {
-            w.write_all(serialize_record(record).as_bytes())
+        for (i, record) in self.storage.records().iter().enumerate() {
+            w.write_all(&encode_record(record, i == 0, &self.options)?)
                 .map_err(io_error)?;
-            if i + 1 < records.len() {
-                w.write_all(b"\n").map_err(io_error)?;
-            }
         }
         Ok(())
     }
 
     fn from_storage(storage: YPBankStorage) -> Self {
-        Self { storage }
+        Self::with_options(storage, TxtOptions::default())
     }
 }
 
@@ -71,7 +133,10 @@ fn parse_key_value(line: &str) -> Result<(&str, &str), ParserError> {
     Ok((&line[..pos], &line[pos + 2..]))
 }
 
-fn build_record(fields: &mut HashMap<String, String>) -> Result<YPBankRecord, ParserError> {
+fn build_record(
+    fields: &mut HashMap<String, String>,
+    options: &TxtOptions,
+) -> Result<YPBankRecord, ParserError> {
     let tx_id = take_field(fields, "TX_ID")?
         .parse::<u64>()
         .map_err(|_| invalid_record("invalid TX_ID"))?;
@@ -86,18 +151,32 @@ fn build_record(fields: &mut HashMap<String, String>) -> Result<YPBankRecord, Pa
         .parse::<u64>()
         .map_err(|_| invalid_record("invalid TO_USER_ID"))?;
 
-    let amount = take_field(fields, "AMOUNT")?
-        .parse::<u64>()
-        .map_err(|_| invalid_record("invalid AMOUNT"))?;
+    let currency = match fields.remove("CURRENCY") {
+        Some(code) => {
+            Some(Currency::from_code(&code).ok_or_else(|| invalid_record("invalid CURRENCY"))?)
+        }
+        None => options.currency,
+    };
+    let (amount, currency) =
+        parse_amount(&take_field(fields, "AMOUNT")?, options.amount, currency)?;
 
-    let timestamp = take_field(fields, "TIMESTAMP")?
-        .parse::<u64>()
-        .map_err(|_| invalid_record("invalid TIMESTAMP"))?;
+    let timestamp = Timestamp::parse(&take_field(fields, "TIMESTAMP")?, &options.timestamp)
+        .ok_or_else(|| invalid_record("invalid TIMESTAMP"))?;
 
     let status = parse_status(&take_field(fields, "STATUS")?)?;
 
     let description_raw = take_field(fields, "DESCRIPTION")?;
     let description = parse_description(&description_raw)?;
+    options.limits.check_description(description.len())?;
+
+    let mut extra = ExtraFields::new();
+    for (key, value) in fields.drain() {
+        // Values that could not be written back are rejected
+        if !is_extra_field_name(&key) || value.trim() != value || value.contains('\r') {
+            return Err(invalid_record(&format!("invalid field: {}", key)));
+        }
+        extra.insert(key, value);
+    }
 
     Ok(YPBankRecord {
         tx_id,
@@ -105,9 +184,11 @@ fn build_record(fields: &mut HashMap<String, String>) -> Result<YPBankRecord, Pa
         from_user_id,
         to_user_id,
         amount,
+        currency,
         timestamp,
         status,
         description,
+        extra,
     })
 }
 
@@ -126,7 +207,10 @@ fn parse_status(s: &str) -> Result<YPBankRecordStatus, ParserError> {
 }
 
 fn parse_description(s: &str) -> Result<String, ParserError> {
-    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+    if s.contains('\r') {
+        // A line break could not be written back
+        Err(invalid_record("DESCRIPTION contains a line break"))
+    } else if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
         Ok(s[1..s.len() - 1].to_string())
     } else {
         Err(invalid_record(
@@ -135,18 +219,59 @@ fn parse_description(s: &str) -> Result<String, ParserError> {
     }
 }
 
-fn serialize_record(record: &YPBankRecord) -> String {
-    format!(
-        "TX_ID: {}\nTX_TYPE: {}\nFROM_USER_ID: {}\nTO_USER_ID: {}\nAMOUNT: {}\nTIMESTAMP: {}\nSTATUS: {}\nDESCRIPTION: \"{}\"\n",
+/// A record block, preceded by the separating empty line unless it is the first
+pub(crate) fn encode_record(
+    record: &YPBankRecord,
+    first: bool,
+    options: &TxtOptions,
+) -> Result<Vec<u8>, ParserError> {
+    let block = serialize_record(record, options)?;
+    if first {
+        Ok(block.into_bytes())
+    } else {
+        Ok(format!("\n{}", block).into_bytes())
+    }
+}
+
+fn serialize_record(record: &YPBankRecord, options: &TxtOptions) -> Result<String, ParserError> {
+    let timestamp = record
+        .timestamp
+        .format(&options.timestamp)
+        .map_err(|e| invalid_record(&e.to_string()))?;
+    if record.description.contains(['\r', '\n']) {
+        return Err(invalid_record("DESCRIPTION contains a line break"));
+    }
+    let currency = record.currency.or(options.currency);
+    let amount = format_amount(record.amount, options.amount, currency)?;
+    // A decimal amount already names its currency
+    let currency_line = match (options.amount, currency) {
+        (AmountFormat::Minor, Some(currency)) => format!("CURRENCY: {}\n", currency),
+        _ => String::new(),
+    };
+    let mut out = format!(
+        "TX_ID: {}\nTX_TYPE: {}\nFROM_USER_ID: {}\nTO_USER_ID: {}\nAMOUNT: {}\n{}TIMESTAMP: {}\nSTATUS: {}\nDESCRIPTION: \"{}\"\n",
         record.tx_id,
         record.tx_type,
         record.from_user_id,
         record.to_user_id,
-        record.amount,
-        record.timestamp,
+        amount,
+        currency_line,
+        timestamp,
         record.status,
         record.description
-    )
+    );
+    for (key, value) in &record.extra {
+        // Lines are trimmed on reading, so the value must survive that
+        if !is_extra_field_name(key)
+            || value.is_empty()
+            || value.trim() != value
+            || value.contains(['\r', '\n'])
+        {
+            return Err(invalid_record(&format!("invalid extra field: {}", key)));
+        }
+        out.push_str(&format!("{}: {}\n", key, value));
+    }
+    Ok(out)
 }
 
 fn invalid_record(msg: &str) -> ParserError {
@@ -165,6 +290,7 @@ fn io_error(e: std::io::Error) -> ParserError {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::timestamp::parse_utc_offset;
     use std::io::Cursor;
 
     fn sample_record() -> YPBankRecord {
@@ -174,9 +300,11 @@ mod tests {
             from_user_id: 1,
             to_user_id: 2,
             amount: 500,
-            timestamp: 1700000000,
+            currency: None,
+            timestamp: Timestamp::from_millis(1700000000),
             status: YPBankRecordStatus::FAILURE,
             description: "test withdrawal".to_string(),
+            extra: ExtraFields::new(),
         }
     }
 
@@ -239,6 +367,28 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_rejects_values_that_cannot_be_written() {
+        let text = concat!(
+            "TX_ID: 44\n",
+            "TX_TYPE: WITHDRAWAL\n",
+            "FROM_USER_ID: 1\n",
+            "TO_USER_ID: 2\n",
+            "AMOUNT: 500\n",
+            "TIMESTAMP: 1700000000\n",
+            "STATUS: FAILURE\n",
+            "DESCRIPTION: \"a\rb\"\r\n",
+        );
+        let result = TxtParser::from_read(&mut Cursor::new(text));
+        assert!(matches!(result, Err(ParserError::InvalidRecord { .. })));
+        let text = text.replace("a\rb", "ab");
+        for channel in ["a\rb", " ab"] {
+            let text = format!("{text}CHANNEL: {channel}\n");
+            let result = TxtParser::from_read(&mut Cursor::new(text));
+            assert!(matches!(result, Err(ParserError::InvalidRecord { .. })));
+        }
+    }
+
     #[test]
     fn test_invalid_tx_type() {
         let text = concat!(
@@ -259,4 +409,151 @@ mod tests {
             result
         );
     }
+
+    #[test]
+    fn test_iso8601_timestamp_with_offset() {
+        let text = concat!(
+            "TX_ID: 44\n",
+            "TX_TYPE: WITHDRAWAL\n",
+            "FROM_USER_ID: 1\n",
+            "TO_USER_ID: 2\n",
+            "AMOUNT: 500\n",
+            "TIMESTAMP: 1970-01-20T19:13:20+03:00\n",
+            "STATUS: FAILURE\n",
+            "DESCRIPTION: \"test withdrawal\"\n",
+        );
+        let options = TxtOptions {
+            timestamp: TimestampFormat::Iso8601(parse_utc_offset("+03:00").unwrap()),
+            ..TxtOptions::default()
+        };
+        let parsed =
+            TxtParser::read_with_options(&mut Cursor::new(text), &options).expect("read failed");
+        assert_eq!(parsed.records(), &[sample_record()]);
+
+        let mut buf = Vec::new();
+        let mut parser = TxtParser::with_options(parsed, options);
+        parser.write_to(&mut buf).expect("write failed");
+        assert_eq!(String::from_utf8(buf).unwrap(), text);
+    }
+
+    #[test]
+    fn test_currency_round_trip() {
+        let mut record = sample_record();
+        record.currency = Currency::from_code("KWD");
+
+        for amount in [AmountFormat::Minor, AmountFormat::Decimal] {
+            let options = TxtOptions {
+                amount,
+                ..TxtOptions::default()
+            };
+            let mut storage = YPBankStorage::new();
+            storage.push(record.clone());
+            let mut buf = Vec::new();
+            let mut parser = TxtParser::with_options(storage, options.clone());
+            parser.write_to(&mut buf).expect("write failed");
+            let text = String::from_utf8(buf).unwrap();
+            match amount {
+                AmountFormat::Minor => assert!(text.contains("AMOUNT: 500\nCURRENCY: KWD\n")),
+                AmountFormat::Decimal => assert!(text.contains("AMOUNT: 0.500 KWD\n")),
+            }
+
+            let parsed = TxtParser::read_with_options(&mut Cursor::new(text), &options)
+                .expect("read failed");
+            assert_eq!(parsed.records(), &[record.clone()]);
+        }
+    }
+
+    #[test]
+    fn test_extra_fields_round_trip() {
+        let text = concat!(
+            "TX_ID: 44\n",
+            "CHANNEL: mobile app\n",
+            "TX_TYPE: WITHDRAWAL\n",
+            "FROM_USER_ID: 1\n",
+            "TO_USER_ID: 2\n",
+            "AMOUNT: 500\n",
+            "TIMESTAMP: 1700000000\n",
+            "STATUS: FAILURE\n",
+            "DESCRIPTION: \"test withdrawal\"\n",
+            "MERCHANT_ID: 7731\n",
+        );
+        let mut expected = sample_record();
+        expected.extra.insert("CHANNEL".into(), "mobile app".into());
+        expected.extra.insert("MERCHANT_ID".into(), "7731".into());
+
+        let parsed = TxtParser::from_read(&mut Cursor::new(text)).expect("read failed");
+        assert_eq!(parsed.records(), &[expected.clone()]);
+
+        let mut buf = Vec::new();
+        TxtParser::from_storage(parsed)
+            .write_to(&mut buf)
+            .expect("write failed");
+        let reparsed = TxtParser::from_read(&mut Cursor::new(buf)).expect("read failed");
+        assert_eq!(reparsed.records(), &[expected]);
+    }
+
+    #[test]
+    fn test_invalid_extra_field_name() {
+        let text = concat!(
+            "TX_ID: 44\n",
+            "TX TYPE: WITHDRAWAL\n",
+            "TX_TYPE: WITHDRAWAL\n",
+            "FROM_USER_ID: 1\n",
+            "TO_USER_ID: 2\n",
+            "AMOUNT: 500\n",
+            "TIMESTAMP: 1700000000\n",
+            "STATUS: FAILURE\n",
+            "DESCRIPTION: \"test withdrawal\"\n",
+        );
+        let result = TxtParser::from_read(&mut Cursor::new(text));
+        assert!(
+            matches!(result, Err(ParserError::InvalidRecord { .. })),
+            "expected InvalidRecord for invalid field name, got: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let text = concat!(
+            "TX_ID: 44\n",
+            "TX_TYPE: WITHDRAWAL\n",
+            "FROM_USER_ID: 1\n",
+            "TO_USER_ID: 2\n",
+            "AMOUNT: 500\n",
+            "TIMESTAMP: 1700000000\n",
+            "STATUS: FAILURE\n",
+            "DESCRIPTION: \"test withdrawal\"\n",
+        );
+        let read = |limits: ParseLimits| {
+            let options = TxtOptions {
+                limits,
+                ..TxtOptions::default()
+            };
+            TxtParser::read_with_options(&mut Cursor::new(text), &options)
+        };
+        assert!(read(ParseLimits::default()).is_ok());
+
+        let result = read(ParseLimits {
+            max_description_len: 10,
+            ..ParseLimits::default()
+        });
+        assert!(matches!(
+            result,
+            Err(ParserError::DescriptionTooLong { len: 15, max: 10 })
+        ));
+        let result = read(ParseLimits {
+            max_line_len: 20,
+            ..ParseLimits::default()
+        });
+        assert!(matches!(result, Err(ParserError::LineTooLong { max: 20 })));
+        let result = read(ParseLimits {
+            max_record_size: 100,
+            ..ParseLimits::default()
+        });
+        assert!(matches!(
+            result,
+            Err(ParserError::RecordTooLarge { max: 100, .. })
+        ));
+    }
 }
@@ -1,7 +1,595 @@
 //! Format-specific parser implementations.
+
+use crate::error::ParserError;
+use crate::parser::ParseLimits;
+use crate::parser::Parser;
+use crate::storage::YPBankStorage;
+use format_bin::{BinOptions, BinParser};
+use format_csv::{CsvOptions, CsvParser};
+#[cfg(feature = "cbor")]
+use format_serde::Cbor;
+#[cfg(feature = "json")]
+use format_serde::Json;
+#[cfg(feature = "msgpack")]
+use format_serde::MessagePack;
+#[cfg(feature = "serde")]
+use format_serde::SerdeOptions;
+#[cfg(any(
+    feature = "json",
+    feature = "yaml",
+    feature = "msgpack",
+    feature = "cbor"
+))]
+use format_serde::SerdeParser;
+#[cfg(feature = "yaml")]
+use format_serde::Yaml;
+#[cfg(feature = "sqlite")]
+use format_sqlite::{SqliteOptions, SqliteParser};
+use format_txt::{TxtOptions, TxtParser};
+use std::io::{Read, Write};
+use std::path::Path;
+use strum_macros::{Display, EnumString};
+
+/// Names of the supported formats, as accepted on the command line.
+pub const FORMAT_NAMES: &[&str] = &[
+    "bin",
+    "csv",
+    "txt",
+    #[cfg(feature = "json")]
+    "json",
+    #[cfg(feature = "yaml")]
+    "yaml",
+    #[cfg(feature = "msgpack")]
+    "msgpack",
+    #[cfg(feature = "cbor")]
+    "cbor",
+    #[cfg(feature = "sqlite")]
+    "sqlite",
+];
+
+/// Async streaming readers and writers, behind the `tokio` feature.
+#[cfg(feature = "tokio")]
+pub mod async_io;
 /// Binary format parser.
 pub mod format_bin;
 /// CSV format parser.
 pub mod format_csv;
+/// Serde data format adapter, behind the `serde` feature.
+#[cfg(feature = "serde")]
+pub mod format_serde;
+/// SQLite database backend, behind the `sqlite` feature.
+#[cfg(feature = "sqlite")]
+pub mod format_sqlite;
 /// Plain-text key-value format parser.
 pub mod format_txt;
+/// Sync streaming reader of records.
+pub mod record_reader;
+/// Sync streaming writer of records.
+pub mod record_writer;
+
+/// Supported record file formats.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum Format {
+    /// Binary `YPBN` records
+    Bin,
+    /// Comma-separated values
+    Csv,
+    /// Plain-text key-value blocks
+    Txt,
+    /// JSON array of records
+    #[cfg(feature = "json")]
+    Json,
+    /// YAML sequence of records
+    #[cfg(feature = "yaml")]
+    #[strum(to_string = "yaml", serialize = "yml")]
+    Yaml,
+    /// MessagePack array of records
+    #[cfg(feature = "msgpack")]
+    #[strum(to_string = "msgpack", serialize = "mpk")]
+    Msgpack,
+    /// CBOR array of records
+    #[cfg(feature = "cbor")]
+    Cbor,
+    /// SQLite database with a `transactions` table
+    #[cfg(feature = "sqlite")]
+    #[strum(to_string = "sqlite", serialize = "sqlite3", serialize = "db")]
+    Sqlite,
+}
+
+/// Per-format options used by [`Format::read`] and [`Format::write`].
+#[derive(Debug, Default, Clone)]
+pub struct FormatOptions {
+    /// Options of the binary format
+    pub bin: BinOptions,
+    /// Options of the CSV format
+    pub csv: CsvOptions,
+    /// Options of the text format
+    pub txt: TxtOptions,
+    /// Options of the serde data formats
+    #[cfg(feature = "serde")]
+    pub serde: SerdeOptions,
+    /// Options of the SQLite format
+    #[cfg(feature = "sqlite")]
+    pub sqlite: SqliteOptions,
+}
+
+impl FormatOptions {
+    /// Applies the same input limits to every format
+    pub fn with_limits(mut self, limits: ParseLimits) -> Self {
+        self.bin.limits = limits;
+        self.csv.limits = limits;
+        self.txt.limits = limits;
+        #[cfg(feature = "serde")]
+        {
+            self.serde.limits = limits;
+        }
+        #[cfg(feature = "sqlite")]
+        {
+            self.sqlite.limits = limits;
+        }
+        self
+    }
+}
+
+impl Format {
+    /// Guesses the format from the file extension
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?;
+        extension.to_ascii_lowercase().parse().ok()
+    }
+
+    /// Reads all records in this format
+    pub fn read<R: Read>(
+        self,
+        r: &mut R,
+        options: &FormatOptions,
+    ) -> Result<YPBankStorage, ParserError> {
+        match self {
+            Format::Bin => BinParser::read_with_options(r, &options.bin),
+            Format::Csv => CsvParser::read_with_options(r, &options.csv),
+            Format::Txt => TxtParser::read_with_options(r, &options.txt),
+            #[cfg(feature = "json")]
+            Format::Json => SerdeParser::<Json>::read_with_options(r, &options.serde),
+            #[cfg(feature = "yaml")]
+            Format::Yaml => SerdeParser::<Yaml>::read_with_options(r, &options.serde),
+            #[cfg(feature = "msgpack")]
+            Format::Msgpack => SerdeParser::<MessagePack>::read_with_options(r, &options.serde),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => SerdeParser::<Cbor>::read_with_options(r, &options.serde),
+            #[cfg(feature = "sqlite")]
+            Format::Sqlite => SqliteParser::read_with_options(r, &options.sqlite),
+        }
+    }
+
+    /// Writes all records in this format
+    ///
+    /// SQLite data is always a new database holding just these records;
+    /// `format_sqlite::SqliteStore` appends to or upserts into an existing
+    /// one.
+    pub fn write<W: Write>(
+        self,
+        storage: YPBankStorage,
+        w: &mut W,
+        options: &FormatOptions,
+    ) -> Result<(), ParserError> {
+        match self {
+            Format::Bin => BinParser::from_storage(storage).write_to(w),
+            Format::Csv => CsvParser::with_options(storage, options.csv.clone()).write_to(w),
+            Format::Txt => TxtParser::with_options(storage, options.txt.clone()).write_to(w),
+            #[cfg(feature = "json")]
+            Format::Json => SerdeParser::<Json>::from_storage(storage).write_to(w),
+            #[cfg(feature = "yaml")]
+            Format::Yaml => SerdeParser::<Yaml>::from_storage(storage).write_to(w),
+            #[cfg(feature = "msgpack")]
+            Format::Msgpack => SerdeParser::<MessagePack>::from_storage(storage).write_to(w),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => SerdeParser::<Cbor>::from_storage(storage).write_to(w),
+            #[cfg(feature = "sqlite")]
+            Format::Sqlite => SqliteParser::from_storage(storage).write_to(w),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::currency::Currency;
+    use crate::storage::{
+        ExtraFields, YPBankRecord, YPBankRecordStatus, YPBankRecordType, is_extra_field_name,
+    };
+    use crate::timestamp::{TimeUnit, Timestamp};
+    use proptest::prelude::*;
+
+    const FORMATS: &[Format] = &[
+        Format::Bin,
+        Format::Csv,
+        Format::Txt,
+        #[cfg(feature = "json")]
+        Format::Json,
+        #[cfg(feature = "yaml")]
+        Format::Yaml,
+        #[cfg(feature = "msgpack")]
+        Format::Msgpack,
+        #[cfg(feature = "cbor")]
+        Format::Cbor,
+        #[cfg(feature = "sqlite")]
+        Format::Sqlite,
+    ];
+
+    fn storage(records: &[YPBankRecord]) -> YPBankStorage {
+        let mut storage = YPBankStorage::new();
+        for record in records {
+            storage.push(record.clone());
+        }
+        storage
+    }
+
+    fn write(format: Format, records: &[YPBankRecord]) -> Result<Vec<u8>, ParserError> {
+        let mut data = Vec::new();
+        format.write(storage(records), &mut data, &FormatOptions::default())?;
+        Ok(data)
+    }
+
+    fn read(format: Format, data: &[u8]) -> Result<Vec<YPBankRecord>, ParserError> {
+        let storage = format.read(&mut &data[..], &FormatOptions::default())?;
+        Ok(storage.into_records())
+    }
+
+    /// Writes the records in `from`, converts them to `to` and reads them back
+    fn convert(
+        records: &[YPBankRecord],
+        from: Format,
+        to: Format,
+    ) -> Result<Vec<YPBankRecord>, ParserError> {
+        let converted = read(from, &write(from, records)?)?;
+        read(to, &write(to, &converted)?)
+    }
+
+    fn id() -> impl Strategy<Value = u64> {
+        prop_oneof![Just(0), Just(1), Just(u64::MAX), any::<u64>()]
+    }
+
+    /// Amounts that fit the signed binary `AMOUNT`
+    fn amount() -> impl Strategy<Value = u64> {
+        prop_oneof![Just(0), Just(i64::MAX as u64), 0..=i64::MAX as u64]
+    }
+
+    /// Descriptions that every format keeps: no line breaks and no quote at
+    /// either end
+    fn description() -> impl Strategy<Value = String> {
+        prop_oneof![Just(String::new()), "[^\r\n]*"].prop_filter("enclosing quote", |s| {
+            !s.starts_with('"') && !s.ends_with('"')
+        })
+    }
+
+    fn extra() -> impl Strategy<Value = ExtraFields> {
+        prop::collection::btree_map(
+            "[A-Za-z0-9_-]{1,12}".prop_filter("standard field", |k| is_extra_field_name(k)),
+            "[^\r\n]+".prop_filter("surrounding whitespace", |v| v.trim() == v),
+            0..4,
+        )
+    }
+
+    /// Timestamps in whole milliseconds, which every format holds
+    fn millis() -> BoxedStrategy<Timestamp> {
+        id().prop_map(Timestamp::from_millis).boxed()
+    }
+
+    /// Timestamps mostly in milliseconds, otherwise in microseconds
+    fn micros() -> BoxedStrategy<Timestamp> {
+        let micros = any::<u64>().prop_map(|micros| Timestamp::from_unix(micros, TimeUnit::Micros));
+        prop_oneof![9 => millis(), 1 => micros].boxed()
+    }
+
+    fn record() -> impl Strategy<Value = YPBankRecord> {
+        record_at(millis())
+    }
+
+    fn record_at(timestamp: BoxedStrategy<Timestamp>) -> impl Strategy<Value = YPBankRecord> {
+        let tx_type = prop_oneof![
+            Just(YPBankRecordType::DEPOSIT),
+            Just(YPBankRecordType::TRANSFER),
+            Just(YPBankRecordType::WITHDRAWAL),
+        ];
+        let status = prop_oneof![
+            Just(YPBankRecordStatus::SUCCESS),
+            Just(YPBankRecordStatus::FAILURE),
+            Just(YPBankRecordStatus::PENDING),
+        ];
+        let currency = prop::option::of(
+            prop::sample::select(&["EUR", "USD", "JPY", "BHD"][..])
+                .prop_map(|code| Currency::from_code(code).unwrap()),
+        );
+        (
+            (id(), tx_type, id(), id()),
+            (amount(), currency, timestamp, status),
+            (description(), extra()),
+        )
+            .prop_map(
+                |(
+                    (tx_id, tx_type, from_user_id, to_user_id),
+                    (amount, currency, timestamp, status),
+                    (description, extra),
+                )| YPBankRecord {
+                    tx_id,
+                    tx_type,
+                    from_user_id,
+                    to_user_id,
+                    amount,
+                    currency,
+                    timestamp,
+                    status,
+                    description,
+                    extra,
+                },
+            )
+    }
+
+    /// Documented errors of `format` for records it cannot hold
+    ///
+    /// With the default options every format writes milliseconds, and SQLite
+    /// stores numbers as signed integers.
+    fn documented_errors(format: Format, records: &[YPBankRecord]) -> Vec<&'static str> {
+        let mut errors = Vec::new();
+        let sub_millis = |r: &YPBankRecord| r.timestamp.to_unix_exact(TimeUnit::Millis).is_err();
+        if records.iter().any(sub_millis) {
+            errors.push("TIMESTAMP has sub-millisecond precision");
+        }
+        #[cfg(feature = "sqlite")]
+        if format == Format::Sqlite {
+            let signed = |value: u64| i64::try_from(value).is_ok();
+            let fits = |r: &YPBankRecord| {
+                [r.tx_id, r.from_user_id, r.to_user_id, r.amount]
+                    .into_iter()
+                    .chain(r.timestamp.to_unix(TimeUnit::Millis))
+                    .all(signed)
+            };
+            if !records.iter().all(fits) {
+                errors.push("out of range");
+            }
+        }
+        #[cfg(not(feature = "sqlite"))]
+        let _ = format;
+        errors
+    }
+
+    proptest! {
+        #[test]
+        fn test_every_format_pair_round_trips(
+            records in prop::collection::vec(record_at(micros()), 0..8)
+        ) {
+            for &from in FORMATS {
+                for &to in FORMATS {
+                    let mut expected = documented_errors(from, &records);
+                    expected.extend(documented_errors(to, &records));
+                    match convert(&records, from, to) {
+                        Ok(converted) => {
+                            prop_assert!(expected.is_empty(), "{} -> {}: {:?}", from, to, expected);
+                            prop_assert_eq!(converted, records.clone(), "{} -> {}", from, to);
+                        }
+                        Err(e) => {
+                            let message = e.to_string();
+                            prop_assert!(
+                                expected.iter().any(|m| message.contains(m)),
+                                "{} -> {}: {}", from, to, message
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn test_line_break_in_description_is_rejected(
+            mut record in record(),
+            description in "[^\r\n]*[\r\n][^\r\n]*"
+        ) {
+            prop_assume!(!description.starts_with('"') && !description.ends_with('"'));
+            record.description = description;
+            for format in [Format::Csv, Format::Txt] {
+                let error = write(format, &[record.clone()]).unwrap_err();
+                prop_assert!(error.to_string().contains("DESCRIPTION contains a line break"));
+            }
+            let records = [record];
+            prop_assert_eq!(read(Format::Bin, &write(Format::Bin, &records)?)?, records);
+        }
+
+        #[test]
+        fn test_enclosing_quote_in_description_is_rejected(
+            mut record in record(),
+            description in "\"[^\r\n]*|[^\r\n]*\""
+        ) {
+            record.description = description;
+            let error = write(Format::Bin, &[record.clone()]).unwrap_err();
+            prop_assert!(error.to_string().contains("cannot start or end with a double quote"));
+            let records = [record];
+            for format in [Format::Csv, Format::Txt] {
+                prop_assert_eq!(read(format, &write(format, &records)?)?, records.clone());
+            }
+        }
+
+        #[test]
+        fn test_amount_beyond_signed_range_is_rejected(
+            mut record in record(),
+            amount in (i64::MAX as u64 + 2)..=u64::MAX
+        ) {
+            record.amount = amount;
+            let error = write(Format::Bin, &[record.clone()]).unwrap_err();
+            prop_assert!(error.to_string().contains("AMOUNT out of range"));
+            let records = [record];
+            for format in [Format::Csv, Format::Txt] {
+                prop_assert_eq!(read(format, &write(format, &records)?)?, records.clone());
+            }
+        }
+
+        #[test]
+        fn test_untrimmed_extra_value_is_rejected_by_text(
+            mut record in record(),
+            value in prop_oneof![Just(String::new()), " [^\r\n]*", "[^\r\n]* "]
+        ) {
+            record.extra.insert("NOTE".to_string(), value);
+            let error = write(Format::Txt, &[record.clone()]).unwrap_err();
+            prop_assert!(error.to_string().contains("invalid extra field: NOTE"));
+        }
+    }
+
+    /// Returns the first fenced code block after `heading` in a document
+    fn example<'a>(doc: &'a str, heading: &str) -> &'a str {
+        let section = &doc[doc.find(heading).unwrap()..];
+        let block = &section[section.find("```").unwrap()..];
+        let block = &block[block.find('\n').unwrap() + 1..];
+        &block[..block.find("```").unwrap()]
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn golden(
+        tx_id: u64,
+        tx_type: YPBankRecordType,
+        from_user_id: u64,
+        to_user_id: u64,
+        amount: u64,
+        millis: u64,
+        status: YPBankRecordStatus,
+        description: &str,
+    ) -> YPBankRecord {
+        YPBankRecord {
+            tx_id,
+            tx_type,
+            from_user_id,
+            to_user_id,
+            amount,
+            currency: None,
+            timestamp: Timestamp::from_millis(millis),
+            status,
+            description: description.to_string(),
+            extra: ExtraFields::new(),
+        }
+    }
+
+    #[test]
+    fn test_golden_text_example() {
+        use YPBankRecordStatus::*;
+        use YPBankRecordType::*;
+        let doc = include_str!("../../docs/YPBankTextFormat_ru.md");
+        let records = read(Format::Txt, example(doc, "## Examples").as_bytes()).unwrap();
+        assert_eq!(
+            records,
+            [
+                golden(
+                    1234567890123456,
+                    DEPOSIT,
+                    0,
+                    9876543210987654,
+                    10000,
+                    1633036800000,
+                    SUCCESS,
+                    "Terminal deposit"
+                ),
+                golden(
+                    2312321321321321,
+                    TRANSFER,
+                    1231231231231231,
+                    9876543210987654,
+                    1000,
+                    1633056800000,
+                    FAILURE,
+                    "User transfer"
+                ),
+                golden(
+                    3213213213213213,
+                    WITHDRAWAL,
+                    9876543210987654,
+                    0,
+                    100,
+                    1633066800000,
+                    SUCCESS,
+                    "User withdrawal"
+                ),
+            ]
+        );
+
+        // The binary layout follows the field table of the specification
+        let bin = write(Format::Bin, &records).unwrap();
+        let mut first = b"YPBN".to_vec();
+        first.extend(62u32.to_be_bytes());
+        first.extend(1234567890123456u64.to_be_bytes());
+        first.push(0);
+        first.extend(0u64.to_be_bytes());
+        first.extend(9876543210987654u64.to_be_bytes());
+        first.extend(10000i64.to_be_bytes());
+        first.extend(1633036800000u64.to_be_bytes());
+        first.push(0);
+        first.extend(16u32.to_be_bytes());
+        first.extend(b"Terminal deposit");
+        assert!(bin.starts_with(&first));
+        // A withdrawal is stored as a negative amount
+        let last = &bin[bin.len() - 69..];
+        assert_eq!(&last[..4], b"YPBN");
+        assert_eq!(last[33..41], (-100i64).to_be_bytes());
+        assert_eq!(read(Format::Bin, &bin).unwrap(), records);
+    }
+
+    #[test]
+    fn test_golden_csv_example() {
+        use YPBankRecordStatus::*;
+        use YPBankRecordType::*;
+        let doc = include_str!("../../docs/YPBankCsvFormat_ru.md");
+        let text = example(doc, "## Пример");
+        let records = read(Format::Csv, text.as_bytes()).unwrap();
+        assert_eq!(
+            records,
+            [
+                golden(
+                    1001,
+                    DEPOSIT,
+                    0,
+                    501,
+                    50000,
+                    1672531200000,
+                    SUCCESS,
+                    "Initial account funding"
+                ),
+                golden(
+                    1002,
+                    TRANSFER,
+                    501,
+                    502,
+                    15000,
+                    1672534800000,
+                    FAILURE,
+                    "Payment for services, invoice #123"
+                ),
+                golden(
+                    1003,
+                    WITHDRAWAL,
+                    502,
+                    0,
+                    1000,
+                    1672538400000,
+                    PENDING,
+                    "ATM withdrawal"
+                ),
+            ]
+        );
+        assert_eq!(
+            String::from_utf8(write(Format::Csv, &records).unwrap()).unwrap(),
+            text
+        );
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Format::from_path("records.bin"), Some(Format::Bin));
+        assert_eq!(Format::from_path("dir/records.CSV"), Some(Format::Csv));
+        assert_eq!(Format::from_path("records.txt"), Some(Format::Txt));
+        assert_eq!(Format::from_path("records.xml"), None);
+        #[cfg(feature = "json")]
+        assert_eq!(Format::from_path("records.json"), Some(Format::Json));
+        #[cfg(feature = "yaml")]
+        assert_eq!(Format::from_path("records.yml"), Some(Format::Yaml));
+        #[cfg(feature = "sqlite")]
+        assert_eq!(Format::from_path("records.db"), Some(Format::Sqlite));
+        assert_eq!(Format::from_path("records"), None);
+    }
+}
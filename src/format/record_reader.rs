@@ -0,0 +1,172 @@
+//! Sync streaming reader of records.
+//!
+//! Binary, CSV and TXT data are decoded one record at a time by the same code
+//! as the parsers, so a huge file is never held in memory as a whole.
+
+use super::format_bin;
+use super::format_csv::CsvDecoder;
+use super::format_txt::TxtDecoder;
+use super::{Format, FormatOptions};
+use crate::error::ParserError;
+use crate::parser::ParseLimits;
+use crate::storage::YPBankRecord;
+use std::io::{BufReader, Read};
+use std::vec;
+
+enum Decoder<R> {
+    Bin(BufReader<R>),
+    Csv(BufReader<R>, CsvDecoder),
+    Txt(BufReader<R>, TxtDecoder),
+    Loaded(vec::IntoIter<YPBankRecord>),
+}
+
+/// Streaming reader of records in one format, iterating over the records
+///
+/// The serde data formats and SQLite are decoded as a whole when the reader
+/// is created. After an error the iterator ends.
+pub struct RecordReader<R> {
+    decoder: Decoder<R>,
+    limits: ParseLimits,
+    count: usize,
+    done: bool,
+}
+
+impl<R: Read> RecordReader<R> {
+    /// Creates a reader of `format` data with the options of that format
+    pub fn new(format: Format, mut r: R, options: &FormatOptions) -> Result<Self, ParserError> {
+        let (decoder, limits) = match format {
+            Format::Bin => (Decoder::Bin(BufReader::new(r)), options.bin.limits),
+            Format::Csv => (
+                Decoder::Csv(BufReader::new(r), CsvDecoder::new(options.csv.clone())?),
+                options.csv.limits,
+            ),
+            Format::Txt => (
+                Decoder::Txt(BufReader::new(r), TxtDecoder::new(options.txt.clone())),
+                options.txt.limits,
+            ),
+            #[allow(unreachable_patterns)]
+            _ => {
+                let records = format.read(&mut r, options)?.into_records();
+                (
+                    Decoder::Loaded(records.into_iter()),
+                    ParseLimits::unlimited(),
+                )
+            }
+        };
+        Ok(Self {
+            decoder,
+            limits,
+            count: 0,
+            done: false,
+        })
+    }
+
+    /// Reads the next record, `None` at the end of input
+    pub fn next_record(&mut self) -> Result<Option<YPBankRecord>, ParserError> {
+        if self.done {
+            return Ok(None);
+        }
+        let limits = &self.limits;
+        let record = match &mut self.decoder {
+            Decoder::Bin(reader) => format_bin::read_record(reader, limits)?,
+            Decoder::Csv(reader, decoder) => loop {
+                match limits.read_line(reader)? {
+                    Some(line) => {
+                        if let Some(record) = decoder.decode_line(&line)? {
+                            break Some(record);
+                        }
+                    }
+                    None => {
+                        decoder.finish()?;
+                        break None;
+                    }
+                }
+            },
+            Decoder::Txt(reader, decoder) => loop {
+                match limits.read_line(reader)? {
+                    Some(line) => {
+                        if let Some(record) = decoder.decode_line(&line)? {
+                            break Some(record);
+                        }
+                    }
+                    None => break decoder.finish()?,
+                }
+            },
+            Decoder::Loaded(records) => records.next(),
+        };
+        match record {
+            Some(_) => {
+                self.limits.check_record_count(self.count)?;
+                self.count += 1;
+            }
+            None => self.done = true,
+        }
+        Ok(record)
+    }
+}
+
+impl<R: Read> Iterator for RecordReader<R> {
+    type Item = Result<YPBankRecord, ParserError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.next_record();
+        if record.is_err() {
+            self.done = true;
+        }
+        record.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::numbered_storage;
+
+    #[test]
+    fn test_streams_every_format() {
+        let options = FormatOptions::default();
+        for name in crate::format::FORMAT_NAMES {
+            let format: Format = name.parse().unwrap();
+            let mut data = Vec::new();
+            format
+                .write(numbered_storage(3), &mut data, &options)
+                .unwrap();
+            let reader = RecordReader::new(format, &data[..], &options).unwrap();
+            let records: Vec<_> = reader.collect::<Result<_, _>>().unwrap();
+            assert_eq!(records, numbered_storage(3).records(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_error_ends_iteration() {
+        let mut data = Vec::new();
+        Format::Bin
+            .write(numbered_storage(2), &mut data, &FormatOptions::default())
+            .unwrap();
+        data.truncate(data.len() - 1);
+        let mut reader =
+            RecordReader::new(Format::Bin, &data[..], &FormatOptions::default()).unwrap();
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_limits() {
+        let options = FormatOptions::default().with_limits(ParseLimits {
+            max_records: 2,
+            ..ParseLimits::default()
+        });
+        let mut data = Vec::new();
+        Format::Txt
+            .write(numbered_storage(3), &mut data, &options)
+            .unwrap();
+        let reader = RecordReader::new(Format::Txt, &data[..], &options).unwrap();
+        let records: Vec<_> = reader.collect();
+        assert_eq!(records.len(), 3);
+        assert!(matches!(
+            records[2],
+            Err(ParserError::TooManyRecords { max: 2 })
+        ));
+    }
+}
@@ -0,0 +1,224 @@
+//! Sync streaming writer of records.
+//!
+//! Binary, CSV and TXT records are encoded and written one at a time by the
+//! same code as the parsers.
+
+use super::format_bin;
+use super::format_csv::{CsvEncoder, CsvOptions};
+use super::format_txt::{self, TxtOptions};
+use super::{Format, FormatOptions};
+use crate::error::ParserError;
+use crate::storage::{YPBankRecord, YPBankStorage};
+use std::io::Write;
+
+enum Encoder {
+    Bin,
+    Csv {
+        options: CsvOptions,
+        encoder: Option<CsvEncoder>,
+    },
+    Txt(TxtOptions),
+    Buffered(Format, Box<FormatOptions>, YPBankStorage),
+}
+
+/// Streaming writer of records in one format
+///
+/// CSV is streamed only with fixed columns, i.e. without
+/// [`CsvOptions::extra_columns`]; otherwise the columns depend on every
+/// record, so the records are buffered like those of the serde data formats
+/// and SQLite, which are encoded as a whole in [`RecordWriter::finish`].
+/// [`RecordWriter::streaming`] streams CSV in either case.
+pub struct RecordWriter<W> {
+    writer: W,
+    encoder: Encoder,
+    count: usize,
+}
+
+impl<W: Write> RecordWriter<W> {
+    /// Creates a writer of `format` data with the options of that format
+    pub fn new(format: Format, w: W, options: &FormatOptions) -> Self {
+        Self::create(format, w, options, !options.csv.extra_columns)
+    }
+
+    /// Creates a writer that streams CSV even with
+    /// [`CsvOptions::extra_columns`]
+    ///
+    /// The header then has the configured columns, with `CURRENCY` only for
+    /// [`CsvOptions::currency`], and a record with a currency the header
+    /// lacks or with extra fields fails instead of all records being
+    /// buffered.
+    pub fn streaming(format: Format, w: W, options: &FormatOptions) -> Self {
+        Self::create(format, w, options, true)
+    }
+
+    fn create(format: Format, w: W, options: &FormatOptions, stream_csv: bool) -> Self {
+        let encoder = match format {
+            Format::Bin => Encoder::Bin,
+            Format::Csv if stream_csv => Encoder::Csv {
+                options: options.csv.clone(),
+                encoder: None,
+            },
+            Format::Txt => Encoder::Txt(options.txt.clone()),
+            _ => Encoder::Buffered(format, Box::new(options.clone()), YPBankStorage::new()),
+        };
+        Self {
+            writer: w,
+            encoder,
+            count: 0,
+        }
+    }
+
+    /// Writes one record
+    pub fn write_record(&mut self, record: &YPBankRecord) -> Result<(), ParserError> {
+        let data = match &mut self.encoder {
+            Encoder::Bin => format_bin::encode_record(record)?,
+            Encoder::Txt(options) => format_txt::encode_record(record, self.count == 0, options)?,
+            Encoder::Csv {
+                encoder: Some(encoder),
+                ..
+            } => encoder.encode(record)?,
+            Encoder::Csv { options, encoder } => {
+                let created = CsvEncoder::new(&[], options.clone())?;
+                let mut data = created.header();
+                data.extend(created.encode(record)?);
+                *encoder = Some(created);
+                data
+            }
+            Encoder::Buffered(.., storage) => {
+                storage.push(record.clone());
+                Vec::new()
+            }
+        };
+        self.writer.write_all(&data).map_err(io_error)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Writes the CSV header if no record was written, or the buffered
+    /// records, flushes and returns the underlying writer
+    pub fn finish(mut self) -> Result<W, ParserError> {
+        match self.encoder {
+            Encoder::Csv {
+                options,
+                encoder: None,
+            } => {
+                let header = CsvEncoder::new(&[], options)?.header();
+                self.writer.write_all(&header).map_err(io_error)?;
+            }
+            Encoder::Buffered(format, options, storage) => {
+                format.write(storage, &mut self.writer, &options)?;
+            }
+            _ => {}
+        }
+        self.writer.flush().map_err(io_error)?;
+        Ok(self.writer)
+    }
+}
+
+fn io_error(e: std::io::Error) -> ParserError {
+    ParserError::IO {
+        message: e.to_string(),
+        error: e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::FORMAT_NAMES;
+    use crate::format::format_csv::CsvColumn;
+    use crate::format::record_reader::RecordReader;
+    use crate::storage::sample_storage;
+
+    fn write_all(format: Format, records: &[YPBankRecord], options: &FormatOptions) -> Vec<u8> {
+        let mut writer = RecordWriter::new(format, Vec::new(), options);
+        for record in records {
+            writer.write_record(record).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_writes_the_same_bytes_as_the_parsers() {
+        let mut records = sample_storage().into_records();
+        let options = FormatOptions::default();
+        for name in FORMAT_NAMES {
+            let format: Format = name.parse().unwrap();
+            // Either record may come first: the second one lacks the
+            // currency and the extra field of the first
+            for _ in 0..2 {
+                records.reverse();
+                let data = write_all(format, &records, &options);
+
+                let mut storage = YPBankStorage::new();
+                storage.extend(records.iter().cloned());
+                let mut expected = Vec::new();
+                format.write(storage, &mut expected, &options).unwrap();
+                assert_eq!(data, expected, "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_csv_columns_of_mixed_records() {
+        let mut records = sample_storage().into_records();
+        records.reverse();
+        let mut txt = Vec::new();
+        let mut storage = YPBankStorage::new();
+        storage.extend(records);
+        Format::Txt
+            .write(storage, &mut txt, &FormatOptions::default())
+            .unwrap();
+
+        let options = FormatOptions::default();
+        let records: Vec<_> = RecordReader::new(Format::Txt, &txt[..], &options)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let csv = String::from_utf8(write_all(Format::Csv, &records, &options)).unwrap();
+        let header = csv.lines().next().unwrap();
+        assert!(header.ends_with(",CURRENCY,CHANNEL"), "{}", header);
+
+        let mut fixed = FormatOptions::default();
+        fixed.csv.columns = vec![CsvColumn::TxId, CsvColumn::Currency];
+        fixed.csv.extra_columns = false;
+        let csv = String::from_utf8(write_all(Format::Csv, &records, &fixed)).unwrap();
+        assert_eq!(csv, "TX_ID,CURRENCY\n2,\n1,EUR\n");
+    }
+
+    #[test]
+    fn test_streams_csv_with_extra_columns() {
+        let records = sample_storage().into_records();
+        let options = FormatOptions::default();
+        let mut writer = RecordWriter::streaming(Format::Csv, Vec::new(), &options);
+        writer.write_record(&records[1]).unwrap();
+        let error = writer.write_record(&records[0]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid record: CURRENCY is not in the streamed CSV columns"
+        );
+        let csv = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert_eq!(
+            csv,
+            "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n\
+             2,DEPOSIT,0,2,700,1700000060000,SUCCESS,\"\"\n"
+        );
+
+        let mut euro = options.clone();
+        euro.csv.currency = records[0].currency;
+        let mut writer = RecordWriter::streaming(Format::Csv, Vec::new(), &euro);
+        let error = writer.write_record(&records[0]).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .ends_with("extra field CHANNEL is not in the streamed CSV columns")
+        );
+    }
+
+    #[test]
+    fn test_empty_csv_has_header() {
+        let writer = RecordWriter::new(Format::Csv, Vec::new(), &FormatOptions::default());
+        let data = writer.finish().unwrap();
+        assert!(String::from_utf8(data).unwrap().starts_with("TX_ID,"));
+    }
+}
@@ -0,0 +1,783 @@
+//! Durable append-only journal of transaction records
+//!
+//! A journal is a directory of segment files, `<id>.ypbn`, holding records in
+//! the binary `YPBN` format, so every segment is also a valid binary file.
+//! Records are only ever appended to the last segment; once it grows past
+//! [`JournalOptions::max_segment_size`] it is sealed and a new one is started.
+//!
+//! The segments are the write-ahead log and the only source of truth. The
+//! index of a sealed segment (`<id>.idx`: `TX_ID`, timestamp, offset and
+//! length of every record) is derived from it and rebuilt whenever it is
+//! missing or does not match the segment.
+
+use crate::error::ParserError;
+use crate::format::format_bin;
+use crate::parser::ParseLimits;
+use crate::storage::YPBankRecord;
+use crate::timestamp::{TimeUnit, Timestamp};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::RangeBounds;
+use std::path::{Path, PathBuf};
+
+const SEGMENT_EXTENSION: &str = "ypbn";
+const INDEX_EXTENSION: &str = "idx";
+/// Output of a compaction until it replaces the segments
+const COMPACT_FILE: &str = "compact.tmp";
+/// Magic and size in front of every record body
+const HEADER_LEN: usize = 8;
+/// `TX_ID`, timestamp, offset and length, all big-endian
+const INDEX_ENTRY_LEN: usize = 28;
+
+/// Options of a [`Journal`]
+#[derive(Debug, Clone)]
+pub struct JournalOptions {
+    /// Size in bytes after which the last segment is sealed
+    pub max_segment_size: u64,
+    /// Sync every append to disk before returning
+    ///
+    /// Without it, appends are durable only after [`Journal::sync`].
+    pub sync: bool,
+    /// Bounds on the records read from the segments
+    pub limits: ParseLimits,
+}
+
+impl Default for JournalOptions {
+    fn default() -> Self {
+        Self {
+            max_segment_size: 64 * 1024 * 1024,
+            sync: true,
+            limits: ParseLimits::default(),
+        }
+    }
+}
+
+/// Location of a record in its segment
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct IndexEntry {
+    tx_id: u64,
+    /// Milliseconds since the Unix epoch
+    timestamp: u64,
+    offset: u64,
+    /// Length including the header
+    len: u32,
+}
+
+impl IndexEntry {
+    fn end(&self) -> u64 {
+        self.offset + self.len as u64
+    }
+
+    fn encode(&self) -> [u8; INDEX_ENTRY_LEN] {
+        let mut out = [0u8; INDEX_ENTRY_LEN];
+        out[..8].copy_from_slice(&self.tx_id.to_be_bytes());
+        out[8..16].copy_from_slice(&self.timestamp.to_be_bytes());
+        out[16..24].copy_from_slice(&self.offset.to_be_bytes());
+        out[24..].copy_from_slice(&self.len.to_be_bytes());
+        out
+    }
+
+    fn decode(data: &[u8]) -> Self {
+        let u64_at = |i: usize| u64::from_be_bytes(data[i..i + 8].try_into().unwrap());
+        Self {
+            tx_id: u64_at(0),
+            timestamp: u64_at(8),
+            offset: u64_at(16),
+            len: u32::from_be_bytes(data[24..28].try_into().unwrap()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Segment {
+    id: u64,
+    size: u64,
+    entries: Vec<IndexEntry>,
+}
+
+/// Append-only journal of records in a directory
+///
+/// Only one `Journal` may have a directory open at a time. Records are kept
+/// in the order they were appended; [`Journal::compact`] drops all but the
+/// last record of every `TX_ID`.
+pub struct Journal {
+    dir: PathBuf,
+    options: JournalOptions,
+    /// Sorted by id; the last one is being appended to
+    segments: Vec<Segment>,
+    active: File,
+    recovered: u64,
+}
+
+impl Journal {
+    /// Opens the journal in `dir`, creating it if needed
+    ///
+    /// A record that was only partly written when the process stopped is
+    /// cut off the end of the last segment (see [`Journal::recovered_bytes`]).
+    /// Damage anywhere else is reported as [`ParserError::CorruptJournal`].
+    pub fn open(dir: impl AsRef<Path>, options: JournalOptions) -> Result<Self, ParserError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(io_error)?;
+        // A compaction that did not finish left its output behind
+        remove_if_exists(&dir.join(COMPACT_FILE))?;
+
+        let mut ids = segment_ids(&dir)?;
+        let last = ids.pop().unwrap_or(0);
+
+        let mut segments = Vec::new();
+        for id in ids {
+            segments.push(load_sealed(&dir, id, &options.limits)?);
+        }
+        let (segment, recovered) = recover(&dir, last, &options.limits)?;
+        segments.push(segment);
+        let active = open_segment(&dir, last)?;
+        Ok(Self {
+            dir,
+            options,
+            segments,
+            active,
+            recovered,
+        })
+    }
+
+    /// Number of bytes of a partly written record cut off when opening
+    pub fn recovered_bytes(&self) -> u64 {
+        self.recovered
+    }
+
+    /// Number of records in the journal
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(|s| s.entries.len()).sum()
+    }
+
+    /// Whether the journal holds no records
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends one record
+    pub fn append(&mut self, record: &YPBankRecord) -> Result<(), ParserError> {
+        self.append_all(std::slice::from_ref(record))
+    }
+
+    /// Appends records, syncing once after the last one
+    ///
+    /// A record that cannot be encoded, or that would not be read back within
+    /// [`JournalOptions::limits`], is rejected; the records before it stay
+    /// appended.
+    pub fn append_all(&mut self, records: &[YPBankRecord]) -> Result<(), ParserError> {
+        for record in records {
+            let data = format_bin::encode_record(record)?;
+            let limits = &self.options.limits;
+            limits.check_record_size(data.len() - HEADER_LEN)?;
+            limits.check_description(record.description.len())?;
+            let segment = self.segments.last().unwrap();
+            if segment.size > 0 && segment.size + data.len() as u64 > self.options.max_segment_size
+            {
+                self.roll()?;
+            }
+            let segment = self.segments.last_mut().unwrap();
+            if let Err(e) = self.active.write_all(&data) {
+                // Do not leave a partial record in front of the next append
+                let _ = self.active.set_len(segment.size);
+                return Err(io_error(e));
+            }
+            segment.entries.push(IndexEntry {
+                tx_id: record.tx_id,
+                timestamp: millis(&record.timestamp),
+                offset: segment.size,
+                len: data.len() as u32,
+            });
+            segment.size += data.len() as u64;
+        }
+        if self.options.sync {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Syncs the appended records to disk
+    pub fn sync(&mut self) -> Result<(), ParserError> {
+        self.active.sync_data().map_err(io_error)
+    }
+
+    /// All records in the order they were appended
+    pub fn iter(&self) -> Records<'_> {
+        self.range(..)
+    }
+
+    /// Records with a timestamp in `range`, in the order they were appended
+    ///
+    /// Only the matching records are read from the segments.
+    pub fn range(&self, range: impl RangeBounds<Timestamp>) -> Records<'_> {
+        let positions = self
+            .positions()
+            .filter(|(_, e)| range.contains(&Timestamp::from_millis(e.timestamp)))
+            .collect::<Vec<_>>();
+        Records {
+            journal: self,
+            positions: positions.into_iter(),
+            reader: None,
+        }
+    }
+
+    /// Rewrites the journal keeping only the last record of every `TX_ID`
+    ///
+    /// The kept records are written to a new file which then replaces the
+    /// last segment, and the older segments are removed. If the process stops
+    /// or the removal fails before that, the journal is left as it was or
+    /// with the old segments still in front of the compacted one; compacting
+    /// again removes them. Returns the number of removed records.
+    pub fn compact(&mut self) -> Result<usize, ParserError> {
+        let mut last = HashMap::new();
+        for (position, (_, entry)) in self.positions().enumerate() {
+            last.insert(entry.tx_id, position);
+        }
+        let total = self.len();
+        if last.len() == total && self.segments.len() == 1 {
+            self.remove_stale_segments()?;
+            return Ok(0);
+        }
+        let kept = self
+            .positions()
+            .enumerate()
+            .filter(|(position, (_, entry))| last[&entry.tx_id] == *position)
+            .map(|(_, position)| position)
+            .collect::<Vec<_>>();
+
+        let temp = self.dir.join(COMPACT_FILE);
+        let mut segment = Segment {
+            id: self.segments.last().unwrap().id,
+            size: 0,
+            entries: Vec::new(),
+        };
+        let write = || -> Result<File, ParserError> {
+            let mut out = BufWriter::new(File::create_new(&temp).map_err(io_error)?);
+            let mut reader = None;
+            for (id, entry) in kept {
+                let data = read_raw(&self.dir, &mut reader, id, &entry)?;
+                out.write_all(&data).map_err(io_error)?;
+                segment.entries.push(IndexEntry {
+                    offset: segment.size,
+                    ..entry
+                });
+                segment.size += entry.len as u64;
+            }
+            let file = out.into_inner().map_err(|e| io_error(e.into_error()))?;
+            file.sync_all().map_err(io_error)?;
+            // Opened before the rename, so it follows the file to its place
+            let active = File::options().append(true).open(&temp).map_err(io_error)?;
+            fs::rename(&temp, segment_path(&self.dir, segment.id)).map_err(io_error)?;
+            Ok(active)
+        };
+        let active = match write() {
+            Ok(active) => active,
+            Err(e) => {
+                let _ = fs::remove_file(&temp);
+                return Err(e);
+            }
+        };
+
+        // The compacted segment is the journal from here on, even if the old
+        // segments cannot be removed yet
+        self.active = active;
+        self.segments = vec![segment];
+        self.remove_stale_segments()?;
+        Ok(total - self.len())
+    }
+
+    /// Removes the segment files that are not part of the journal, left
+    /// behind by an interrupted compaction
+    fn remove_stale_segments(&self) -> Result<(), ParserError> {
+        sync_dir(&self.dir)?;
+        let stale = segment_ids(&self.dir)?
+            .into_iter()
+            .filter(|id| self.segments.iter().all(|s| s.id != *id))
+            .collect::<Vec<_>>();
+        if stale.is_empty() {
+            return Ok(());
+        }
+        for id in stale {
+            fs::remove_file(segment_path(&self.dir, id)).map_err(io_error)?;
+            remove_if_exists(&index_path(&self.dir, id))?;
+        }
+        sync_dir(&self.dir)
+    }
+
+    fn positions(&self) -> impl Iterator<Item = (u64, IndexEntry)> + '_ {
+        self.segments
+            .iter()
+            .flat_map(|s| s.entries.iter().map(move |e| (s.id, *e)))
+    }
+
+    /// Seals the last segment and starts a new one
+    fn roll(&mut self) -> Result<(), ParserError> {
+        self.sync()?;
+        let sealed = self.segments.last().unwrap();
+        write_index(&self.dir, sealed)?;
+        let id = sealed.id + 1;
+        self.active = open_segment(&self.dir, id)?;
+        sync_dir(&self.dir)?;
+        self.segments.push(Segment {
+            id,
+            size: 0,
+            entries: Vec::new(),
+        });
+        Ok(())
+    }
+}
+
+/// Iterator over records of a [`Journal`]
+pub struct Records<'a> {
+    journal: &'a Journal,
+    positions: std::vec::IntoIter<(u64, IndexEntry)>,
+    reader: Option<(u64, BufReader<File>)>,
+}
+
+impl Iterator for Records<'_> {
+    type Item = Result<YPBankRecord, ParserError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, entry) = self.positions.next()?;
+        let journal = self.journal;
+        Some(
+            read_raw(&journal.dir, &mut self.reader, id, &entry).and_then(|data| {
+                format_bin::parse_record_body(&data[HEADER_LEN..], &journal.options.limits)
+            }),
+        )
+    }
+}
+
+/// Reads a whole record, reusing the open segment if it is the same
+fn read_raw(
+    dir: &Path,
+    reader: &mut Option<(u64, BufReader<File>)>,
+    id: u64,
+    entry: &IndexEntry,
+) -> Result<Vec<u8>, ParserError> {
+    if reader.as_ref().is_none_or(|(open, _)| *open != id) {
+        let file = File::open(segment_path(dir, id)).map_err(io_error)?;
+        *reader = Some((id, BufReader::new(file)));
+    }
+    let (_, r) = reader.as_mut().unwrap();
+    r.seek(SeekFrom::Start(entry.offset)).map_err(io_error)?;
+    let mut data = vec![0u8; entry.len as usize];
+    r.read_exact(&mut data).map_err(io_error)?;
+    Ok(data)
+}
+
+/// A sealed segment, using its index file if it matches
+fn load_sealed(dir: &Path, id: u64, limits: &ParseLimits) -> Result<Segment, ParserError> {
+    let size = fs::metadata(segment_path(dir, id)).map_err(io_error)?.len();
+    if let Some(entries) = read_index(dir, id, size)? {
+        return Ok(Segment { id, size, entries });
+    }
+    let data = fs::read(segment_path(dir, id)).map_err(io_error)?;
+    let segment = match scan(&data, limits) {
+        (entries, None) => Segment { id, size, entries },
+        (_, Some(damage)) => return Err(corrupt(dir, id, damage)),
+    };
+    write_index(dir, &segment)?;
+    Ok(segment)
+}
+
+/// The last segment, with a partly written record cut off its end
+fn recover(dir: &Path, id: u64, limits: &ParseLimits) -> Result<(Segment, u64), ParserError> {
+    let path = segment_path(dir, id);
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(io_error(e)),
+    };
+    let (entries, damage) = scan(&data, limits);
+    let size = match damage {
+        None => data.len() as u64,
+        Some(damage) if damage.torn => {
+            let file = File::options().write(true).open(&path).map_err(io_error)?;
+            file.set_len(damage.offset).map_err(io_error)?;
+            file.sync_all().map_err(io_error)?;
+            damage.offset
+        }
+        Some(damage) => return Err(corrupt(dir, id, damage)),
+    };
+    // Only sealed segments keep an index
+    remove_if_exists(&index_path(dir, id))?;
+    let recovered = data.len() as u64 - size;
+    Ok((Segment { id, size, entries }, recovered))
+}
+
+/// The first record that could not be read
+struct Damage {
+    offset: u64,
+    /// The record runs past the end of the data with nothing readable after
+    /// it, as a partly written one would
+    torn: bool,
+    error: ParserError,
+}
+
+/// Indexes the records of a segment up to the first damaged one
+fn scan(data: &[u8], limits: &ParseLimits) -> (Vec<IndexEntry>, Option<Damage>) {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let rest = &data[offset..];
+        let damage = |torn, error| Damage {
+            offset: offset as u64,
+            torn,
+            error,
+        };
+        if rest.len() < HEADER_LEN {
+            return (
+                entries,
+                Some(damage(true, invalid_record("truncated header"))),
+            );
+        }
+        let size = u32::from_be_bytes(rest[4..8].try_into().unwrap()) as usize;
+        let end = HEADER_LEN.saturating_add(size);
+        // A complete record that does not parse is damage, and so is a size
+        // running past the end with whole records after it. Space that was
+        // allocated but never written reads as zeros.
+        let torn = (end > rest.len() && !has_record(&rest[HEADER_LEN..], limits))
+            || rest.iter().all(|&b| b == 0);
+        let body = format_bin::check_magic(rest[..4].try_into().unwrap())
+            .and_then(|()| limits.check_record_size(size))
+            .and_then(|()| {
+                if end > rest.len() {
+                    Err(invalid_record("truncated record"))
+                } else {
+                    format_bin::parse_record_body(&rest[HEADER_LEN..end], limits)
+                }
+            });
+        match body {
+            Ok(record) => entries.push(IndexEntry {
+                tx_id: record.tx_id,
+                timestamp: millis(&record.timestamp),
+                offset: offset as u64,
+                len: end as u32,
+            }),
+            Err(error) => return (entries, Some(damage(torn, error))),
+        }
+        offset += end;
+    }
+    (entries, None)
+}
+
+/// Whether a whole readable record starts anywhere in `data`
+fn has_record(data: &[u8], limits: &ParseLimits) -> bool {
+    (0..data.len().saturating_sub(HEADER_LEN - 1)).any(|start| {
+        let rest = &data[start..];
+        let size = u32::from_be_bytes(rest[4..8].try_into().unwrap()) as usize;
+        let end = HEADER_LEN.saturating_add(size);
+        format_bin::check_magic(rest[..4].try_into().unwrap()).is_ok()
+            && end <= rest.len()
+            && limits.check_record_size(size).is_ok()
+            && format_bin::parse_record_body(&rest[HEADER_LEN..end], limits).is_ok()
+    })
+}
+
+/// Ids of the segment files in `dir`, sorted
+fn segment_ids(dir: &Path) -> Result<Vec<u64>, ParserError> {
+    let mut ids = Vec::new();
+    for entry in fs::read_dir(dir).map_err(io_error)? {
+        let path = entry.map_err(io_error)?.path();
+        if path.extension() == Some(SEGMENT_EXTENSION.as_ref())
+            && let Some(id) = path.file_stem().and_then(|s| s.to_str()?.parse().ok())
+        {
+            ids.push(id);
+        }
+    }
+    ids.sort_unstable();
+    Ok(ids)
+}
+
+/// The index of a segment of `size` bytes, `None` if it is missing or stale
+fn read_index(dir: &Path, id: u64, size: u64) -> Result<Option<Vec<IndexEntry>>, ParserError> {
+    let data = match fs::read(index_path(dir, id)) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(io_error(e)),
+    };
+    if data.len() % INDEX_ENTRY_LEN != 0 {
+        return Ok(None);
+    }
+    let entries: Vec<_> = data
+        .chunks(INDEX_ENTRY_LEN)
+        .map(IndexEntry::decode)
+        .collect();
+    // The records must follow each other and cover the whole segment
+    let mut end = 0;
+    for entry in &entries {
+        if entry.offset != end {
+            return Ok(None);
+        }
+        end = entry.end();
+    }
+    Ok((end == size).then_some(entries))
+}
+
+fn write_index(dir: &Path, segment: &Segment) -> Result<(), ParserError> {
+    let data: Vec<u8> = segment.entries.iter().flat_map(|e| e.encode()).collect();
+    fs::write(index_path(dir, segment.id), data).map_err(io_error)
+}
+
+fn open_segment(dir: &Path, id: u64) -> Result<File, ParserError> {
+    File::options()
+        .append(true)
+        .create(true)
+        .open(segment_path(dir, id))
+        .map_err(io_error)
+}
+
+fn segment_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{:020}.{}", id, SEGMENT_EXTENSION))
+}
+
+fn index_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{:020}.{}", id, INDEX_EXTENSION))
+}
+
+/// Makes created, renamed and removed files in `dir` durable
+fn sync_dir(dir: &Path) -> Result<(), ParserError> {
+    #[cfg(unix)]
+    File::open(dir)
+        .and_then(|d| d.sync_all())
+        .map_err(io_error)?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> Result<(), ParserError> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(io_error(e)),
+        _ => Ok(()),
+    }
+}
+
+/// Timestamp as stored in the binary format; encoding has already rejected
+/// the ones that do not fit
+fn millis(timestamp: &Timestamp) -> u64 {
+    timestamp.to_unix(TimeUnit::Millis).unwrap_or(u64::MAX)
+}
+
+fn corrupt(dir: &Path, id: u64, damage: Damage) -> ParserError {
+    ParserError::CorruptJournal {
+        path: segment_path(dir, id).display().to_string(),
+        offset: damage.offset,
+        message: damage.error.to_string(),
+    }
+}
+
+fn invalid_record(msg: &str) -> ParserError {
+    ParserError::InvalidRecord {
+        message: msg.to_string(),
+    }
+}
+
+fn io_error(e: std::io::Error) -> ParserError {
+    ParserError::IO {
+        message: e.to_string(),
+        error: e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{YPBankRecordStatus, YPBankRecordType};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("ypbank-journal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn record(tx_id: u64, millis: u64) -> YPBankRecord {
+        YPBankRecord {
+            tx_id,
+            tx_type: YPBankRecordType::DEPOSIT,
+            from_user_id: 0,
+            to_user_id: 9,
+            amount: 100 * tx_id,
+            currency: None,
+            timestamp: Timestamp::from_millis(millis),
+            status: YPBankRecordStatus::SUCCESS,
+            description: format!("record {}", tx_id),
+            extra: Default::default(),
+        }
+    }
+
+    fn read_all(journal: &Journal) -> Vec<YPBankRecord> {
+        journal.iter().collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn test_append_and_reopen() {
+        let dir = temp_dir("reopen");
+        let records: Vec<_> = (1..=5).map(|i| record(i, 1000 * i)).collect();
+        let mut journal = Journal::open(&dir, JournalOptions::default()).unwrap();
+        journal.append_all(&records[..3]).unwrap();
+        journal.append(&records[3]).unwrap();
+        drop(journal);
+
+        let mut journal = Journal::open(&dir, JournalOptions::default()).unwrap();
+        assert_eq!(journal.recovered_bytes(), 0);
+        journal.append(&records[4]).unwrap();
+        assert_eq!(read_all(&journal), records);
+
+        // Every segment is a valid binary file
+        let data = fs::read(segment_path(&dir, 0)).unwrap();
+        let storage = crate::format::Format::Bin
+            .read(&mut data.as_slice(), &Default::default())
+            .unwrap();
+        assert_eq!(storage.records(), records);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_partial_record_is_recovered() {
+        let dir = temp_dir("recover");
+        let mut journal = Journal::open(&dir, JournalOptions::default()).unwrap();
+        journal.append_all(&[record(1, 1), record(2, 2)]).unwrap();
+        drop(journal);
+
+        let path = segment_path(&dir, 0);
+        let complete = fs::metadata(&path).unwrap().len();
+        let partial = format_bin::encode_record(&record(3, 3)).unwrap();
+        for tail in [&partial[..5], &partial[..partial.len() - 1], &[0u8; 64][..]] {
+            let mut file = File::options().append(true).open(&path).unwrap();
+            file.write_all(tail).unwrap();
+            drop(file);
+
+            let journal = Journal::open(&dir, JournalOptions::default()).unwrap();
+            assert_eq!(journal.recovered_bytes(), tail.len() as u64);
+            assert_eq!(fs::metadata(&path).unwrap().len(), complete);
+            assert_eq!(read_all(&journal), [record(1, 1), record(2, 2)]);
+        }
+
+        // Damage before the last record is not repaired
+        let mut data = fs::read(&path).unwrap();
+        data[0] = b'X';
+        fs::write(&path, &data).unwrap();
+        assert!(matches!(
+            Journal::open(&dir, JournalOptions::default()),
+            Err(ParserError::CorruptJournal { offset: 0, .. })
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_damage_is_not_cut_off() {
+        let dir = temp_dir("damage");
+        let mut journal = Journal::open(&dir, JournalOptions::default()).unwrap();
+        journal.append_all(&[record(1, 1), record(2, 2)]).unwrap();
+        drop(journal);
+        let path = segment_path(&dir, 0);
+        let data = fs::read(&path).unwrap();
+        let second = format_bin::encode_record(&record(1, 1)).unwrap().len();
+
+        // A whole last record that does not parse
+        let mut damaged = data.clone();
+        damaged[second + HEADER_LEN + 8] = 9;
+        // A size running past the end in front of a whole record
+        let mut oversized = data.clone();
+        oversized[4..8].copy_from_slice(&1000u32.to_be_bytes());
+        for (damaged, offset) in [(damaged, second as u64), (oversized, 0)] {
+            fs::write(&path, &damaged).unwrap();
+            assert!(matches!(
+                Journal::open(&dir, JournalOptions::default()),
+                Err(ParserError::CorruptJournal { offset: o, .. }) if o == offset
+            ));
+            assert_eq!(fs::read(&path).unwrap(), damaged);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_records_beyond_the_limits_are_rejected() {
+        let dir = temp_dir("limits");
+        let mut journal = Journal::open(&dir, JournalOptions::default()).unwrap();
+        journal.append(&record(1, 1)).unwrap();
+        let mut long = record(2, 2);
+        long.description = "x".repeat(70_000);
+        assert!(matches!(
+            journal.append_all(&[record(3, 3), long]),
+            Err(ParserError::DescriptionTooLong { .. })
+        ));
+        drop(journal);
+
+        let journal = Journal::open(&dir, JournalOptions::default()).unwrap();
+        assert_eq!(journal.recovered_bytes(), 0);
+        assert_eq!(read_all(&journal), [record(1, 1), record(3, 3)]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_segments_and_time_range() {
+        let dir = temp_dir("range");
+        let options = JournalOptions {
+            max_segment_size: 150,
+            sync: false,
+            ..JournalOptions::default()
+        };
+        let records: Vec<_> = (1..=10).map(|i| record(i, 1000 * (i % 5))).collect();
+        let mut journal = Journal::open(&dir, options.clone()).unwrap();
+        journal.append_all(&records).unwrap();
+        journal.sync().unwrap();
+        assert!(journal.segments.len() > 2);
+        drop(journal);
+
+        // A stale index is rebuilt from its segment
+        fs::write(index_path(&dir, 0), [0u8; INDEX_ENTRY_LEN]).unwrap();
+        fs::remove_file(index_path(&dir, 1)).unwrap();
+        let journal = Journal::open(&dir, options).unwrap();
+        assert_eq!(journal.len(), 10);
+        let range = journal
+            .range(Timestamp::from_millis(1000)..Timestamp::from_millis(3000))
+            .map(|r| r.unwrap().tx_id)
+            .collect::<Vec<_>>();
+        assert_eq!(range, [1, 2, 6, 7]);
+        let from = journal
+            .range(Timestamp::from_millis(4000)..)
+            .map(|r| r.unwrap().tx_id)
+            .collect::<Vec<_>>();
+        assert_eq!(from, [4, 9]);
+        assert_eq!(read_all(&journal), records);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_compaction_keeps_last_record_per_tx_id() {
+        let dir = temp_dir("compact");
+        let options = JournalOptions {
+            max_segment_size: 150,
+            ..JournalOptions::default()
+        };
+        let mut journal = Journal::open(&dir, options.clone()).unwrap();
+        for i in 1..=6 {
+            journal.append(&record(i % 3, i)).unwrap();
+        }
+        assert_eq!(journal.compact().unwrap(), 3);
+        assert_eq!(journal.compact().unwrap(), 0);
+        let expected = [record(1, 4), record(2, 5), record(0, 6)];
+        assert_eq!(read_all(&journal), expected);
+
+        // Segments that a failed compaction left behind are removed
+        let active = journal.segments[0].id;
+        assert!(active > 0);
+        fs::copy(segment_path(&dir, active), segment_path(&dir, 0)).unwrap();
+        assert_eq!(journal.compact().unwrap(), 0);
+        assert!(!segment_path(&dir, 0).exists());
+
+        journal.append(&record(7, 7)).unwrap();
+        drop(journal);
+        let segments = fs::read_dir(&dir).unwrap().count();
+        let journal = Journal::open(&dir, options).unwrap();
+        assert!(segments <= 3, "{} files", segments);
+        assert_eq!(read_all(&journal)[..3], expected);
+        assert_eq!(journal.len(), 4);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
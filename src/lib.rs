@@ -3,7 +3,18 @@
 //! Supports reading and writing bank records in TXT, CSV, and binary formats.
 #![warn(missing_docs)]
 pub mod cli;
+pub mod commands;
+pub mod currency;
 pub mod error;
+pub mod filter;
 pub mod format;
+pub mod journal;
+mod json;
 pub mod parser;
+pub mod reconcile;
+#[cfg(feature = "server")]
+pub mod server;
 pub mod storage;
+pub mod timestamp;
+#[cfg(feature = "tui")]
+pub mod viewer;
@@ -1,7 +1,8 @@
 //! Core parser trait definition.
 
 use crate::error::ParserError;
-use crate::storage::YPBankStorage;
+use crate::storage::{YPBankRecord, YPBankStorage};
+use std::io::{BufRead, Read};
 
 /// Trait for parsing and writing YPBankStorage data
 pub trait Parser {
@@ -14,3 +15,129 @@ pub trait Parser {
     /// Creates new parser from storage
     fn from_storage(storage: YPBankStorage) -> Self;
 }
+
+/// Upper bounds on untrusted input, honoured by every parser
+///
+/// Exceeding a limit fails the read with a dedicated [`ParserError`] variant
+/// before the offending data is buffered.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ParseLimits {
+    /// Largest record in bytes: a binary record body or a text record block
+    pub max_record_size: usize,
+    /// Longest description in bytes
+    pub max_description_len: usize,
+    /// Longest line of CSV or TXT data in bytes, without the terminator
+    pub max_line_len: usize,
+    /// Largest number of records in one input
+    pub max_records: usize,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        Self {
+            max_record_size: 1 << 20,
+            max_description_len: 64 << 10,
+            max_line_len: 1 << 20,
+            max_records: usize::MAX,
+        }
+    }
+}
+
+impl ParseLimits {
+    /// Limits that accept any input
+    pub fn unlimited() -> Self {
+        Self {
+            max_record_size: usize::MAX,
+            max_description_len: usize::MAX,
+            max_line_len: usize::MAX,
+            max_records: usize::MAX,
+        }
+    }
+
+    /// Fails if a record of `size` bytes is too large
+    pub(crate) fn check_record_size(&self, size: usize) -> Result<(), ParserError> {
+        if size > self.max_record_size {
+            return Err(ParserError::RecordTooLarge {
+                size,
+                max: self.max_record_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Fails if a description of `len` bytes is too long
+    pub(crate) fn check_description(&self, len: usize) -> Result<(), ParserError> {
+        if len > self.max_description_len {
+            return Err(ParserError::DescriptionTooLong {
+                len,
+                max: self.max_description_len,
+            });
+        }
+        Ok(())
+    }
+
+    /// Fails if another record after `count` ones would exceed the maximum
+    pub(crate) fn check_record_count(&self, count: usize) -> Result<(), ParserError> {
+        if count >= self.max_records {
+            return Err(ParserError::TooManyRecords {
+                max: self.max_records,
+            });
+        }
+        Ok(())
+    }
+
+    /// Adds a record to the storage unless it already holds the maximum
+    pub(crate) fn push(
+        &self,
+        storage: &mut YPBankStorage,
+        record: YPBankRecord,
+    ) -> Result<(), ParserError> {
+        self.check_record_count(storage.records().len())?;
+        storage.push(record);
+        Ok(())
+    }
+
+    /// Reads the next line without its terminator, `None` at the end of input
+    ///
+    /// At most [`ParseLimits::line_cap`] bytes are buffered.
+    pub(crate) fn read_line(&self, r: &mut impl BufRead) -> Result<Option<String>, ParserError> {
+        let mut line = Vec::new();
+        r.take(self.line_cap())
+            .read_until(b'\n', &mut line)
+            .map_err(|error| ParserError::IO {
+                message: error.to_string(),
+                error,
+            })?;
+        self.finish_line(line)
+    }
+
+    /// Bytes to read for one line: the longest line plus a `\r\n` terminator
+    pub(crate) fn line_cap(&self) -> u64 {
+        (self.max_line_len as u64).saturating_add(2)
+    }
+
+    /// Strips the terminator from a line read with at most
+    /// [`ParseLimits::line_cap`] bytes and checks its length
+    pub(crate) fn finish_line(&self, mut line: Vec<u8>) -> Result<Option<String>, ParserError> {
+        if line.is_empty() {
+            return Ok(None);
+        }
+        let len = line.len()
+            - line
+                .iter()
+                .rev()
+                .take_while(|&&b| b == b'\n' || b == b'\r')
+                .count();
+        if len > self.max_line_len {
+            return Err(ParserError::LineTooLong {
+                max: self.max_line_len,
+            });
+        }
+        line.truncate(len);
+        String::from_utf8(line)
+            .map(Some)
+            .map_err(|_| ParserError::InvalidRecord {
+                message: "line is not valid UTF-8".to_string(),
+            })
+    }
+}
@@ -0,0 +1,252 @@
+//! Reconciliation of transaction records from several sources by `TX_ID`
+
+use crate::json;
+use crate::storage::{YPBankRecord, YPBankStorage};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use strum_macros::{Display, EnumString};
+
+/// Kind of disagreement between the sources
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum Category {
+    /// A source holds the transaction more than once
+    Duplicate,
+    /// The transaction is missing from at least one source
+    Missing,
+    /// The sources disagree on the status
+    StatusMismatch,
+    /// The sources disagree on the amount
+    AmountMismatch,
+}
+
+/// A transaction on which the sources disagree
+#[derive(Debug, PartialEq, Clone)]
+pub struct Discrepancy {
+    /// Transaction identifier
+    pub tx_id: u64,
+    /// Kind of disagreement
+    pub category: Category,
+    /// Value per source, in the order of [`Report::sources`]: the number of
+    /// records for duplicates, the amount for amount mismatches and the status
+    /// otherwise; `None` where the transaction is missing
+    pub values: Vec<Option<String>>,
+}
+
+/// Result of a reconciliation
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Report {
+    /// Names of the sources
+    pub sources: Vec<String>,
+    /// Number of transactions on which all sources agree
+    pub matched: usize,
+    /// Disagreements, ordered by `TX_ID` and category
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+/// Matches the records of named sources by `TX_ID`
+///
+/// A transaction agrees when every source holds it exactly once with the same
+/// status and amount. Duplicates and missing records are reported without
+/// comparing the fields further.
+pub fn reconcile(sources: &[(&str, &YPBankStorage)]) -> Report {
+    let mut by_id: BTreeMap<u64, Vec<Vec<&YPBankRecord>>> = BTreeMap::new();
+    for (index, (_, storage)) in sources.iter().enumerate() {
+        for record in storage.records() {
+            by_id
+                .entry(record.tx_id)
+                .or_insert_with(|| vec![Vec::new(); sources.len()])[index]
+                .push(record);
+        }
+    }
+
+    let mut report = Report {
+        sources: sources.iter().map(|(name, _)| name.to_string()).collect(),
+        ..Report::default()
+    };
+    for (tx_id, records) in by_id {
+        let discrepancy =
+            |category, value: &dyn Fn(&[&YPBankRecord]) -> Option<String>| Discrepancy {
+                tx_id,
+                category,
+                values: records.iter().map(|r| value(r)).collect(),
+            };
+        let status = |r: &[&YPBankRecord]| r.first().map(|r| r.status.to_string());
+        let amount = |r: &[&YPBankRecord]| r.first().map(|r| r.amount.to_string());
+
+        if records.iter().any(|r| r.len() > 1) {
+            let count = |r: &[&YPBankRecord]| (!r.is_empty()).then(|| r.len().to_string());
+            report
+                .discrepancies
+                .push(discrepancy(Category::Duplicate, &count));
+            continue;
+        }
+        if records.iter().any(|r| r.is_empty()) {
+            report
+                .discrepancies
+                .push(discrepancy(Category::Missing, &status));
+            continue;
+        }
+        let first = records[0][0];
+        let mut agrees = true;
+        if records.iter().any(|r| r[0].status != first.status) {
+            report
+                .discrepancies
+                .push(discrepancy(Category::StatusMismatch, &status));
+            agrees = false;
+        }
+        if records.iter().any(|r| r[0].amount != first.amount) {
+            report
+                .discrepancies
+                .push(discrepancy(Category::AmountMismatch, &amount));
+            agrees = false;
+        }
+        if agrees {
+            report.matched += 1;
+        }
+    }
+    report
+}
+
+impl Report {
+    /// Writes the discrepancies as CSV with a `TX_ID,CATEGORY,<source>...` header
+    pub fn write_csv<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let names: Vec<String> = self.sources.iter().map(|s| s.to_uppercase()).collect();
+        writeln!(w, "TX_ID,CATEGORY,{}", names.join(","))?;
+        for d in &self.discrepancies {
+            let values: Vec<&str> = d
+                .values
+                .iter()
+                .map(|v| v.as_deref().unwrap_or(""))
+                .collect();
+            writeln!(w, "{},{},{}", d.tx_id, d.category, values.join(","))?;
+        }
+        Ok(())
+    }
+
+    /// Writes the report as a JSON object
+    ///
+    /// Discrepancies are objects with `tx_id`, `category` and one key per
+    /// source, which is `null` where the transaction is missing.
+    pub fn write_json<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let sources: Vec<String> = self.sources.iter().map(|s| json::string(s)).collect();
+        write!(
+            w,
+            "{{\"sources\":[{}],\"matched\":{},\"discrepancies\":[",
+            sources.join(","),
+            self.matched
+        )?;
+        for (i, d) in self.discrepancies.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            write!(
+                w,
+                "{}{{\"tx_id\":{},\"category\":{}",
+                separator,
+                d.tx_id,
+                json::string(&d.category.to_string())
+            )?;
+            for (source, value) in sources.iter().zip(&d.values) {
+                let value = value.as_deref().map_or("null".to_string(), json::string);
+                write!(w, ",{}:{}", source, value)?;
+            }
+            write!(w, "}}")?;
+        }
+        writeln!(w, "]}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{ExtraFields, YPBankRecordStatus, YPBankRecordType};
+    use crate::timestamp::Timestamp;
+
+    fn storage(records: &[(u64, YPBankRecordStatus, u64)]) -> YPBankStorage {
+        let mut storage = YPBankStorage::new();
+        for (tx_id, status, amount) in records {
+            storage.push(YPBankRecord {
+                tx_id: *tx_id,
+                tx_type: YPBankRecordType::DEPOSIT,
+                from_user_id: 0,
+                to_user_id: 1,
+                amount: *amount,
+                currency: None,
+                timestamp: Timestamp::from_millis(0),
+                status: status.clone(),
+                description: "test".to_string(),
+                extra: ExtraFields::new(),
+            });
+        }
+        storage
+    }
+
+    fn sample() -> Report {
+        use YPBankRecordStatus::*;
+        let ledger = storage(&[(1, SUCCESS, 100), (2, PENDING, 50), (3, SUCCESS, 7)]);
+        let bank = storage(&[(1, SUCCESS, 100), (2, SUCCESS, 50), (4, FAILURE, 1)]);
+        let processor = storage(&[
+            (1, SUCCESS, 100),
+            (2, SUCCESS, 55),
+            (3, SUCCESS, 7),
+            (4, FAILURE, 1),
+            (4, FAILURE, 1),
+        ]);
+        reconcile(&[
+            ("ledger", &ledger),
+            ("bank", &bank),
+            ("processor", &processor),
+        ])
+    }
+
+    #[test]
+    fn categorizes_discrepancies() {
+        let report = sample();
+        assert_eq!(report.matched, 1);
+        let found: Vec<(u64, Category)> = report
+            .discrepancies
+            .iter()
+            .map(|d| (d.tx_id, d.category))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (2, Category::StatusMismatch),
+                (2, Category::AmountMismatch),
+                (3, Category::Missing),
+                (4, Category::Duplicate),
+            ]
+        );
+        assert_eq!(
+            report.discrepancies[2].values,
+            [
+                Some("SUCCESS".to_string()),
+                None,
+                Some("SUCCESS".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_csv_and_json() {
+        let report = sample();
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(
+            csv,
+            "TX_ID,CATEGORY,LEDGER,BANK,PROCESSOR\n\
+             2,status_mismatch,PENDING,SUCCESS,SUCCESS\n\
+             2,amount_mismatch,50,50,55\n\
+             3,missing,SUCCESS,,SUCCESS\n\
+             4,duplicate,,1,2\n"
+        );
+
+        let mut json = Vec::new();
+        report.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.starts_with(r#"{"sources":["ledger","bank","processor"],"matched":1,"#));
+        assert!(json.contains(
+            r#"{"tx_id":3,"category":"missing","ledger":"SUCCESS","bank":null,"processor":"SUCCESS"}"#
+        ));
+    }
+}
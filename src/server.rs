@@ -0,0 +1,478 @@
+//! Local HTTP service converting, validating and summarizing record files
+//!
+//! Every endpoint takes a record file as the `POST` body:
+//!
+//! - `/convert?from=FMT&to=FMT` answers with the file converted to `to`;
+//! - `/validate?format=FMT` answers with `{"valid":true,"records":N}` or
+//!   `{"valid":false,"errors":["..."]}`;
+//! - `/stats?format=FMT` answers with the counts and totals of `ypbank stats`
+//!   as JSON.
+//!
+//! The body is decoded record by record as it arrives, with the limits of
+//! [`ServerOptions`], and each record is encoded for `/convert` or counted
+//! for `/validate` and `/stats` at once, as in [`RecordReader`] and
+//! [`RecordWriter`]. tiny_http answers only after the body is read, so the
+//! converted file is held in memory until then instead of being sent in
+//! chunks; a body over [`ServerOptions::max_body_size`] is answered with 413,
+//! which bounds that memory, at the cost of refusing larger files that
+//! `ypbank convert` would take. Failures are answered with the JSON object of
+//! `--error-format json` and a status matching its class: 400 for usage
+//! errors, 422 for records that cannot be parsed or written and 500
+//! otherwise.
+
+use crate::commands::error_json;
+use crate::commands::stats::Stats;
+use crate::error::{CliError, ErrorClass, ParserError};
+use crate::format::record_reader::RecordReader;
+use crate::format::record_writer::RecordWriter;
+use crate::format::{Format, FormatOptions};
+use crate::json;
+use crate::parser::ParseLimits;
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, StatusCode};
+
+/// Options of a [`Server`]
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+    /// Bounds on the request bodies
+    pub limits: ParseLimits,
+    /// Largest request body in bytes
+    pub max_body_size: u64,
+    /// Number of requests served at once
+    pub workers: usize,
+    /// Log every request on stderr
+    pub verbose: bool,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self {
+            limits: ParseLimits::default(),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            workers: 4,
+            verbose: false,
+        }
+    }
+}
+
+/// Default of [`ServerOptions::max_body_size`], 64 MiB
+pub const DEFAULT_MAX_BODY_SIZE: u64 = 64 << 20;
+
+type Reply = Response<Box<dyn Read + Send>>;
+
+/// HTTP server bound to a local address
+pub struct Server {
+    http: Arc<tiny_http::Server>,
+    options: ServerOptions,
+}
+
+impl Server {
+    /// Listens on `addr`, e.g. `127.0.0.1:8080`; port 0 picks a free port
+    pub fn bind(addr: &str, options: ServerOptions) -> Result<Self, CliError> {
+        let http = tiny_http::Server::http(addr).map_err(|e| {
+            let error = io::Error::other(e);
+            CliError::IO {
+                message: format!("cannot listen on {}: {}", addr, error),
+                error,
+            }
+        })?;
+        Ok(Self {
+            http: Arc::new(http),
+            options,
+        })
+    }
+
+    /// The address the server listens on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.http
+            .server_addr()
+            .to_ip()
+            .expect("bound to an IP address")
+    }
+
+    /// Serves requests until the process exits
+    pub fn run(self) {
+        let workers: Vec<_> = (1..self.options.workers.max(1))
+            .map(|_| {
+                let http = Arc::clone(&self.http);
+                let options = self.options.clone();
+                thread::spawn(move || serve(&http, &options))
+            })
+            .collect();
+        serve(&self.http, &self.options);
+        for worker in workers {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn serve(http: &tiny_http::Server, options: &ServerOptions) {
+    for mut request in http.incoming_requests() {
+        let reply = route(&mut request, options).unwrap_or_else(|e| error_reply(&e));
+        if options.verbose {
+            eprintln!(
+                "{} {} {}",
+                request.method(),
+                request.url(),
+                reply.status_code().0
+            );
+        }
+        // The client may have gone away; that only concerns this request
+        let _ = request.respond(reply);
+    }
+}
+
+fn route(request: &mut Request, options: &ServerOptions) -> Result<Reply, CliError> {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let allowed: &[&str] = match path {
+        "/convert" => &["from", "to"],
+        "/validate" | "/stats" => &["format"],
+        _ => {
+            return Err(CliError::InvalidValue {
+                name: "endpoint".to_string(),
+                value: path.to_string(),
+            });
+        }
+    };
+    if *request.method() != Method::Post {
+        return Err(CliError::InvalidValue {
+            name: "method".to_string(),
+            value: format!("{} (POST expected)", request.method()),
+        });
+    }
+    let query = Query::parse(query, allowed)?;
+    let max = options.max_body_size;
+    if request
+        .body_length()
+        .is_some_and(|length| length as u64 > max)
+    {
+        return Err(body_too_large(max));
+    }
+    let read_options = FormatOptions::default().with_limits(options.limits);
+    let mut body = LimitedBody::new(request.as_reader(), max);
+    let reply = answer(path, &query, &mut body, &read_options);
+    if body.exceeded() {
+        return Err(body_too_large(max));
+    }
+    reply
+}
+
+fn answer<R: Read>(
+    path: &str,
+    query: &Query,
+    body: R,
+    read_options: &FormatOptions,
+) -> Result<Reply, CliError> {
+    match path {
+        "/convert" => {
+            let (from, to) = (query.format("from")?, query.format("to")?);
+            convert(RecordReader::new(from, body, read_options)?, to)
+        }
+        "/validate" => {
+            let format = query.format("format")?;
+            let count = RecordReader::new(format, body, read_options)
+                .and_then(|mut records| records.try_fold(0, |n, r| r.map(|_| n + 1)));
+            let json = match count {
+                Ok(count) => format!("{{\"valid\":true,\"records\":{}}}", count),
+                // Invalid records are the finding of the check, not a failure
+                Err(e) if !matches!(e, ParserError::IO { .. }) => {
+                    format!(
+                        "{{\"valid\":false,\"errors\":[{}]}}",
+                        json::string(&e.to_string())
+                    )
+                }
+                Err(e) => return Err(e.into()),
+            };
+            Ok(json_reply(200, json))
+        }
+        _ => {
+            let format = query.format("format")?;
+            let mut stats = Stats::default();
+            for record in RecordReader::new(format, body, read_options)? {
+                stats.add(&record?);
+            }
+            let mut json = Vec::new();
+            stats.write_json(&mut json).map_err(|e| CliError::IO {
+                message: e.to_string(),
+                error: e,
+            })?;
+            Ok(json_reply(200, String::from_utf8_lossy(&json).into_owned()))
+        }
+    }
+}
+
+/// Encodes the records as `format` as they are read
+///
+/// tiny_http answers a request only after its body is read, so the encoded
+/// file is sent once the last record is encoded, with its `Content-Length`.
+/// Until then an error, e.g. a record the format cannot hold, can still be
+/// answered with its status.
+fn convert<R: Read>(records: RecordReader<R>, format: Format) -> Result<Reply, CliError> {
+    let mut writer = RecordWriter::new(format, Vec::new(), &FormatOptions::default());
+    for record in records {
+        writer.write_record(&record?)?;
+    }
+    let data = writer.finish()?;
+    let length = data.len();
+    let body: Box<dyn Read + Send> = Box::new(io::Cursor::new(data));
+    Ok(
+        Response::new(StatusCode(200), Vec::new(), body, Some(length), None)
+            .with_header(content_type(content_type_of(format)))
+            .with_chunked_threshold(usize::MAX),
+    )
+}
+
+/// Request body that fails once more than `max` bytes are read
+struct LimitedBody<R> {
+    body: io::Take<R>,
+    max: u64,
+}
+
+impl<R: Read> LimitedBody<R> {
+    fn new(body: R, max: u64) -> Self {
+        Self {
+            body: body.take(max.saturating_add(1)),
+            max,
+        }
+    }
+
+    fn exceeded(&self) -> bool {
+        self.body.limit() == 0
+    }
+}
+
+impl<R: Read> Read for LimitedBody<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.body.read(buf)?;
+        if self.exceeded() {
+            return Err(io::Error::other(format!(
+                "request body over {} bytes",
+                self.max
+            )));
+        }
+        Ok(n)
+    }
+}
+
+fn body_too_large(max: u64) -> CliError {
+    CliError::InvalidValue {
+        name: "body".to_string(),
+        value: format!("over {} bytes", max),
+    }
+}
+
+/// Query parameters of a request
+struct Query<'a>(HashMap<&'a str, &'a str>);
+
+impl<'a> Query<'a> {
+    /// Parses `a=1&b=2`, rejecting parameters not in `allowed`
+    fn parse(query: &'a str, allowed: &[&str]) -> Result<Self, CliError> {
+        let mut params = HashMap::new();
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            if !allowed.contains(&name) {
+                return Err(CliError::UnknownArgument {
+                    name: name.to_string(),
+                });
+            }
+            params.insert(name, value);
+        }
+        Ok(Self(params))
+    }
+
+    fn format(&self, name: &str) -> Result<Format, CliError> {
+        let value = self.0.get(name).ok_or_else(|| CliError::MissingArgument {
+            name: name.to_string(),
+        })?;
+        value.parse().map_err(|_| CliError::InvalidValue {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+fn content_type_of(format: Format) -> &'static str {
+    match format {
+        Format::Csv => "text/csv; charset=utf-8",
+        Format::Txt => "text/plain; charset=utf-8",
+        #[cfg(feature = "json")]
+        Format::Json => "application/json",
+        #[cfg(feature = "yaml")]
+        Format::Yaml => "application/yaml",
+        _ => "application/octet-stream",
+    }
+}
+
+fn content_type(value: &str) -> Header {
+    Header::from_bytes(&b"Content-Type"[..], value.as_bytes()).expect("valid header")
+}
+
+fn json_reply(status: u16, json: String) -> Reply {
+    let length = json.len();
+    let body: Box<dyn Read + Send> = Box::new(io::Cursor::new(json.into_bytes()));
+    Response::new(StatusCode(status), Vec::new(), body, Some(length), None)
+        .with_header(content_type("application/json"))
+}
+
+fn error_reply(error: &CliError) -> Reply {
+    let status = match error {
+        CliError::InvalidValue { name, .. } if name == "endpoint" => 404,
+        CliError::InvalidValue { name, .. } if name == "method" => 405,
+        CliError::InvalidValue { name, .. } if name == "body" => 413,
+        _ => match error.class() {
+            ErrorClass::Usage => 400,
+            ErrorClass::Parse => 422,
+            _ => 500,
+        },
+    };
+    json_reply(status, error_json(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::TcpStream;
+
+    const CSV: &str = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n\
+        1,DEPOSIT,0,5,100,1700000000000,SUCCESS,\"first\"\n\
+        2,TRANSFER,5,6,40,1700000001000,PENDING,\"second\"\n";
+
+    fn start() -> SocketAddr {
+        start_with(ServerOptions::default())
+    }
+
+    fn start_with(options: ServerOptions) -> SocketAddr {
+        let server = Server::bind("127.0.0.1:0", options).unwrap();
+        let addr = server.local_addr();
+        thread::spawn(move || server.run());
+        addr
+    }
+
+    /// Sends a request and returns the status, headers and body
+    fn send(addr: SocketAddr, method: &str, path: &str, body: &[u8]) -> (u16, String, Vec<u8>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n",
+            method,
+            path,
+            body.len()
+        )
+        .unwrap();
+        stream.write_all(body).unwrap();
+        receive(stream)
+    }
+
+    /// Sends a `POST` with a chunked body of unknown length
+    fn send_chunked(addr: SocketAddr, path: &str, body: &[u8]) -> (u16, String, Vec<u8>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nTransfer-Encoding: chunked\r\n\r\n",
+            path
+        )
+        .unwrap();
+        for chunk in body.chunks(16) {
+            write!(stream, "{:x}\r\n", chunk.len()).unwrap();
+            stream.write_all(chunk).unwrap();
+            stream.write_all(b"\r\n").unwrap();
+        }
+        stream.write_all(b"0\r\n\r\n").unwrap();
+        receive(stream)
+    }
+
+    fn receive(mut stream: TcpStream) -> (u16, String, Vec<u8>) {
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8(response[..end].to_vec()).unwrap();
+        let status = head[9..12].parse().unwrap();
+        (status, head, response[end + 4..].to_vec())
+    }
+
+    #[test]
+    fn converts_between_formats() {
+        let addr = start();
+        let (status, head, bin) = send(addr, "POST", "/convert?from=csv&to=bin", CSV.as_bytes());
+        assert_eq!(status, 200);
+        assert!(head.contains(&format!("Content-Length: {}", bin.len())));
+        assert!(bin.starts_with(b"YPBN"));
+
+        let (status, head, csv) = send(addr, "POST", "/convert?from=bin&to=csv", &bin);
+        assert_eq!(status, 200);
+        assert!(head.contains("Content-Type: text/csv"));
+        assert_eq!(String::from_utf8(csv).unwrap(), CSV);
+    }
+
+    #[test]
+    fn validates_and_summarizes() {
+        let addr = start();
+        let (status, _, body) = send(addr, "POST", "/validate?format=csv", CSV.as_bytes());
+        assert_eq!(
+            (status, body.as_slice()),
+            (200, &b"{\"valid\":true,\"records\":2}"[..])
+        );
+
+        let invalid = CSV.replace("PENDING", "LOST");
+        let (status, _, body) = send(addr, "POST", "/validate?format=csv", invalid.as_bytes());
+        let body = String::from_utf8(body).unwrap();
+        assert_eq!(status, 200);
+        assert!(
+            body.starts_with("{\"valid\":false,\"errors\":[\""),
+            "{}",
+            body
+        );
+
+        let (status, _, body) = send(addr, "POST", "/stats?format=csv", CSV.as_bytes());
+        let body = String::from_utf8(body).unwrap();
+        assert_eq!(status, 200);
+        assert!(body.starts_with("{\"records\":2,\"by_type\":{\"DEPOSIT\":1,\"TRANSFER\":1}"));
+    }
+
+    #[test]
+    fn reports_errors_as_json() {
+        let addr = start();
+        let huge_amount = CSV.replace(",100,", &format!(",{},", u64::MAX));
+        for (method, path, body, expected) in [
+            ("POST", "/convert?from=csv&to=xml", CSV, 400),
+            ("POST", "/convert?from=csv", CSV, 400),
+            ("POST", "/stats?format=csv&color=red", CSV, 400),
+            ("POST", "/stats?format=txt", CSV, 422),
+            ("POST", "/convert?from=csv&to=txt", "TX_ID\n1\n", 422),
+            ("POST", "/convert?from=csv&to=bin", &huge_amount, 422),
+            ("GET", "/stats?format=csv", "", 405),
+            ("POST", "/reconcile", CSV, 404),
+        ] {
+            let (status, head, body) = send(addr, method, path, body.as_bytes());
+            assert_eq!(status, expected, "{} {}", method, path);
+            assert!(head.contains("Content-Type: application/json"));
+            assert!(body.starts_with(b"{\"class\":"), "{} {}", method, path);
+        }
+    }
+
+    #[test]
+    fn refuses_large_bodies() {
+        let max = CSV.len() as u64;
+        let addr = start_with(ServerOptions {
+            max_body_size: max,
+            ..ServerOptions::default()
+        });
+        let (status, _, _) = send(addr, "POST", "/validate?format=csv", CSV.as_bytes());
+        assert_eq!(status, 200);
+        let (status, _, _) = send_chunked(addr, "/validate?format=csv", CSV.as_bytes());
+        assert_eq!(status, 200);
+
+        let larger = format!("{}3,DEPOSIT,0,5,1,1700000002000,SUCCESS,\"\"\n", CSV);
+        let (status, _, body) = send(addr, "POST", "/convert?from=csv&to=bin", larger.as_bytes());
+        assert_eq!(status, 413);
+        assert!(body.starts_with(b"{\"class\":"));
+        let (status, _, _) = send_chunked(addr, "/convert?from=csv&to=bin", larger.as_bytes());
+        assert_eq!(status, 413);
+    }
+}
@@ -1,10 +1,20 @@
 //! In-memory storage and data types for YPBank transaction records
 
+use crate::currency::Currency;
+use crate::timestamp::{TimeUnit, Timestamp};
+use std::collections::BTreeMap;
 use strum_macros::Display;
 use strum_macros::EnumString;
 
 /// Storage for YPBank records
-#[derive(Debug)]
+///
+/// With the `serde` feature it is serialized as a sequence of records.
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct YPBankStorage {
     records: Vec<YPBankRecord>,
 }
@@ -31,10 +41,29 @@ impl YPBankStorage {
     pub fn push(&mut self, record: YPBankRecord) {
         self.records.push(record);
     }
+
+    /// Consumes the storage, returning its records
+    pub fn into_records(self) -> Vec<YPBankRecord> {
+        self.records
+    }
+}
+
+impl Extend<YPBankRecord> for YPBankStorage {
+    fn extend<I: IntoIterator<Item = YPBankRecord>>(&mut self, records: I) {
+        self.records.extend(records);
+    }
 }
 
 /// A record in the YPBank storage
+///
+/// With the `serde` feature the fields are named as the CSV columns, e.g.
+/// `TX_ID`; extra fields are flattened next to them.
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "SCREAMING_SNAKE_CASE")
+)]
 pub struct YPBankRecord {
     /// Unique transaction identifier
     pub tx_id: u64,
@@ -46,19 +75,76 @@ pub struct YPBankRecord {
     pub to_user_id: u64,
     /// Transaction amount in the smallest currency unit
     pub amount: u64,
-    /// Unix timestamp of the transaction
-    pub timestamp: u64,
+    /// Currency of the amount, if known
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub currency: Option<Currency>,
+    /// Time of the transaction
+    pub timestamp: Timestamp,
     /// Current status of the transaction
     pub status: YPBankRecordStatus,
     /// Free-text description of the transaction
     pub description: Description,
+    /// Additional metadata fields, e.g. `CHANNEL` or `MERCHANT_ID`
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub extra: ExtraFields,
+}
+
+impl YPBankRecord {
+    /// Value of a field named as its CSV column, e.g. `AMOUNT`, or of an
+    /// extra field; `None` for an unknown name or a missing currency
+    ///
+    /// The timestamp is given in milliseconds since the Unix epoch.
+    pub fn field(&self, name: &str) -> Option<String> {
+        Some(match name {
+            "TX_ID" => self.tx_id.to_string(),
+            "TX_TYPE" => self.tx_type.to_string(),
+            "FROM_USER_ID" => self.from_user_id.to_string(),
+            "TO_USER_ID" => self.to_user_id.to_string(),
+            "AMOUNT" => self.amount.to_string(),
+            "CURRENCY" => self.currency?.to_string(),
+            "TIMESTAMP" => self.timestamp.to_unix(TimeUnit::Millis)?.to_string(),
+            "STATUS" => self.status.to_string(),
+            "DESCRIPTION" => self.description.clone(),
+            _ => self.extra.get(name)?.clone(),
+        })
+    }
 }
 
 /// A description attached to a transaction record
 pub type Description = String;
 
+/// Additional named fields of a record, ordered by name
+pub type ExtraFields = BTreeMap<String, String>;
+
+/// Names of the fields stored in dedicated [`YPBankRecord`] members
+pub const STANDARD_FIELDS: [&str; 9] = [
+    "TX_ID",
+    "TX_TYPE",
+    "FROM_USER_ID",
+    "TO_USER_ID",
+    "AMOUNT",
+    "CURRENCY",
+    "TIMESTAMP",
+    "STATUS",
+    "DESCRIPTION",
+];
+
+/// Checks whether `name` can name an extra field: a non-empty identifier of
+/// ASCII letters, digits, `_` and `-` that is not a standard field name
+pub fn is_extra_field_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+        && !STANDARD_FIELDS.contains(&name)
+}
+
 /// Possible transaction types for a bank record
 #[derive(Debug, PartialEq, Clone, Display, EnumString)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum YPBankRecordType {
     /// Funds added to an account
     DEPOSIT,
@@ -70,6 +156,7 @@ pub enum YPBankRecordType {
 
 /// Possible processing statuses for a bank record
 #[derive(Debug, PartialEq, Clone, Display, EnumString)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum YPBankRecordStatus {
     /// Transaction completed successfully
     SUCCESS,
@@ -79,6 +166,68 @@ pub enum YPBankRecordStatus {
     PENDING,
 }
 
+/// Two records shared by the format tests
+///
+/// The first has a currency, an extra field and a description with quotes, a
+/// comma and non-ASCII text; the second has none of these.
+#[cfg(test)]
+pub(crate) fn sample_storage() -> YPBankStorage {
+    let mut storage = YPBankStorage::new();
+    storage.push(YPBankRecord {
+        tx_id: 1,
+        tx_type: YPBankRecordType::TRANSFER,
+        from_user_id: 1,
+        to_user_id: 2,
+        amount: 1500,
+        currency: Some(Currency::from_code("EUR").unwrap()),
+        timestamp: Timestamp::from_millis(1700000000000),
+        status: YPBankRecordStatus::PENDING,
+        description: "Перевод \"за обед\", кафе".to_string(),
+        extra: ExtraFields::from([("CHANNEL".to_string(), "mobile".to_string())]),
+    });
+    storage.push(YPBankRecord {
+        tx_id: 2,
+        tx_type: YPBankRecordType::DEPOSIT,
+        from_user_id: 0,
+        to_user_id: 2,
+        amount: 700,
+        currency: None,
+        timestamp: Timestamp::from_millis(1700000060000),
+        status: YPBankRecordStatus::SUCCESS,
+        description: String::new(),
+        extra: ExtraFields::new(),
+    });
+    storage
+}
+
+/// `count` transfers numbered from 1 for tests of streaming and paging
+///
+/// The sender, the amount and the status vary with `TX_ID`, and the
+/// timestamps grow with it.
+#[cfg(test)]
+pub(crate) fn numbered_storage(count: u64) -> YPBankStorage {
+    let mut storage = YPBankStorage::new();
+    for tx_id in 1..=count {
+        storage.push(YPBankRecord {
+            tx_id,
+            tx_type: YPBankRecordType::TRANSFER,
+            from_user_id: tx_id % 7,
+            to_user_id: 42,
+            amount: (tx_id * 37) % 1000,
+            currency: None,
+            timestamp: Timestamp::from_millis(1633036800000 + tx_id),
+            status: if tx_id % 3 == 0 {
+                YPBankRecordStatus::PENDING
+            } else {
+                YPBankRecordStatus::SUCCESS
+            },
+            description: format!("Payment {}", tx_id),
+            extra: ExtraFields::new(),
+        });
+    }
+    storage
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -98,9 +247,11 @@ mod tests {
             from_user_id: 1,
             to_user_id: 2,
             amount: 100,
-            timestamp: 1638224000,
+            currency: None,
+            timestamp: Timestamp::from_millis(1638224000),
             status: YPBankRecordStatus::SUCCESS,
             description: "Some deposit".to_string(),
+            extra: ExtraFields::new(),
         };
         let expected = record.clone();
         storage.push(record);
@@ -108,6 +259,45 @@ mod tests {
         assert_eq!(storage.records(), &[expected]);
     }
 
+    #[test]
+    fn test_field() {
+        let record = YPBankRecord {
+            tx_id: 3,
+            tx_type: YPBankRecordType::WITHDRAWAL,
+            from_user_id: 4,
+            to_user_id: 0,
+            amount: 250,
+            currency: Currency::from_code("EUR"),
+            timestamp: Timestamp::from_millis(1638224000),
+            status: YPBankRecordStatus::FAILURE,
+            description: "ATM".to_string(),
+            extra: ExtraFields::from([("CHANNEL".to_string(), "atm".to_string())]),
+        };
+        assert_eq!(record.field("TX_TYPE").as_deref(), Some("WITHDRAWAL"));
+        assert_eq!(record.field("CURRENCY").as_deref(), Some("EUR"));
+        assert_eq!(record.field("TIMESTAMP").as_deref(), Some("1638224000"));
+        assert_eq!(record.field("CHANNEL").as_deref(), Some("atm"));
+        assert_eq!(record.field("MERCHANT_ID"), None);
+    }
+
+    #[test]
+    fn test_extra_field_names() {
+        assert!(is_extra_field_name("CHANNEL"));
+        assert!(is_extra_field_name("merchant-id_2"));
+        assert!(!is_extra_field_name(""));
+        assert!(!is_extra_field_name("MERCHANT ID"));
+        assert!(!is_extra_field_name("AMOUNT"));
+    }
+
+    #[test]
+    fn test_extend_and_into_records() {
+        let mut storage = YPBankStorage::new();
+        storage.extend(sample_storage().into_records());
+        storage.extend(sample_storage().into_records());
+        let ids: Vec<_> = storage.into_records().iter().map(|r| r.tx_id).collect();
+        assert_eq!(ids, [1, 2, 1, 2]);
+    }
+
     #[test]
     fn test_get_record() {
         let mut storage = YPBankStorage::new();
@@ -117,9 +307,11 @@ mod tests {
             from_user_id: 2,
             to_user_id: 3,
             amount: 120,
-            timestamp: 1638224111,
+            currency: None,
+            timestamp: Timestamp::from_millis(1638224111),
             status: YPBankRecordStatus::PENDING,
             description: "Some pending transfer".to_string(),
+            extra: ExtraFields::new(),
         };
         let expected = record.clone();
         storage.push(record);
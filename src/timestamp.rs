@@ -0,0 +1,320 @@
+//! Typed timestamps with explicit units and ISO-8601 rendering
+
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+use std::str::FromStr;
+use std::time::Duration;
+use strum_macros::{Display, EnumString};
+use thiserror::Error;
+
+/// Unit of a numeric Unix timestamp
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Display, EnumString)]
+pub enum TimeUnit {
+    /// Seconds since the Unix epoch
+    #[strum(serialize = "s")]
+    Seconds,
+    /// Milliseconds since the Unix epoch
+    #[strum(serialize = "ms")]
+    Millis,
+    /// Microseconds since the Unix epoch
+    #[strum(to_string = "us", serialize = "µs")]
+    Micros,
+}
+
+impl TimeUnit {
+    fn micros(&self) -> u128 {
+        match self {
+            TimeUnit::Seconds => 1_000_000,
+            TimeUnit::Millis => 1_000,
+            TimeUnit::Micros => 1,
+        }
+    }
+}
+
+/// A point in time with microsecond precision, not earlier than the Unix epoch
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Default)]
+pub struct Timestamp {
+    micros: u128,
+}
+
+impl Timestamp {
+    /// Creates a timestamp from a count of `unit`s since the Unix epoch
+    pub fn from_unix(value: u64, unit: TimeUnit) -> Self {
+        Self {
+            micros: value as u128 * unit.micros(),
+        }
+    }
+
+    /// Creates a timestamp from milliseconds since the Unix epoch
+    pub fn from_millis(millis: u64) -> Self {
+        Self::from_unix(millis, TimeUnit::Millis)
+    }
+
+    /// Returns the count of whole `unit`s since the Unix epoch, if it fits in `u64`
+    ///
+    /// A finer precision is truncated; writers use [`Timestamp::to_unix_exact`].
+    pub fn to_unix(&self, unit: TimeUnit) -> Option<u64> {
+        u64::try_from(self.micros / unit.micros()).ok()
+    }
+
+    /// Returns the count of `unit`s since the Unix epoch, failing if it does
+    /// not fit in `u64` or the timestamp is finer than `unit`
+    pub fn to_unix_exact(&self, unit: TimeUnit) -> Result<u64, TimestampError> {
+        if !self.micros.is_multiple_of(unit.micros()) {
+            return Err(TimestampError::Precision(unit));
+        }
+        self.to_unix(unit).ok_or(TimestampError::OutOfRange)
+    }
+
+    /// Parses an ISO-8601 / RFC 3339 date and time with a UTC offset
+    pub fn parse_iso8601(s: &str) -> Option<Self> {
+        let micros = DateTime::parse_from_rfc3339(s).ok()?.timestamp_micros();
+        Some(Self {
+            micros: u128::try_from(micros).ok()?,
+        })
+    }
+
+    /// Renders the timestamp as ISO-8601 at the given UTC offset
+    pub fn to_iso8601(&self, offset: FixedOffset) -> Option<String> {
+        let utc = DateTime::from_timestamp_micros(i64::try_from(self.micros).ok()?)?;
+        Some(
+            utc.with_timezone(&offset)
+                .to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        )
+    }
+
+    /// Absolute distance to `other`, saturating at `u64::MAX` microseconds
+    pub fn abs_diff(&self, other: &Timestamp) -> Duration {
+        let micros = self.micros.abs_diff(other.micros);
+        Duration::from_micros(u64::try_from(micros).unwrap_or(u64::MAX))
+    }
+
+    /// Parses a timestamp written in the given format
+    pub fn parse(s: &str, format: &TimestampFormat) -> Option<Self> {
+        match format {
+            TimestampFormat::Unix(unit) => Some(Self::from_unix(s.parse().ok()?, *unit)),
+            TimestampFormat::Iso8601(_) => Self::parse_iso8601(s),
+        }
+    }
+
+    /// Renders the timestamp in the given format, failing rather than
+    /// truncating it
+    pub fn format(&self, format: &TimestampFormat) -> Result<String, TimestampError> {
+        match format {
+            TimestampFormat::Unix(unit) => self.to_unix_exact(*unit).map(|v| v.to_string()),
+            TimestampFormat::Iso8601(offset) => {
+                self.to_iso8601(*offset).ok_or(TimestampError::OutOfRange)
+            }
+        }
+    }
+}
+
+/// Why a timestamp cannot be written in a format
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Error)]
+pub enum TimestampError {
+    /// The value does not fit in the format
+    #[error("TIMESTAMP out of range")]
+    OutOfRange,
+    /// The timestamp is finer than the unit of the format
+    #[error("TIMESTAMP has sub-{} precision", unit_name(*.0))]
+    Precision(TimeUnit),
+}
+
+fn unit_name(unit: TimeUnit) -> &'static str {
+    match unit {
+        TimeUnit::Seconds => "second",
+        TimeUnit::Millis => "millisecond",
+        TimeUnit::Micros => "microsecond",
+    }
+}
+
+/// Parses a duration written as a count and a [`TimeUnit`], e.g. `500ms` or `2s`
+///
+/// A bare `0` is accepted without a unit.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let value: u64 = s[..split].parse().ok()?;
+    match &s[split..] {
+        "" if value == 0 => Some(Duration::ZERO),
+        unit => Some(match unit.parse().ok()? {
+            TimeUnit::Seconds => Duration::from_secs(value),
+            TimeUnit::Millis => Duration::from_millis(value),
+            TimeUnit::Micros => Duration::from_micros(value),
+        }),
+    }
+}
+
+/// Textual representation of timestamps in the CSV and TXT formats
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TimestampFormat {
+    /// Integer count of units since the Unix epoch
+    Unix(TimeUnit),
+    /// ISO-8601 date and time, rendered at the given UTC offset
+    Iso8601(FixedOffset),
+}
+
+impl Default for TimestampFormat {
+    fn default() -> Self {
+        TimestampFormat::Unix(TimeUnit::Millis)
+    }
+}
+
+impl FromStr for TimestampFormat {
+    type Err = strum::ParseError;
+
+    /// Parses `s`, `ms`, `us` or `iso8601` (rendered in UTC)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "iso8601" => Ok(TimestampFormat::Iso8601(utc())),
+            _ => TimeUnit::from_str(s).map(TimestampFormat::Unix),
+        }
+    }
+}
+
+/// Parses a UTC offset such as `Z`, `UTC`, `+03:00` or `-0500`
+pub fn parse_utc_offset(s: &str) -> Option<FixedOffset> {
+    match s {
+        "Z" | "UTC" => Some(utc()),
+        _ => FixedOffset::from_str(s).ok(),
+    }
+}
+
+fn utc() -> FixedOffset {
+    FixedOffset::east_opt(0).expect("zero offset is valid")
+}
+
+/// Serialized as milliseconds since the Unix epoch, like the default
+/// `TIMESTAMP`; a finer precision is an error
+#[cfg(feature = "serde")]
+impl serde::Serialize for Timestamp {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let millis = self
+            .to_unix_exact(TimeUnit::Millis)
+            .map_err(serde::ser::Error::custom)?;
+        serializer.serialize_u64(millis)
+    }
+}
+
+/// Deserialized from milliseconds since the Unix epoch or an ISO-8601 string
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Timestamp {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::{Error, Unexpected};
+
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = Timestamp;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("milliseconds since the Unix epoch or an ISO-8601 date and time")
+            }
+
+            fn visit_u64<E: Error>(self, v: u64) -> Result<Timestamp, E> {
+                Ok(Timestamp::from_millis(v))
+            }
+
+            fn visit_i64<E: Error>(self, v: i64) -> Result<Timestamp, E> {
+                u64::try_from(v)
+                    .map(Timestamp::from_millis)
+                    .map_err(|_| E::invalid_value(Unexpected::Signed(v), &self))
+            }
+
+            fn visit_str<E: Error>(self, v: &str) -> Result<Timestamp, E> {
+                Timestamp::parse_iso8601(v)
+                    .ok_or_else(|| E::invalid_value(Unexpected::Str(v), &self))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit_conversion() {
+        let ts = Timestamp::from_unix(1700000000, TimeUnit::Seconds);
+        assert_eq!(ts, Timestamp::from_millis(1700000000000));
+        assert_eq!(ts.to_unix(TimeUnit::Micros), Some(1700000000000000));
+        assert_eq!(
+            Timestamp::from_millis(1500).to_unix(TimeUnit::Seconds),
+            Some(1)
+        );
+        assert_eq!(
+            Timestamp::from_millis(u64::MAX).to_unix(TimeUnit::Micros),
+            None
+        );
+    }
+
+    #[test]
+    fn test_exact_conversion() {
+        let ts = Timestamp::from_unix(1700000000123456, TimeUnit::Micros);
+        assert_eq!(ts.to_unix(TimeUnit::Millis), Some(1700000000123));
+        assert_eq!(
+            ts.to_unix_exact(TimeUnit::Millis),
+            Err(TimestampError::Precision(TimeUnit::Millis))
+        );
+        assert_eq!(ts.to_unix_exact(TimeUnit::Micros), Ok(1700000000123456));
+        let format = TimestampFormat::Unix(TimeUnit::Seconds);
+        let error = Timestamp::from_millis(1500).format(&format).unwrap_err();
+        assert_eq!(error.to_string(), "TIMESTAMP has sub-second precision");
+        assert_eq!(
+            Timestamp::from_millis(u64::MAX).to_unix_exact(TimeUnit::Micros),
+            Err(TimestampError::OutOfRange)
+        );
+        let iso = ts.format(&TimestampFormat::Iso8601(utc())).unwrap();
+        assert_eq!(Timestamp::parse_iso8601(&iso), Some(ts));
+    }
+
+    #[test]
+    fn test_iso8601_round_trip_with_offset() {
+        let ts = Timestamp::from_millis(1672531200123);
+        assert_eq!(ts.to_iso8601(utc()).unwrap(), "2023-01-01T00:00:00.123Z");
+        let moscow = parse_utc_offset("+03:00").unwrap();
+        let rendered = ts.to_iso8601(moscow).unwrap();
+        assert_eq!(rendered, "2023-01-01T03:00:00.123+03:00");
+        assert_eq!(Timestamp::parse_iso8601(&rendered), Some(ts));
+    }
+
+    #[test]
+    fn test_parse_rejects_pre_epoch_and_garbage() {
+        assert_eq!(Timestamp::parse_iso8601("1969-12-31T23:59:59Z"), None);
+        assert_eq!(Timestamp::parse_iso8601("yesterday"), None);
+        assert_eq!(Timestamp::parse("12x", &TimestampFormat::default()), None);
+    }
+
+    #[test]
+    fn test_durations() {
+        assert_eq!(parse_duration("0"), Some(Duration::ZERO));
+        assert_eq!(parse_duration("2s"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("7us"), Some(Duration::from_micros(7)));
+        assert_eq!(parse_duration("5"), None);
+        assert_eq!(parse_duration("ms"), None);
+        assert_eq!(parse_duration("1h"), None);
+
+        let a = Timestamp::from_millis(1500);
+        let b = Timestamp::from_unix(1, TimeUnit::Seconds);
+        assert_eq!(a.abs_diff(&b), Duration::from_millis(500));
+        assert_eq!(b.abs_diff(&a), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_format_from_str() {
+        assert_eq!(
+            TimestampFormat::from_str("s"),
+            Ok(TimestampFormat::Unix(TimeUnit::Seconds))
+        );
+        assert_eq!(
+            TimestampFormat::from_str("µs"),
+            Ok(TimestampFormat::Unix(TimeUnit::Micros))
+        );
+        assert_eq!(
+            TimestampFormat::from_str("iso8601"),
+            Ok(TimestampFormat::Iso8601(utc()))
+        );
+        assert!(TimestampFormat::from_str("minutes").is_err());
+    }
+}
@@ -0,0 +1,675 @@
+//! Interactive terminal viewer of record files
+//!
+//! Records are read by a background thread with a [`RecordReader`] and shown
+//! as they arrive, so a huge binary file can be browsed before it is read to
+//! the end. Records are read only while the shown rows do not fill two pages
+//! past the view, unless the whole file is asked for, and the thread reads at
+//! most one batch ahead. The screen holds a table of the records, a pane with
+//! every field of the selected one and a status line. Keys:
+//!
+//! - `↑`/`↓`, `PgUp`/`PgDn`, `Home`/`End` (or `k`/`j`, `g`/`G`) move the
+//!   selection;
+//! - `/` edits the filter, applied while typing: `FIELD=text` keeps records
+//!   whose field, named as its CSV column or an extra field, contains `text`
+//!   ignoring case, a plain `text` matches any field;
+//! - `s` sorts by the next column and `r` reverses the order;
+//! - `:` jumps to the record with a TX_ID;
+//! - `L` reads the rest of the file;
+//! - `Esc` clears the filter and `q` quits.
+
+use crate::error::ParserError;
+use crate::filter::RecordFilter;
+use crate::format::format_csv::{CsvColumn, DEFAULT_COLUMNS};
+use crate::format::record_reader::RecordReader;
+use crate::format::{Format, FormatOptions};
+use crate::storage::{STANDARD_FIELDS, YPBankRecord};
+use crate::timestamp::TimeUnit;
+use chrono::FixedOffset;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph, Row, Table, TableState, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::cmp::Ordering;
+use std::io::{self, Read};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, sync_channel};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Records sent at once by the reading thread
+const BATCH_SIZE: usize = 1024;
+/// Batches the reading thread may send before they are received
+const BATCHES_AHEAD: usize = 1;
+/// Longest wait for a key before new records are shown
+const TICK: Duration = Duration::from_millis(100);
+
+type Batch = Result<Vec<YPBankRecord>, ParserError>;
+
+/// Line being edited at the bottom of the screen
+#[derive(Debug, PartialEq)]
+enum Input {
+    None,
+    Filter,
+    Jump(String),
+}
+
+/// State of the viewer of one file
+pub struct Viewer {
+    title: String,
+    records: Vec<YPBankRecord>,
+    loading: Option<Receiver<Batch>>,
+    /// Read the whole file, not only what the view needs
+    load_all: bool,
+    error: Option<String>,
+    /// Indices of the shown records in display order
+    rows: Vec<usize>,
+    /// Position of the selection in `rows`
+    selected: usize,
+    /// Position in `rows` of the first row on screen
+    offset: usize,
+    /// Rows on screen, for paging
+    page: usize,
+    filter_text: String,
+    filter: RecordFilter,
+    sort: Option<CsvColumn>,
+    descending: bool,
+    input: Input,
+    message: Option<String>,
+}
+
+impl Viewer {
+    /// Starts reading `format` data from `r` in the background
+    pub fn new<R: Read + Send + 'static>(
+        title: &str,
+        format: Format,
+        r: R,
+        options: &FormatOptions,
+    ) -> Self {
+        let (sender, receiver) = sync_channel(BATCHES_AHEAD);
+        let options = options.clone();
+        thread::spawn(move || {
+            let mut reader = match RecordReader::new(format, r, &options) {
+                Ok(reader) => reader,
+                Err(e) => return sender.send(Err(e)),
+            };
+            loop {
+                let mut batch = Vec::with_capacity(BATCH_SIZE);
+                for record in reader.by_ref().take(BATCH_SIZE) {
+                    match record {
+                        Ok(record) => batch.push(record),
+                        Err(e) => {
+                            sender.send(Ok(batch))?;
+                            return sender.send(Err(e));
+                        }
+                    }
+                }
+                if batch.is_empty() {
+                    return Ok(());
+                }
+                sender.send(Ok(batch))?;
+            }
+        });
+        Self {
+            title: title.to_string(),
+            records: Vec::new(),
+            loading: Some(receiver),
+            load_all: false,
+            error: None,
+            rows: Vec::new(),
+            selected: 0,
+            offset: 0,
+            page: 1,
+            filter_text: String::new(),
+            filter: RecordFilter::default(),
+            sort: None,
+            descending: false,
+            input: Input::None,
+            message: None,
+        }
+    }
+
+    /// Runs the viewer in the terminal until the user quits
+    pub fn run(mut self) -> io::Result<()> {
+        let mut terminal = ratatui::try_init()?;
+        let result = self.event_loop(&mut terminal);
+        ratatui::try_restore()?;
+        result
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        loop {
+            // Waits up to a tick for records the view needs
+            let waited = self.receive();
+            terminal.draw(|frame| self.draw(frame))?;
+            let timeout = if waited { Duration::ZERO } else { TICK };
+            if event::poll(timeout)?
+                && let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+                && !self.handle_key(key)
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Whether the view needs more records than are read
+    fn wants_records(&self) -> bool {
+        self.loading.is_some() && (self.load_all || self.rows.len() < self.offset + 3 * self.page)
+    }
+
+    /// Adds the records read within a tick while the view needs them,
+    /// returning whether it waited for them
+    fn receive(&mut self) -> bool {
+        let (first, shown) = (self.records.len(), self.rows.len());
+        let start = Instant::now();
+        let mut waited = false;
+        while self.wants_records() {
+            let Some(receiver) = &self.loading else {
+                break;
+            };
+            let Some(left) = TICK.checked_sub(start.elapsed()) else {
+                break;
+            };
+            waited = true;
+            match receiver.recv_timeout(left) {
+                Ok(Ok(batch)) => {
+                    let filter = &self.filter;
+                    let added = self.records.len()..self.records.len() + batch.len();
+                    let matching = batch.iter().zip(added).filter(|(r, _)| filter.matches(r));
+                    let rows: Vec<usize> = matching.map(|(_, i)| i).collect();
+                    self.records.extend(batch);
+                    // Rows are merged once per call, after the loop
+                    self.rows.extend(rows);
+                }
+                Ok(Err(e)) => self.error = Some(e.to_string()),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => self.loading = None,
+            }
+        }
+        if self.records.len() > first && self.sort.is_some() {
+            self.merge_rows(shown);
+        }
+        waited
+    }
+
+    /// Sorts the rows from position `shown` on, which are of new records,
+    /// and merges them into the sorted rows before them, keeping the
+    /// selected record
+    fn merge_rows(&mut self, shown: usize) {
+        let selected = self.rows[..shown].get(self.selected).copied();
+        let mut added = self.rows.split_off(shown);
+        added.sort_by(|&a, &b| self.order(a, b));
+        let old = std::mem::take(&mut self.rows);
+        let mut merged = Vec::with_capacity(old.len() + added.len());
+        let mut added = added.into_iter().peekable();
+        for i in old {
+            // Equal records keep the file order, as in a stable sort
+            while let Some(&a) = added.peek()
+                && self.order(a, i) == Ordering::Less
+            {
+                merged.push(a);
+                added.next();
+            }
+            merged.push(i);
+        }
+        merged.extend(added);
+        self.rows = merged;
+        let position = selected.and_then(|s| self.rows.iter().position(|&i| i == s));
+        self.select_at(position.unwrap_or(0) as isize);
+    }
+
+    /// Display order of the records at `a` and `b`
+    fn order(&self, a: usize, b: usize) -> Ordering {
+        let Some(column) = self.sort else {
+            return a.cmp(&b);
+        };
+        let order = compare(&self.records[a], &self.records[b], column);
+        if self.descending {
+            order.reverse()
+        } else {
+            order
+        }
+    }
+
+    /// Handles a key, returning `false` to quit
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return false;
+        }
+        match &mut self.input {
+            Input::Filter => match key.code {
+                KeyCode::Enter => self.input = Input::None,
+                KeyCode::Esc => {
+                    self.input = Input::None;
+                    self.set_filter(String::new());
+                }
+                KeyCode::Backspace => {
+                    let mut text = self.filter_text.clone();
+                    text.pop();
+                    self.set_filter(text);
+                }
+                KeyCode::Char(c) => self.set_filter(format!("{}{}", self.filter_text, c)),
+                _ => {}
+            },
+            Input::Jump(text) => match key.code {
+                KeyCode::Enter => {
+                    let text = std::mem::take(text);
+                    self.input = Input::None;
+                    self.jump(&text);
+                }
+                KeyCode::Esc => self.input = Input::None,
+                KeyCode::Backspace => {
+                    text.pop();
+                }
+                KeyCode::Char(c) if c.is_ascii_digit() => text.push(c),
+                _ => {}
+            },
+            Input::None => {
+                self.message = None;
+                match key.code {
+                    KeyCode::Char('q') => return false,
+                    KeyCode::Esc => self.set_filter(String::new()),
+                    KeyCode::Up | KeyCode::Char('k') => self.select_at(self.selected as isize - 1),
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        self.select_at(self.selected as isize + 1)
+                    }
+                    KeyCode::PageUp => self.select_at(self.selected as isize - self.page as isize),
+                    KeyCode::PageDown => {
+                        self.select_at(self.selected as isize + self.page as isize)
+                    }
+                    KeyCode::Home | KeyCode::Char('g') => self.select_at(0),
+                    KeyCode::End | KeyCode::Char('G') => self.select_at(isize::MAX),
+                    KeyCode::Char('/') => self.input = Input::Filter,
+                    KeyCode::Char(':') => self.input = Input::Jump(String::new()),
+                    KeyCode::Char('L') => self.load_all = true,
+                    KeyCode::Char('s') => {
+                        let next = match self.sort {
+                            None => Some(0),
+                            Some(column) => DEFAULT_COLUMNS
+                                .iter()
+                                .position(|&c| c == column)
+                                .map(|i| i + 1)
+                                .filter(|&i| i < DEFAULT_COLUMNS.len()),
+                        };
+                        self.sort = next.map(|i| DEFAULT_COLUMNS[i]);
+                        self.descending = false;
+                        self.refresh();
+                    }
+                    KeyCode::Char('r') if self.sort.is_some() => {
+                        self.descending = !self.descending;
+                        self.refresh();
+                    }
+                    _ => {}
+                }
+            }
+        }
+        true
+    }
+
+    fn set_filter(&mut self, text: String) {
+        self.filter = RecordFilter {
+            ignore_case: true,
+            ..RecordFilter::parse(&text)
+        };
+        self.filter_text = text;
+        self.refresh();
+    }
+
+    /// Index of the selected record in `records`
+    fn selected_record(&self) -> Option<usize> {
+        self.rows.get(self.selected).copied()
+    }
+
+    /// Rebuilds the shown rows, keeping the selected record if it is shown
+    fn refresh(&mut self) {
+        let selected = self.selected_record();
+        let filter = &self.filter;
+        let records = &self.records;
+        self.rows = (0..records.len())
+            .filter(|&i| filter.matches(&records[i]))
+            .collect();
+        self.sort_rows(selected);
+    }
+
+    fn sort_rows(&mut self, selected: Option<usize>) {
+        let mut rows = std::mem::take(&mut self.rows);
+        rows.sort_by(|&a, &b| self.order(a, b));
+        self.rows = rows;
+        let position = selected.and_then(|s| self.rows.iter().position(|&i| i == s));
+        self.select_at(position.unwrap_or(0) as isize);
+    }
+
+    /// Selects the row at `position`, clamped to the shown rows
+    fn select_at(&mut self, position: isize) {
+        let last = self.rows.len().saturating_sub(1);
+        self.selected = position.clamp(0, last as isize) as usize;
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + self.page {
+            self.offset = self.selected + 1 - self.page;
+        }
+    }
+
+    fn jump(&mut self, text: &str) {
+        let Ok(tx_id) = text.parse::<u64>() else {
+            return;
+        };
+        let records = &self.records;
+        match self.rows.iter().position(|&i| records[i].tx_id == tx_id) {
+            Some(position) => self.select_at(position as isize),
+            None if self.loading.is_some() => {
+                self.message = Some(format!("TX_ID {} not read yet; L reads the rest", tx_id))
+            }
+            None => self.message = Some(format!("TX_ID {} not found", tx_id)),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status, input] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [table, detail] =
+            Layout::horizontal([Constraint::Percentage(65), Constraint::Percentage(35)])
+                .areas(main);
+        self.draw_table(frame, table);
+        self.draw_detail(frame, detail);
+        frame.render_widget(Paragraph::new(self.status_line()).reversed(), status);
+        frame.render_widget(Paragraph::new(self.input_line()), input);
+    }
+
+    fn draw_table(&mut self, frame: &mut Frame, area: Rect) {
+        // Borders and the header take three lines
+        self.page = (area.height as usize).saturating_sub(3).max(1);
+        self.select_at(self.selected as isize);
+        let header = DEFAULT_COLUMNS.iter().map(|&column| {
+            let mut name = column.to_string();
+            if self.sort == Some(column) {
+                name.push_str(if self.descending { " ▼" } else { " ▲" });
+            }
+            name
+        });
+        let end = (self.offset + self.page).min(self.rows.len());
+        let rows = self.rows[self.offset..end].iter().map(|&i| {
+            let record = &self.records[i];
+            Row::new([
+                record.tx_id.to_string(),
+                record.tx_type.to_string(),
+                record.from_user_id.to_string(),
+                record.to_user_id.to_string(),
+                amount(record),
+                timestamp(record),
+                record.status.to_string(),
+                record.description.clone(),
+            ])
+        });
+        let widths = [
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(12),
+            Constraint::Length(12),
+            Constraint::Length(14),
+            Constraint::Length(24),
+            Constraint::Length(9),
+            Constraint::Fill(1),
+        ];
+        let table = Table::new(rows, widths)
+            .header(Row::new(header).bold())
+            .block(Block::bordered().title(self.title.as_str()))
+            .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        let mut state = TableState::new()
+            .with_selected((!self.rows.is_empty()).then(|| self.selected - self.offset));
+        frame.render_stateful_widget(table, area, &mut state);
+    }
+
+    fn draw_detail(&self, frame: &mut Frame, area: Rect) {
+        let lines: Vec<Line> = match self.selected_record() {
+            Some(i) => {
+                let record = &self.records[i];
+                let standard = STANDARD_FIELDS.iter().map(|name| {
+                    let value = match *name {
+                        "AMOUNT" => amount(record),
+                        "TIMESTAMP" => format!(
+                            "{} ({})",
+                            record.field(name).unwrap_or_default(),
+                            timestamp(record)
+                        ),
+                        _ => record.field(name).unwrap_or_default(),
+                    };
+                    (name.to_string(), value)
+                });
+                let extra = record.extra.clone().into_iter();
+                standard
+                    .chain(extra)
+                    .map(|(name, value)| {
+                        Line::from(vec![Span::from(format!("{}: ", name)).bold(), value.into()])
+                    })
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        let detail = Paragraph::new(lines)
+            .block(Block::bordered().title("Record"))
+            .wrap(Wrap { trim: false });
+        frame.render_widget(detail, area);
+    }
+
+    fn status_line(&self) -> String {
+        let mut status = if self.rows.len() == self.records.len() {
+            format!(" {} records", self.records.len())
+        } else {
+            format!(" {} of {} records", self.rows.len(), self.records.len())
+        };
+        match (&self.loading, self.load_all) {
+            (Some(_), true) => status.push_str(", reading…"),
+            (Some(_), false) => status.push_str(" read so far"),
+            (None, _) => {}
+        }
+        if !self.filter_text.is_empty() {
+            status.push_str(&format!(" | filter: {}", self.filter_text));
+        }
+        if let Some(column) = self.sort {
+            let order = if self.descending { "desc" } else { "asc" };
+            status.push_str(&format!(" | sort: {} {}", column, order));
+        }
+        if let Some(error) = &self.error {
+            status.push_str(&format!(" | error: {}", error));
+        }
+        status
+    }
+
+    fn input_line(&self) -> String {
+        match &self.input {
+            Input::Filter => format!("/{}", self.filter_text),
+            Input::Jump(text) => format!("TX_ID: {}", text),
+            Input::None => match &self.message {
+                Some(message) => message.clone(),
+                None => {
+                    "q quit  / filter  s sort  r reverse  : jump to TX_ID  L read all  Esc clear filter"
+                        .to_string()
+                }
+            },
+        }
+    }
+}
+
+fn compare(a: &YPBankRecord, b: &YPBankRecord, column: CsvColumn) -> Ordering {
+    match column {
+        CsvColumn::TxId => a.tx_id.cmp(&b.tx_id),
+        CsvColumn::TxType => a.tx_type.to_string().cmp(&b.tx_type.to_string()),
+        CsvColumn::FromUserId => a.from_user_id.cmp(&b.from_user_id),
+        CsvColumn::ToUserId => a.to_user_id.cmp(&b.to_user_id),
+        CsvColumn::Amount => a.amount.cmp(&b.amount),
+        CsvColumn::Timestamp => a.timestamp.cmp(&b.timestamp),
+        CsvColumn::Status => a.status.to_string().cmp(&b.status.to_string()),
+        CsvColumn::Description => a.description.cmp(&b.description),
+        CsvColumn::Currency => a
+            .currency
+            .map(|c| c.code())
+            .cmp(&b.currency.map(|c| c.code())),
+    }
+}
+
+fn amount(record: &YPBankRecord) -> String {
+    match record.currency {
+        Some(currency) => format!("{} {}", currency.format_decimal(record.amount), currency),
+        None => record.amount.to_string(),
+    }
+}
+
+fn timestamp(record: &YPBankRecord) -> String {
+    let utc = FixedOffset::east_opt(0).unwrap();
+    record
+        .timestamp
+        .to_iso8601(utc)
+        .or_else(|| {
+            record
+                .timestamp
+                .to_unix(TimeUnit::Millis)
+                .map(|t| t.to_string())
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::numbered_storage;
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+    use std::io::Cursor;
+
+    /// A viewer of 3000 records that has not read any yet
+    fn open() -> Viewer {
+        let mut data = Vec::new();
+        Format::Bin
+            .write(numbered_storage(3000), &mut data, &FormatOptions::default())
+            .unwrap();
+        Viewer::new(
+            "test.bin",
+            Format::Bin,
+            Cursor::new(data),
+            &FormatOptions::default(),
+        )
+    }
+
+    fn load_all(viewer: &mut Viewer) {
+        keys(viewer, "L");
+        while viewer.loading.is_some() {
+            viewer.receive();
+        }
+    }
+
+    fn viewer() -> Viewer {
+        let mut viewer = open();
+        load_all(&mut viewer);
+        viewer
+    }
+
+    fn keys(viewer: &mut Viewer, keys: &str) {
+        for c in keys.chars() {
+            let code = match c {
+                '\n' => KeyCode::Enter,
+                '\x1b' => KeyCode::Esc,
+                c => KeyCode::Char(c),
+            };
+            assert!(viewer.handle_key(KeyEvent::from(code)));
+        }
+    }
+
+    fn selected_tx_id(viewer: &Viewer) -> u64 {
+        viewer.records[viewer.selected_record().unwrap()].tx_id
+    }
+
+    #[test]
+    fn reads_in_background() {
+        let viewer = viewer();
+        assert_eq!(viewer.records.len(), 3000);
+        assert_eq!(viewer.rows.len(), 3000);
+        assert_eq!(viewer.error, None);
+    }
+
+    #[test]
+    fn reads_only_what_the_view_needs() {
+        let mut viewer = open();
+        viewer.page = 20;
+        while viewer.records.is_empty() {
+            viewer.receive();
+        }
+        // Later calls wait for nothing: the first batch fills the view
+        thread::sleep(Duration::from_millis(20));
+        assert!(!viewer.receive());
+        assert_eq!(viewer.records.len(), BATCH_SIZE);
+
+        keys(&mut viewer, ":2500\n");
+        let message = viewer.message.clone().unwrap();
+        assert!(message.contains("not read yet"), "{}", message);
+        keys(&mut viewer, "G");
+        viewer.receive();
+        assert_eq!(viewer.records.len(), 2 * BATCH_SIZE);
+        load_all(&mut viewer);
+        assert_eq!(viewer.records.len(), 3000);
+    }
+
+    #[test]
+    fn merges_sorted_batches() {
+        let mut viewer = open();
+        keys(&mut viewer, "sssssr");
+        load_all(&mut viewer);
+        let mut expected: Vec<usize> = (0..3000).collect();
+        expected.sort_by_key(|&i| std::cmp::Reverse(viewer.records[i].amount));
+        assert_eq!(viewer.rows, expected);
+        // Equal amounts keep the file order, as after a full sort
+        keys(&mut viewer, "rr");
+        assert_eq!(viewer.rows, expected);
+    }
+
+    #[test]
+    fn filters_by_field_and_any_field() {
+        let mut viewer = viewer();
+        keys(&mut viewer, "/status=pend");
+        assert_eq!(viewer.rows.len(), 1000);
+        keys(&mut viewer, "\x1b/payment 12");
+        assert_eq!(viewer.rows.len(), 111);
+        keys(&mut viewer, "\n\x1b");
+        assert_eq!(viewer.rows.len(), 3000);
+    }
+
+    #[test]
+    fn sorts_and_jumps() {
+        let mut viewer = viewer();
+        keys(&mut viewer, ":2500\n");
+        assert_eq!(selected_tx_id(&viewer), 2500);
+        // Sorting keeps the selected record
+        keys(&mut viewer, "sssss");
+        assert_eq!(viewer.sort, Some(CsvColumn::Amount));
+        assert_eq!(selected_tx_id(&viewer), 2500);
+        keys(&mut viewer, "rg");
+        assert_eq!(viewer.records[viewer.rows[0]].amount, 999);
+        keys(&mut viewer, ":9999\n");
+        assert_eq!(viewer.message.as_deref(), Some("TX_ID 9999 not found"));
+        assert!(!viewer.handle_key(KeyEvent::from(KeyCode::Char('q'))));
+    }
+
+    #[test]
+    fn draws_table_and_detail() {
+        let mut viewer = viewer();
+        keys(&mut viewer, ":3\n");
+        let mut terminal = Terminal::new(TestBackend::new(160, 20)).unwrap();
+        terminal.draw(|frame| viewer.draw(frame)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("test.bin"));
+        assert!(screen.contains("Payment 3"));
+        assert!(screen.contains("STATUS: PENDING"));
+        assert!(screen.contains("3000 records"));
+    }
+}
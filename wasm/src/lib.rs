@@ -0,0 +1,56 @@
+//! WebAssembly bindings of the YPBank parsers for JavaScript.
+//!
+//! Files are passed as `Uint8Array`s and records as plain objects with the
+//! CSV column names as keys, e.g. `{TX_ID: 1, TX_TYPE: "DEPOSIT", ...}`,
+//! extra fields next to them. Numbers above `Number.MAX_SAFE_INTEGER` cannot
+//! be represented and are rejected.
+
+use rust_parser::format::{FORMAT_NAMES, Format, FormatOptions};
+use rust_parser::storage::YPBankStorage;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+/// Names of the supported formats
+#[wasm_bindgen]
+pub fn formats() -> Vec<String> {
+    FORMAT_NAMES.iter().map(|name| name.to_string()).collect()
+}
+
+/// Format of a file detected from its name, e.g. `"bin"` for `records.bin`
+#[wasm_bindgen(js_name = detectFormat)]
+pub fn detect_format(file_name: &str) -> Option<String> {
+    Format::from_path(file_name).map(|format| format.to_string())
+}
+
+/// Reads the records of a file held in `data`
+#[wasm_bindgen]
+pub fn parse(data: &[u8], format: &str) -> Result<JsValue, JsError> {
+    let storage = parse_format(format)?.read(&mut &data[..], &FormatOptions::default())?;
+    let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+    Ok(storage.serialize(&serializer)?)
+}
+
+/// Writes records, an array of objects as returned by `parse`, in `format`
+#[wasm_bindgen]
+pub fn write(records: JsValue, format: &str) -> Result<Vec<u8>, JsError> {
+    let format = parse_format(format)?;
+    let storage: YPBankStorage = serde_wasm_bindgen::from_value(records)?;
+    let mut data = Vec::new();
+    format.write(storage, &mut data, &FormatOptions::default())?;
+    Ok(data)
+}
+
+/// Converts a file held in `data` between formats
+#[wasm_bindgen]
+pub fn convert(data: &[u8], from: &str, to: &str) -> Result<Vec<u8>, JsError> {
+    let options = FormatOptions::default();
+    let storage = parse_format(from)?.read(&mut &data[..], &options)?;
+    let mut out = Vec::new();
+    parse_format(to)?.write(storage, &mut out, &options)?;
+    Ok(out)
+}
+
+fn parse_format(name: &str) -> Result<Format, JsError> {
+    name.parse()
+        .map_err(|_| JsError::new(&format!("unknown format: {}", name)))
+}
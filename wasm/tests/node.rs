@@ -0,0 +1,70 @@
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::wasm_bindgen_test;
+use ypbank_wasm::{convert, detect_format, formats, parse, write};
+
+const CSV: &str = "\
+TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION
+1,DEPOSIT,0,42,1000,1633036800000,SUCCESS,\"Initial deposit\"
+2,TRANSFER,42,7,250,1633036860000,PENDING,\"Rent\"
+";
+
+fn get(object: &JsValue, key: &str) -> JsValue {
+    js_sys::Reflect::get(object, &key.into()).unwrap()
+}
+
+#[wasm_bindgen_test]
+fn test_parse() {
+    let records = parse(CSV.as_bytes(), "csv").unwrap();
+    let records = js_sys::Array::from(&records);
+    assert_eq!(records.length(), 2);
+    let first = records.get(0);
+    assert_eq!(get(&first, "TX_ID").as_f64(), Some(1.0));
+    assert_eq!(
+        get(&first, "TX_TYPE").as_string().as_deref(),
+        Some("DEPOSIT")
+    );
+    assert_eq!(get(&first, "TIMESTAMP").as_f64(), Some(1633036800000.0));
+    assert_eq!(
+        get(&first, "DESCRIPTION").as_string().as_deref(),
+        Some("Initial deposit")
+    );
+}
+
+#[wasm_bindgen_test]
+fn test_write_round_trip() {
+    let records = parse(CSV.as_bytes(), "csv").unwrap();
+    for format in formats() {
+        let data = write(records.clone(), &format).unwrap();
+        let again = parse(&data, &format).unwrap();
+        assert_eq!(
+            js_sys::JSON::stringify(&again).unwrap(),
+            js_sys::JSON::stringify(&records).unwrap(),
+            "{}",
+            format
+        );
+    }
+}
+
+#[wasm_bindgen_test]
+fn test_convert() {
+    let bin = convert(CSV.as_bytes(), "csv", "bin").unwrap();
+    assert_eq!(&bin[..4], b"YPBN");
+    let csv = convert(&bin, "bin", "csv").unwrap();
+    assert_eq!(String::from_utf8(csv).unwrap(), CSV);
+}
+
+#[wasm_bindgen_test]
+fn test_detect_format() {
+    assert_eq!(detect_format("records.BIN").as_deref(), Some("bin"));
+    assert_eq!(detect_format("records.unknown"), None);
+}
+
+#[wasm_bindgen_test]
+fn test_errors() {
+    assert!(parse(b"not a record", "bin").is_err());
+    assert!(convert(CSV.as_bytes(), "csv", "xml").is_err());
+    assert!(write(JsValue::from_str("records"), "csv").is_err());
+
+    let large = CSV.replace("\n1,", "\n9007199254740993,");
+    assert!(parse(large.as_bytes(), "csv").is_err());
+}